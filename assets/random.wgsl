@@ -2,6 +2,7 @@
 let random_scale: f32 = 43758.5453123;
 let random_x: f32 = 12.9898;
 let random_y: f32 = 78.233;
+let pi: f32 = 3.14159265;
 
 fn random(x: f32) -> f32 {
     return fract(sin(x) * random_scale);
@@ -18,19 +19,51 @@ struct VertexOutput {
 	[[location(2)]] uv: vec2<f32>;
 };
 
+// laid out the same as ExtractedInfo in effects.rs
 struct Info {
+	tint_color: vec4<f32>;
 	time: f32;
-	power: f32;
+	opacity: f32;
+	grain_size: f32;
+	speed: f32;
+	tint: f32;
+	vignette: f32;
+	scanlines: f32;
+	chromatic_offset: f32;
 };
 
 [[group(1), binding(0)]] var<uniform> info: Info;
 
+// the noise of the grain a pixel falls in
+fn grain(pixel: vec2<f32>, frame: f32) -> f32 {
+	var cell = floor(pixel / max(info.grain_size, 1.0));
+	return random(random(frame) + random_vec2(cell)) * 0.5;
+}
+
 [[stage(fragment)]]
 fn fragment(input: VertexOutput) -> [[location(0)]] vec4<f32> {
-	//var r = random_vec2(vec2<f32>(input.world_position[0], input.world_position[1]));
-	var r = random(random(info.time) + random_vec2(input.uv)) * 0.5;
+	var pixel = vec2<f32>(input.world_position[0], input.world_position[1]);
+	//at a speed of 1 the noise changes every frame at 60 fps
+	var frame = floor(info.time * 60.0 * info.speed);
+	var offset = vec2<f32>(info.chromatic_offset, 0.0);
+	var color = vec3<f32>(
+		grain(pixel + offset, frame),
+		grain(pixel, frame),
+		grain(pixel - offset, frame)
+	);
+
+	var gray = (color[0] + color[1] + color[2]) / 3.0;
+	color = mix(color, gray * 2.0 * info.tint_color.rgb, info.tint);
 
-	var alpha = 0.6 - info.power * 1024.0 / 255.0;
-	var output = vec4<f32>(r, r, r, alpha);
+	//dark lines every other pixel, and darker edges
+	var line = info.scanlines * (0.5 + 0.5 * sin(pixel[1] * pi));
+	var edge = length(input.uv - vec2<f32>(0.5, 0.5)) * 1.414;
+	var t = clamp((edge - 0.4) / 0.6, 0.0, 1.0);
+	var vignette = info.vignette * t * t * (3.0 - 2.0 * t);
+	var shade = 1.0 - (1.0 - line) * (1.0 - vignette);
+
+	var alpha = clamp(info.opacity, 0.0, 1.0);
+	alpha = alpha + shade * (1.0 - alpha);
+	var output = vec4<f32>(color * (1.0 - shade), alpha);
     return output;
-}
\ No newline at end of file
+}
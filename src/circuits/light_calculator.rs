@@ -35,6 +35,36 @@ impl DisconnectLightCircuitCalculator {
         }
     }
 
+    /// Returns a calculator representing an RLC series circuit connected to a lightbulb that starts with a current flowing.
+    ///
+    /// # Arguments
+    ///
+    /// * `startcharge` - Q0, the charge present on the capacitor at the start.
+    /// * `startcurrent` - I0, the current flowing through the inductor at the start.
+    /// * `resistance` - R, the resistance.
+    /// * `inductance` - L, the inductance.
+    /// * `capacitance` - C, the capacitance.
+    ///
+    /// # Returns
+    /// A `DisconnectLightCircuitCalculator` representing an RLC series circuit connected to a lightbulb starting with the given charge and current.
+    pub fn with_initial_current(
+        startcharge: Float,
+        startcurrent: Float,
+        resistance: Float,
+        inductance: Float,
+        capacitance: Float,
+    ) -> Self {
+        Self {
+            circuit: RLCCalculator::with_initial_current(
+                startcharge,
+                startcurrent,
+                resistance,
+                inductance,
+                capacitance,
+            ),
+        }
+    }
+
     /// Consumes a RLCCalculator to return a calculator representing that RLC circuit connected to a lightbulb.
     ///
     /// # Arguments
@@ -1,13 +1,45 @@
 type Float = f64;
 
+/// how many integration steps the automatic step takes for every radian the fastest part of the
+/// circuit turns or decays through
+const SUBSTEPS_PER_RADIAN: Float = 1000.0;
+
+/// the most integration steps taken in a single tick, so a tiny step can't freeze the game
+pub const MAX_SUBSTEPS: Float = 100_000.0;
+
+/// how much the amplitude may drift from the true one every natural period before a step counts
+/// as unstable
+const MAX_STABLE_DRIFT: Float = 0.01;
+
+/// the largest coupling coefficient used, as perfect coupling makes the coupled system singular
+const MAX_COUPLING: Float = 0.99;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A snapshot of a circuit's state, which can be restored with `RLCCalculator::set_state`
+pub struct CircuitState {
+    /// the time passed since the start
+    pub time: Float,
+    /// the charge on the capacitor
+    pub charge: Float,
+    /// the current flowing in the circuit
+    pub current: Float,
+}
+
 #[derive(Debug)]
 /// Helper struct for calculating RLC series circuit current.
 pub struct RLCCalculator {
     pub startcharge: Float,
+    /// I0, the current flowing at the start
+    pub startcurrent: Float,
     pub resistance: Float,
     pub inductance: Float,
     pub capacitance: Float,
-    time_since_first_tick: Float,
+    /// E, the voltage of the sources around the loop, which pushes charge onto the capacitor
+    pub emf: Float,
+    /// the integration step forced by the player, none to pick one from the circuit's time
+    /// constants
+    pub forced_substep: Option<Float>,
+    time: Float,
     q: Float,
     dqdt: Float,
     d2qdt2: Float,
@@ -31,17 +63,73 @@ impl RLCCalculator {
         inductance: Float,
         capacitance: Float,
     ) -> Self {
-        RLCCalculator {
+        let mut circuit = RLCCalculator {
             startcharge,
+            startcurrent: 0.0,
             resistance,
             inductance,
             capacitance,
-            time_since_first_tick: 0.0,
-            //should get set later when time_since_first_tick is 0
+            emf: 0.0,
+            forced_substep: None,
+            time: 0.0,
             q: 0.0,
             dqdt: 0.0,
             d2qdt2: 0.0,
-        }
+        };
+        circuit.reset();
+        circuit
+    }
+
+    /// Returns a calculator representing an RLC series circuit that starts with a current flowing.
+    ///
+    /// # Arguments
+    ///
+    /// * `startcharge` - Q0, the charge present on the capacitor at the start.
+    /// * `startcurrent` - I0, the current flowing through the inductor at the start.
+    /// * `resistance` - R, the resistance.
+    /// * `inductance` - L, the inductance.
+    /// * `capacitance` - C, the capacitance.
+    ///
+    /// # Returns
+    /// A `RLCCalculator` representing an RLC series circuit starting with the given charge and current.
+    pub fn with_initial_current(
+        startcharge: Float,
+        startcurrent: Float,
+        resistance: Float,
+        inductance: Float,
+        capacitance: Float,
+    ) -> Self {
+        let mut circuit = Self::with_constants(startcharge, resistance, inductance, capacitance);
+        circuit.set_initial_state(startcharge, startcurrent);
+        circuit
+    }
+
+    /// Returns a calculator representing an RLC series circuit whose capacitor starts at a voltage.
+    ///
+    /// # Arguments
+    ///
+    /// * `startvoltage` - V0, the voltage across the capacitor at the start, which is Q0/C.
+    /// * `startcurrent` - I0, the current flowing through the inductor at the start.
+    /// * `resistance` - R, the resistance.
+    /// * `inductance` - L, the inductance.
+    /// * `capacitance` - C, the capacitance.
+    ///
+    /// # Returns
+    /// A `RLCCalculator` representing an RLC series circuit starting with the given voltage and current.
+    pub fn with_initial_voltage(
+        startvoltage: Float,
+        startcurrent: Float,
+        resistance: Float,
+        inductance: Float,
+        capacitance: Float,
+    ) -> Self {
+        Self::with_initial_current(
+            startvoltage * capacitance,
+            startcurrent,
+            resistance,
+            inductance,
+            capacitance,
+        )
     }
 
     /// calculates the current in the circuit
@@ -56,40 +144,620 @@ impl RLCCalculator {
         -self.d2qdt2
     }
 
+    /// calculates the charge on the capacitor
+    ///
+    /// # Returns
+    /// The charge, in coulombs
+    pub fn charge(&self) -> Float {
+        self.q
+    }
+
+    /// calculates the energy stored in the capacitor and inductor
+    ///
+    /// # Returns
+    /// Q^2/2C + LI^2/2, in joules
+    pub fn energy(&self) -> Float {
+        let current = self.current();
+        self.charge() * self.charge() * 0.5 / self.capacitance
+            + self.inductance * current * current * 0.5
+    }
+
+    /// calculates the angular frequency the circuit would oscillate at without resistance
+    ///
+    /// # Returns
+    /// w0 = 1/sqrt(LC), in radians per second
+    pub fn natural_angular_frequency(&self) -> Float {
+        (self.inductance * self.capacitance).sqrt().recip()
+    }
+
+    /// calculates the largest current the circuit could reach from its starting state
+    ///
+    /// # Returns
+    /// sqrt(((Q0 - CE) w0)^2 + I0^2), the peak current without resistance, as the charge swings
+    /// around CE, where the sources hold it
+    pub fn peak_current(&self) -> Float {
+        let swing = self.startcharge - self.capacitance * self.emf;
+        (swing * self.natural_angular_frequency()).hypot(self.startcurrent)
+    }
+
+    /// calculates the angular frequency the circuit actually oscillates at
+    ///
+    /// # Returns
+    /// wd = sqrt(w0^2 - (R/2L)^2) in radians per second, or none if the circuit is critically
+    /// damped or overdamped and doesn't oscillate
+    pub fn damped_angular_frequency(&self) -> Option<Float> {
+        let w_squared = (self.inductance * self.capacitance).recip();
+        let modifier = self.resistance * 0.5 * self.inductance.recip();
+        let wd_squared = w_squared - modifier * modifier;
+        (wd_squared > 0.0).then(|| wd_squared.sqrt())
+    }
+
+    /// calculates the rates the circuit's two natural modes change at
+    ///
+    /// # Returns
+    /// The eigenvalues of the circuit, as (real, imaginary) pairs: -R/2L +- sqrt((R/2L)^2 - w0^2)
+    pub fn eigenvalues(&self) -> [(Float, Float); 2] {
+        let gamma = self.resistance * 0.5 / self.inductance;
+        let discriminant = gamma * gamma - (self.inductance * self.capacitance).recip();
+        if discriminant < 0.0 {
+            let wd = (-discriminant).sqrt();
+            [(-gamma, wd), (-gamma, -wd)]
+        } else {
+            let root = discriminant.sqrt();
+            [(-gamma + root, 0.0), (-gamma - root, 0.0)]
+        }
+    }
+
+    /// picks the integration step from the circuit's time constants
+    ///
+    /// # Returns
+    /// A step taking `SUBSTEPS_PER_RADIAN` steps for every radian the fastest mode turns or decays
+    /// through, which is 1/w0 for an underdamped circuit and the fast decay time of an overdamped
+    /// one
+    pub fn automatic_substep(&self) -> Float {
+        let fastest = self
+            .eigenvalues()
+            .iter()
+            .map(|&(re, im)| re.hypot(im))
+            .fold(0.0, Float::max);
+        if fastest.is_finite() && fastest > 0.0 {
+            (fastest * SUBSTEPS_PER_RADIAN).recip()
+        } else {
+            Float::INFINITY
+        }
+    }
+
+    /// calculates the integration step a tick actually takes
+    ///
+    /// # Arguments
+    /// * 'delta_t' - the time the tick passes
+    ///
+    /// # Returns
+    /// The forced or automatic step, shrunk to fit a whole number of times into delta_t and
+    /// grown if it would take more than `MAX_SUBSTEPS` steps
+    pub fn effective_substep(&self, delta_t: Float) -> Float {
+        let wanted = self
+            .forced_substep
+            .unwrap_or_else(|| self.automatic_substep());
+        delta_t / substep_count(delta_t, wanted)
+    }
+
+    /// checks if a tick would take a longer step than wanted, to keep to `MAX_SUBSTEPS` steps
+    ///
+    /// # Arguments
+    /// * 'delta_t' - the time the tick passes
+    ///
+    /// # Returns
+    /// Whether the forced or automatic step needs more than `MAX_SUBSTEPS` steps
+    pub fn is_substep_clamped(&self, delta_t: Float) -> bool {
+        let wanted = self
+            .forced_substep
+            .unwrap_or_else(|| self.automatic_substep());
+        (delta_t / wanted).ceil() > MAX_SUBSTEPS
+    }
+
+    /// calculates how far an integration step makes the amplitude drift from the true one
+    ///
+    /// The integrator is forward Euler, which multiplies each mode by |1 + lambda dt| every
+    /// step, where the true solution multiplies it by e^(lambda dt).
+    ///
+    /// # Arguments
+    /// * 'substep' - the integration step
+    ///
+    /// # Returns
+    /// How much bigger than the true amplitude the amplitude gets every natural period, as a
+    /// fraction, where 0 is exact and negative drifts mean extra damping
+    pub fn step_drift(&self, substep: Float) -> Float {
+        let period = std::f64::consts::TAU / self.natural_angular_frequency();
+        self.eigenvalues()
+            .iter()
+            .map(|&(re, im)| {
+                let growth = (1.0 + re * substep).hypot(im * substep);
+                (period * (growth.ln() / substep - re)).exp() - 1.0
+            })
+            .fold(Float::NEG_INFINITY, Float::max)
+    }
+
+    /// checks if an integration step keeps the simulation close to the true solution
+    ///
+    /// # Arguments
+    /// * 'substep' - the integration step
+    ///
+    /// # Returns
+    /// Whether the amplitude drifts less than `MAX_STABLE_DRIFT` every natural period
+    pub fn is_stable(&self, substep: Float) -> bool {
+        self.step_drift(substep) <= MAX_STABLE_DRIFT
+    }
+
     /// increments the internat state of the circuit, passing time by delta_t
     ///
     /// # Arguments
-    /// * 'delta_t' - the time passed in the circuit, keep it kind of small to minimize error
+    /// * 'delta_t' - the time passed in the circuit, split into steps of `effective_substep`
     pub fn tick(&mut self, delta_t: Float) {
-        if self.time_since_first_tick == 0.0 {
-            self.q = self.startcharge;
-            self.dqdt = 0.0;
-            let w_squared = (self.inductance * self.capacitance).recip();
-            let modifier = self.resistance * 0.5 * self.inductance.recip();
-            //-Qw^2
-            self.d2qdt2 = -self.startcharge * (w_squared - modifier * modifier);
-        }
-
-        const FIDELITY: i32 = 100;
-        let dt = delta_t / FIDELITY as Float;
+        let dt = self.effective_substep(delta_t);
         let mut new_q = self.q;
         let mut new_dqdt = self.dqdt;
-        let mut new_d2qdt2 = self.d2qdt2;
+        //worked out again in case the constants changed since the last tick
+        let mut new_d2qdt2 = self.acceleration(new_q, new_dqdt);
 
-        for _ in 0..FIDELITY {
+        for _ in 0..(delta_t / dt).round() as usize {
             new_q += new_dqdt * dt;
             new_dqdt += new_d2qdt2 * dt;
-            new_d2qdt2 = -(new_q / self.capacitance + self.resistance * new_dqdt) / self.inductance;
+            new_d2qdt2 = self.acceleration(new_q, new_dqdt);
         }
 
         self.q = new_q;
         self.dqdt = new_dqdt;
         self.d2qdt2 = new_d2qdt2;
-        self.time_since_first_tick += delta_t;
+        self.time += delta_t;
+    }
+
+    /// increments the internal state of this circuit and another circuit sharing a mutual
+    /// inductance with it, passing time by delta_t for both
+    ///
+    /// The two loops are solved as one system, so energy moves between them through the shared
+    /// magnetic field.
+    ///
+    /// # Arguments
+    /// * 'other' - the circuit coupled to this one
+    /// * 'coupling' - k, the coupling coefficient, where the mutual inductance is k * sqrt(L1 * L2)
+    /// * 'delta_t' - the time passed in both circuits, split into steps of
+    ///   `coupled_substep`
+    pub fn tick_coupled(&mut self, other: &mut RLCCalculator, coupling: Float, delta_t: Float) {
+        //a coupling of 1 makes the system singular
+        let mutual =
+            coupling.clamp(0.0, MAX_COUPLING) * (self.inductance * other.inductance).sqrt();
+        let accelerations = |q1: Float, dq1: Float, q2: Float, dq2: Float| {
+            let f1 = self.emf - q1 / self.capacitance - self.resistance * dq1;
+            let f2 = other.emf - q2 / other.capacitance - other.resistance * dq2;
+            let det = self.inductance * other.inductance - mutual * mutual;
+            (
+                (other.inductance * f1 - mutual * f2) / det,
+                (self.inductance * f2 - mutual * f1) / det,
+            )
+        };
+
+        let dt = self.coupled_substep(other, coupling, delta_t);
+        let (mut q1, mut dq1) = (self.q, self.dqdt);
+        let (mut q2, mut dq2) = (other.q, other.dqdt);
+        let (mut d2q1, mut d2q2) = accelerations(q1, dq1, q2, dq2);
+
+        for _ in 0..(delta_t / dt).round() as usize {
+            q1 += dq1 * dt;
+            q2 += dq2 * dt;
+            dq1 += d2q1 * dt;
+            dq2 += d2q2 * dt;
+            (d2q1, d2q2) = accelerations(q1, dq1, q2, dq2);
+        }
+
+        (self.q, self.dqdt, self.d2qdt2) = (q1, dq1, d2q1);
+        (other.q, other.dqdt, other.d2qdt2) = (q2, dq2, d2q2);
+        self.time += delta_t;
+        other.time += delta_t;
+    }
+
+    /// calculates the integration step a coupled tick actually takes
+    ///
+    /// The shared field makes the coupled modes faster than either circuit's own, by up to
+    /// 1/sqrt(1 - k^2), so the smaller step of the two is shrunk by that much. A step forced on
+    /// either circuit is used as it is.
+    ///
+    /// # Arguments
+    /// * 'other' - the circuit coupled to this one
+    /// * 'coupling' - k, the coupling coefficient
+    /// * 'delta_t' - the time the tick passes
+    ///
+    /// # Returns
+    /// The step, fitting a whole number of times into delta_t
+    pub fn coupled_substep(&self, other: &RLCCalculator, coupling: Float, delta_t: Float) -> Float {
+        let k = coupling.clamp(0.0, MAX_COUPLING);
+        let wanted = match (self.forced_substep, other.forced_substep) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(forced), None) | (None, Some(forced)) => forced,
+            (None, None) => {
+                self.automatic_substep().min(other.automatic_substep()) * (1.0 - k * k).sqrt()
+            }
+        };
+        delta_t / substep_count(delta_t, wanted)
+    }
+
+    /// calculates the rate of change of the current, as the circuit's equation gives it
+    ///
+    /// # Arguments
+    /// * 'q' - the charge on the capacitor
+    /// * 'dqdt' - the rate of change of the charge
+    ///
+    /// # Returns
+    /// d2q/dt2 = (E - q/C - R dq/dt) / L
+    fn acceleration(&self, q: Float, dqdt: Float) -> Float {
+        (self.emf - q / self.capacitance - self.resistance * dqdt) / self.inductance
+    }
+
+    /// sets the charge and current the circuit starts with, and resets it back to them
+    ///
+    /// # Arguments
+    /// * 'startcharge' - Q0, the charge on the capacitor at the start
+    /// * 'startcurrent' - I0, the current flowing at the start
+    pub fn set_initial_state(&mut self, startcharge: Float, startcurrent: Float) {
+        self.startcharge = startcharge;
+        self.startcurrent = startcurrent;
+        self.reset();
     }
 
-    /// resets the state of the circuit back to time 0
+    /// resets the state of the circuit back to time 0, with the starting charge and current
     pub fn reset(&mut self) {
-        self.time_since_first_tick = 0.0;
+        self.set_state(CircuitState {
+            time: 0.0,
+            charge: self.startcharge,
+            current: self.startcurrent,
+        });
+    }
+
+    /// takes a snapshot of the circuit's state
+    ///
+    /// # Returns
+    /// The time, charge and current, which `set_state` can restore
+    pub fn state(&self) -> CircuitState {
+        CircuitState {
+            time: self.time,
+            charge: self.charge(),
+            current: self.current(),
+        }
+    }
+
+    /// restores a snapshot of the circuit's state, keeping its constants
+    ///
+    /// # Arguments
+    /// * 'state' - the time, charge and current to restore
+    pub fn set_state(&mut self, state: CircuitState) {
+        self.time = state.time;
+        self.q = state.charge;
+        //the current flows out of the capacitor, so it's minus the rate of change of the charge
+        self.dqdt = -state.current;
+        self.d2qdt2 = self.acceleration(self.q, self.dqdt);
+    }
+}
+
+/// Works out how many steps a tick is split into
+///
+/// # Arguments
+/// * 'delta_t' - the time the tick passes
+/// * 'substep' - the longest step wanted
+///
+/// # Returns
+/// The number of steps, at least one and at most `MAX_SUBSTEPS`
+fn substep_count(delta_t: Float, substep: Float) -> Float {
+    let count = (delta_t / substep).ceil();
+    if count.is_nan() {
+        1.0
+    } else {
+        count.clamp(1.0, MAX_SUBSTEPS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::f64::consts::{PI, TAU};
+
+    /// the time passed by each tick in the reference tests
+    const DELTA_T: Float = 1e-3;
+    /// how far the current may be from the analytic one, as a fraction of the largest current
+    const CURRENT_TOLERANCE: Float = 5e-3;
+
+    /// Ticks a circuit until a given time
+    ///
+    /// # Arguments
+    /// * 'circuit' - the circuit to tick
+    /// * 'until' - the time to stop at, which should be a whole number of `delta_t`s
+    /// * 'delta_t' - the time passed by each tick
+    fn run(circuit: &mut RLCCalculator, until: Float, delta_t: Float) {
+        for _ in 0..(until / delta_t).round() as usize {
+            circuit.tick(delta_t);
+        }
+    }
+
+    /// Checks a simulated current against the analytic one at a list of times
+    ///
+    /// # Arguments
+    /// * 'circuit' - the circuit, which hasn't been ticked yet
+    /// * 'analytic' - the exact current at a time
+    /// * 'times' - the times to check at, in increasing order
+    fn assert_matches_analytic(
+        mut circuit: RLCCalculator,
+        analytic: impl Fn(Float) -> Float,
+        times: &[Float],
+    ) {
+        let peak = (1..=5000)
+            .map(|step| analytic(step as Float * 1e-3 * times[times.len() - 1]).abs())
+            .fold(0.0, Float::max);
+        let mut now = 0.0;
+        for &time in times {
+            run(&mut circuit, time - now, DELTA_T);
+            now = time;
+            let error = (circuit.current() - analytic(time)).abs();
+            assert!(
+                error <= CURRENT_TOLERANCE * peak,
+                "at t = {}, the current was {} rather than {}",
+                time,
+                circuit.current(),
+                analytic(time)
+            );
+        }
+    }
+
+    /* ANALYTIC SOLUTIONS */
+
+    #[test]
+    fn underdamped_current() {
+        //w0 = 1, gamma = 0.1
+        let circuit = RLCCalculator::with_constants(1.0, 0.2, 1.0, 1.0);
+        let wd = circuit.damped_angular_frequency().unwrap();
+        assert!((wd - 0.99f64.sqrt()).abs() < 1e-12);
+        //I = Q0 w0^2 / wd e^(-gamma t) sin(wd t)
+        let analytic = |t: Float| (-0.1 * t).exp() * (wd * t).sin() / wd;
+        assert_matches_analytic(circuit, analytic, &[0.5, 1.0, 2.0, 3.0, 5.0, 8.0, 13.0]);
+    }
+
+    #[test]
+    fn critically_damped_current() {
+        //w0 = gamma = 1
+        let circuit = RLCCalculator::with_constants(2.0, 2.0, 1.0, 1.0);
+        assert!(circuit.damped_angular_frequency().is_none());
+        //I = Q0 gamma^2 t e^(-gamma t)
+        let analytic = |t: Float| 2.0 * t * (-t).exp();
+        assert_matches_analytic(circuit, analytic, &[0.25, 0.5, 1.0, 2.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn overdamped_current() {
+        //w0 = 1, gamma = 2.5
+        let circuit = RLCCalculator::with_constants(1.0, 5.0, 1.0, 1.0);
+        assert!(circuit.damped_angular_frequency().is_none());
+        let [(s1, _), (s2, _)] = circuit.eigenvalues();
+        //I = -Q0 s1 s2 (e^(s1 t) - e^(s2 t)) / (s2 - s1)
+        let analytic = |t: Float| -s1 * s2 * ((s1 * t).exp() - (s2 * t).exp()) / (s2 - s1);
+        assert_matches_analytic(circuit, analytic, &[0.1, 0.3, 0.5, 1.0, 2.0, 5.0]);
+    }
+
+    #[test]
+    fn current_extremum_times() {
+        let mut circuit = RLCCalculator::with_constants(1.0, 0.2, 1.0, 1.0);
+        let gamma = 0.1;
+        let wd = circuit.damped_angular_frequency().unwrap();
+        //the current peaks where tan(wd t) = wd / gamma, then every half of a damped period
+        let expected: Vec<Float> = (0..4)
+            .map(|k| (wd.atan2(gamma) + k as Float * PI) / wd)
+            .collect();
+
+        let mut found = Vec::new();
+        circuit.tick(DELTA_T);
+        let mut last_sign = circuit.current_rate().signum();
+        let mut time = DELTA_T;
+        while found.len() < expected.len() {
+            circuit.tick(DELTA_T);
+            time += DELTA_T;
+            if circuit.current_rate().signum() != last_sign {
+                found.push(time);
+                last_sign = circuit.current_rate().signum();
+            }
+        }
+        for (found, expected) in found.iter().zip(&expected) {
+            assert!(
+                (found - expected).abs() < 0.02,
+                "an extremum was at t = {} rather than {}",
+                found,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn energy_decay() {
+        let circuit = RLCCalculator::with_constants(1.0, 0.2, 1.0, 1.0);
+        let start = circuit.energy();
+        assert!((start - 0.5).abs() < 1e-12);
+        let wd = circuit.damped_angular_frequency().unwrap();
+        //every half of a damped period no current flows, and the energy is E0 e^(-2 gamma t)
+        for half_periods in 1..=4 {
+            let time = half_periods as Float * PI / wd;
+            let mut ticked = RLCCalculator::with_constants(1.0, 0.2, 1.0, 1.0);
+            let steps = (time / DELTA_T).floor();
+            run(&mut ticked, steps * DELTA_T, DELTA_T);
+            ticked.tick(time - steps * DELTA_T);
+            let expected = start * (-0.2 * time).exp();
+            assert!(
+                (ticked.energy() - expected).abs() < 0.01 * start,
+                "after {} half periods the energy was {} rather than {}",
+                half_periods,
+                ticked.energy(),
+                expected
+            );
+        }
+    }
+
+    /* RESET */
+
+    #[test]
+    fn reset_restores_start() {
+        let mut circuit = RLCCalculator::with_constants(1.0, 0.2, 1.0, 1.0);
+        let mut fresh = RLCCalculator::with_constants(1.0, 0.2, 1.0, 1.0);
+        run(&mut circuit, 2.5, DELTA_T);
+        circuit.reset();
+        //restored at once, not on the next tick
+        assert_eq!(circuit.state(), fresh.state());
+        assert_eq!(circuit.current(), 0.0);
+        assert_eq!(circuit.current_rate(), fresh.current_rate());
+
+        run(&mut circuit, 1.0, DELTA_T);
+        run(&mut fresh, 1.0, DELTA_T);
+        assert_eq!(circuit.charge(), fresh.charge());
+        assert_eq!(circuit.current(), fresh.current());
+        assert_eq!(circuit.current_rate(), fresh.current_rate());
+    }
+
+    #[test]
+    fn initial_current() {
+        //w0 = 1, gamma = 0.1, starting with the inductor energized and the capacitor empty
+        let circuit = RLCCalculator::with_initial_voltage(0.0, 1.0, 0.2, 1.0, 1.0);
+        assert_eq!(circuit.charge(), 0.0);
+        assert_eq!(circuit.current(), 1.0);
+        assert!((circuit.energy() - 0.5).abs() < 1e-12);
+        let wd = circuit.damped_angular_frequency().unwrap();
+        //I = I0 e^(-gamma t) (cos(wd t) - gamma / wd sin(wd t))
+        let analytic = |t: Float| (-0.1 * t).exp() * ((wd * t).cos() - 0.1 / wd * (wd * t).sin());
+        assert_matches_analytic(circuit, analytic, &[0.5, 1.0, 2.0, 3.0, 5.0, 8.0, 13.0]);
+    }
+
+    #[test]
+    fn initial_voltage_and_current() {
+        //both stores start full, 1 J in the capacitor (Q^2/2C) and 2 J in the inductor (LI^2/2)
+        let circuit = RLCCalculator::with_initial_voltage(2.0, 1.0, 0.0, 4.0, 0.5);
+        assert_eq!(circuit.charge(), 1.0);
+        assert_eq!(circuit.current(), 1.0);
+        assert!((circuit.energy() - 3.0).abs() < 1e-12);
+        //with no resistance, dI/dt = V_C / L
+        assert!((circuit.current_rate() - 0.5).abs() < 1e-12);
+
+        let mut reset = RLCCalculator::with_initial_current(1.0, 1.0, 0.0, 4.0, 0.5);
+        run(&mut reset, 1.0, DELTA_T);
+        reset.reset();
+        assert_eq!(reset.state(), circuit.state());
+    }
+
+    #[test]
+    fn source_charges_the_capacitor() {
+        //with no resistance, the charge swings between 0 and 2CE, so Q = CE (1 - cos(w0 t))
+        let mut circuit = RLCCalculator::with_constants(0.0, 0.0, 1.0, 1.0);
+        circuit.emf = 2.0;
+        circuit.reset();
+        assert!((circuit.peak_current() - 2.0).abs() < 1e-12);
+        assert_matches_analytic(circuit, |t| -2.0 * t.sin(), &[0.5, 1.0, 2.0, 3.0, 5.0]);
+
+        //with resistance, it settles at CE
+        let mut circuit = RLCCalculator::with_constants(0.0, 1.0, 1.0, 1.0);
+        circuit.emf = 2.0;
+        circuit.reset();
+        run(&mut circuit, 30.0, DELTA_T);
+        assert!((circuit.charge() - 2.0).abs() < 1e-3);
+        assert!(circuit.current().abs() < 1e-3);
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut circuit = RLCCalculator::with_constants(1.0, 0.2, 1.0, 1.0);
+        run(&mut circuit, 1.5, DELTA_T);
+        let snapshot = circuit.state();
+        assert!((snapshot.time - 1.5).abs() < 1e-9);
+        run(&mut circuit, 1.0, DELTA_T);
+        let later = circuit.state();
+
+        circuit.set_state(snapshot);
+        assert_eq!(circuit.state(), snapshot);
+        run(&mut circuit, 1.0, DELTA_T);
+        assert_eq!(circuit.state(), later);
+    }
+
+    /* TIMESTEP */
+
+    #[test]
+    fn automatic_substep_is_stable() {
+        for resistance in [0.0, 0.2, 2.0, 5.0, 100.0] {
+            let circuit = RLCCalculator::with_constants(1.0, resistance, 1.0, 1.0);
+            let substep = circuit.automatic_substep();
+            assert!(circuit.is_stable(substep), "R = {}", resistance);
+        }
+        let mut circuit = RLCCalculator::with_constants(1.0, 0.2, 1.0, 1.0);
+        circuit.forced_substep = Some(0.5);
+        assert!(!circuit.is_stable(circuit.effective_substep(DELTA_T * 1000.0)));
+    }
+
+    #[test]
+    fn long_ticks_clamp_the_substep() {
+        let circuit = RLCCalculator::with_constants(1.0, 0.2, 1.0, 1.0);
+        assert!(!circuit.is_substep_clamped(DELTA_T));
+        //w0 is 1, so a step is a thousandth of a second
+        let delta_t = 1000.0;
+        assert!(circuit.is_substep_clamped(delta_t));
+        assert_eq!(circuit.effective_substep(delta_t), delta_t / MAX_SUBSTEPS);
+        assert!(!circuit.is_stable(circuit.effective_substep(delta_t)));
+    }
+
+    /* PROPERTIES */
+
+    /// Picks values spread evenly over the powers of ten between two exponents
+    fn log_uniform(low: Float, high: Float) -> impl Strategy<Value = Float> {
+        (low..high).prop_map(|exponent| 10f64.powf(exponent))
+    }
+
+    /// Picks the constants of a circuit from its inductance, capacitance and damping ratio
+    ///
+    /// R comes from the damping ratio z = R/2 sqrt(C/L), keeping the quality factor under the
+    /// 1000 that the automatic step loses energy below.
+    fn circuits() -> impl Strategy<Value = RLCCalculator> {
+        (
+            log_uniform(-9.0, 3.0),
+            log_uniform(-2.0, 0.5),
+            log_uniform(-6.0, 0.0),
+            log_uniform(-12.0, -3.0),
+        )
+            .prop_map(|(startcharge, damping_ratio, inductance, capacitance)| {
+                let resistance = 2.0 * damping_ratio * (inductance / capacitance).sqrt();
+                RLCCalculator::with_constants(startcharge, resistance, inductance, capacitance)
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn never_nan(mut circuit in circuits()) {
+            let delta_t = TAU / circuit.natural_angular_frequency() / 20.0;
+            for _ in 0..100 {
+                circuit.tick(delta_t);
+                prop_assert!(circuit.charge().is_finite());
+                prop_assert!(circuit.current().is_finite());
+                prop_assert!(circuit.current_rate().is_finite());
+                prop_assert!(circuit.energy().is_finite());
+            }
+        }
+
+        #[test]
+        fn energy_never_grows(mut circuit in circuits()) {
+            //a tick within a period may gain a little near a turning point, as forward Euler
+            //does, but a whole period never does
+            let period = TAU / circuit.natural_angular_frequency();
+            let start = circuit.energy();
+            let mut last_period = start;
+            for _ in 0..5 {
+                for _ in 0..20 {
+                    let before = circuit.energy();
+                    circuit.tick(period / 20.0);
+                    prop_assert!(circuit.energy() <= before * (1.0 + 1e-3));
+                }
+                prop_assert!(circuit.energy() < last_period);
+                last_period = circuit.energy();
+            }
+            prop_assert!(circuit.energy() < start);
+        }
     }
 }
@@ -1,49 +1,636 @@
-use crate::graphics::DLRCCircuit;
-use bevy::{audio::AudioSink, prelude::*};
+use crate::graphics::{
+    default_mappings, simulation_state, AppState, AudioParameters, Mapping, MusicTempo, Scale,
+    KEY_NAMES,
+};
+use crate::storage::SaveFile;
+use bevy::{
+    asset::{Asset, LoadState},
+    audio::{play_queued_audio_system, AudioSink},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use bevy_egui::egui::{Align2, Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 pub struct MusicController(Handle<AudioSink>, Handle<AudioSink>);
 
+impl MusicController {
+    /// Pauses or resumes the music, leaving the noise as it is
+    ///
+    /// # Arguments
+    /// * 'audio_sinks' - the sinks the music is playing through
+    /// * 'paused' - whether the music should be paused
+    pub fn set_music_paused(&self, audio_sinks: &Assets<AudioSink>, paused: bool) {
+        if let Some(sink) = audio_sinks.get(&self.1) {
+            if paused && !sink.is_paused() {
+                sink.pause();
+            } else if !paused && sink.is_paused() {
+                sink.play();
+            }
+        }
+    }
+}
+
 pub struct MusicPlugin;
 
 impl Plugin for MusicPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_startup_system(setup_audio).add_system(volume);
+        add_audio_source::<ProceduralNoise>(app);
+        app.insert_resource(AudioSettings::load())
+            .add_event::<ReloadAudio>()
+            .add_startup_system(setup_audio)
+            .add_system(reload_audio)
+            .add_system(start_loaded_audio)
+            .add_system(audio_status_frame)
+            .add_system(apply_audio_parameters)
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(pause_music))
+            .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(resume_music));
+    }
+}
+
+/// Lets a custom audio source be played through `Audio<T>`, the way bevy sets up `AudioSource`
+///
+/// # Arguments
+/// * 'app' - the app to add the source to
+pub fn add_audio_source<T: Asset + Decodable>(app: &mut App) {
+    app.init_non_send_resource::<AudioOutput<T>>()
+        .add_asset::<T>()
+        .init_resource::<Audio<T>>()
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            play_queued_audio_system::<T>.exclusive_system(),
+        );
+}
+
+/* ASSET MANIFEST */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What an audio file is played for
+pub enum AudioRole {
+    /// the static that fades in while the lightbulb is dim
+    Noise,
+    Music,
+    /// the note played on every peak of the current
+    Pulse,
+}
+
+impl AudioRole {
+    /// The name of the role, as shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioRole::Noise => "noise",
+            AudioRole::Music => "music",
+            AudioRole::Pulse => "pulse",
+        }
+    }
+
+    /// What is played instead when the file for this role can't be loaded
+    pub fn fallback(&self) -> &'static str {
+        match self {
+            AudioRole::Noise => "procedural noise",
+            AudioRole::Music | AudioRole::Pulse => "silence",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How loading an audio file went
+pub enum AssetStatus {
+    Loading,
+    Loaded,
+    /// the file is missing or couldn't be decoded, so the role's fallback is used
+    Failed,
+}
+
+/// An audio file in the manifest
+pub struct AudioAsset {
+    pub role: AudioRole,
+    pub path: String,
+    pub handle: Handle<AudioSource>,
+    pub status: AssetStatus,
+}
+
+/// Every audio file the app plays, checked as they load
+pub struct AudioAssets {
+    pub assets: Vec<AudioAsset>,
+    /// whether the player has hidden the status panel
+    pub dismissed: bool,
+}
+
+impl AudioAssets {
+    /// Finds the file played for a role
+    pub fn get(&self, role: AudioRole) -> Option<&AudioAsset> {
+        self.assets.iter().find(|asset| asset.role == role)
+    }
+
+    /// The sound to play for a role
+    ///
+    /// # Returns
+    /// A handle to the sound, or none if it hasn't loaded
+    pub fn handle(&self, role: AudioRole) -> Option<Handle<AudioSource>> {
+        self.get(role)
+            .filter(|asset| asset.status == AssetStatus::Loaded)
+            .map(|asset| asset.handle.clone())
+    }
+
+    /// Starts loading the file for a role, replacing whatever was loaded before
+    fn load(&mut self, asset_server: &AssetServer, role: AudioRole, path: &str) {
+        let asset = AudioAsset {
+            role,
+            path: path.to_string(),
+            handle: asset_server.load(path),
+            status: AssetStatus::Loading,
+        };
+        match self.assets.iter_mut().find(|asset| asset.role == role) {
+            Some(old) => *old = asset,
+            None => self.assets.push(asset),
+        }
+    }
+}
+
+/* MIXER */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A group of sounds whose volume is set together
+pub enum Channel {
+    /// every other channel
+    Master,
+    Music,
+    Noise,
+    /// the pulse notes, the metronome and the circuit tones
+    Effects,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [
+        Channel::Master,
+        Channel::Music,
+        Channel::Noise,
+        Channel::Effects,
+    ];
+
+    /// The name of the channel, as shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Master => "master",
+            Channel::Music => "music",
+            Channel::Noise => "noise",
+            Channel::Effects => "effects",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// The volume of one channel
+pub struct ChannelLevel {
+    /// from 0 to 1
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for ChannelLevel {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            muted: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+/// The volume of every channel
+pub struct Mixer {
+    pub master: ChannelLevel,
+    pub music: ChannelLevel,
+    pub noise: ChannelLevel,
+    pub effects: ChannelLevel,
+}
+
+impl Mixer {
+    pub fn level(&self, channel: Channel) -> &ChannelLevel {
+        match channel {
+            Channel::Master => &self.master,
+            Channel::Music => &self.music,
+            Channel::Noise => &self.noise,
+            Channel::Effects => &self.effects,
+        }
+    }
+
+    pub fn level_mut(&mut self, channel: Channel) -> &mut ChannelLevel {
+        match channel {
+            Channel::Master => &mut self.master,
+            Channel::Music => &mut self.music,
+            Channel::Noise => &mut self.noise,
+            Channel::Effects => &mut self.effects,
+        }
+    }
+
+    /// Works out what a channel's sounds should be multiplied by
+    ///
+    /// # Returns
+    /// The channel's volume times the master volume, or 0 if either is muted
+    pub fn gain(&self, channel: Channel) -> f32 {
+        [self.master, *self.level(channel)]
+            .iter()
+            .map(|level| if level.muted { 0.0 } else { level.volume })
+            .product()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+/// The audio picked in the settings menu, saved between runs
+///
+/// Paths are relative to the `assets` folder unless they're absolute.
+pub struct AudioSettings {
+    pub mixer: Mixer,
+    pub noise_path: String,
+    pub music_path: String,
+    /// the tempo of the music file, which rhythm mode's beats follow
+    pub music_tempo: MusicTempo,
+    /// whether each circuit's current is played as a tone
+    pub tone: bool,
+    /// how many times faster than the simulation the tone's pitch runs, so slow circuits can be
+    /// heard, where 1 plays the circuit's real frequency
+    pub tone_time_scale: f64,
+    /// what the circuit does to the music, noise and pulse note
+    pub mappings: Vec<Mapping>,
+    /// the scale the pulse notes climb as the peaks get bigger
+    pub pulse_scale: Scale,
+    /// the semitones the pulse notes' scale is shifted up by, from 0 to 11
+    pub pulse_key: u8,
+    /// how many pulse notes can ring at once
+    pub pulse_polyphony: usize,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            mixer: Mixer::default(),
+            noise_path: "01-White-Noise-10min-popgone.ogg".to_string(),
+            music_path: "taishi-reverie-loop.ogg".to_string(),
+            music_tempo: MusicTempo::default(),
+            tone: true,
+            tone_time_scale: 5000.0,
+            mappings: default_mappings(),
+            pulse_scale: Scale::Pentatonic,
+            pulse_key: 0,
+            pulse_polyphony: 6,
+        }
+    }
+}
+
+impl SaveFile for AudioSettings {
+    const FILE: &'static str = "audio.ron";
+    const NAME: &'static str = "audio settings";
+}
+
+/// Sent to reload the noise and music from the paths in `AudioSettings`
+pub struct ReloadAudio;
+
+/* PROCEDURAL NOISE */
+#[derive(TypeUuid, Default)]
+#[uuid = "4731be7f-e036-4c0e-82a0-4a6c8f74e1af"]
+/// Endless white noise, played when the noise file can't be loaded
+pub struct ProceduralNoise;
+
+/// The samples of `ProceduralNoise`
+pub struct NoiseDecoder {
+    state: u32,
+}
+
+impl Iterator for NoiseDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        //xorshift, since the noise only has to sound random
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        Some(self.state as f32 / u32::MAX as f32 - 0.5)
     }
 }
 
+impl rodio::Source for NoiseDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        44100
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Decodable for ProceduralNoise {
+    type Decoder = NoiseDecoder;
+    type DecoderItem = f32;
+
+    fn decoder(&self) -> NoiseDecoder {
+        NoiseDecoder { state: 0x9e3779b9 }
+    }
+}
+
+/// Adds the controls for the audio settings, saving them as they change
+///
+/// # Arguments
+/// * 'ui' - the ui to add the controls to
+/// * 'settings' - the audio settings being edited
+/// * 'reload_writer' - used to reload the noise and music once new files are picked
+pub fn audio_settings_ui(
+    ui: &mut egui::Ui,
+    settings: &mut AudioSettings,
+    reload_writer: &mut EventWriter<ReloadAudio>,
+) {
+    for channel in Channel::ALL {
+        ui.with_layout(egui::Layout::left_to_right(), |ui| {
+            let level = settings.mixer.level_mut(channel);
+            let muted = ui.checkbox(&mut level.muted, "mute");
+            let volume = ui.add_enabled(
+                !level.muted,
+                egui::Slider::new(&mut level.volume, 0.0..=1.0).text(channel.name()),
+            );
+            if muted.changed() || finished_changing(&volume) {
+                settings.save();
+            }
+        });
+    }
+
+    ui.separator();
+    ui.label("Noise and music files, relative to the assets folder or absolute:");
+    ui.text_edit_singleline(&mut settings.noise_path);
+    ui.text_edit_singleline(&mut settings.music_path);
+    ui.label("The music's tempo and where its first beat is, which rhythm mode plays along with:");
+    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+        let bpm = ui.add(
+            egui::DragValue::new(&mut settings.music_tempo.bpm)
+                .clamp_range(20.0..=300.0)
+                .suffix(" bpm"),
+        );
+        let first_beat = ui.add(
+            egui::DragValue::new(&mut settings.music_tempo.first_beat)
+                .clamp_range(0.0..=10.0)
+                .speed(0.01)
+                .suffix(" (s)"),
+        );
+        if finished_changing(&bpm) || finished_changing(&first_beat) {
+            settings.save();
+        }
+    });
+    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+        if ui.button("Apply").clicked() {
+            settings.save();
+            reload_writer.send(ReloadAudio);
+        }
+        if ui.button("Default files").clicked() {
+            let defaults = AudioSettings::default();
+            settings.noise_path = defaults.noise_path;
+            settings.music_path = defaults.music_path;
+            settings.music_tempo = defaults.music_tempo;
+            settings.save();
+            reload_writer.send(ReloadAudio);
+        }
+    });
+    let tone = ui.checkbox(&mut settings.tone, "Play the circuit as a tone");
+    let time_scale = ui.add_enabled(
+        settings.tone,
+        egui::Slider::new(&mut settings.tone_time_scale, 1.0..=100000.0)
+            .logarithmic(true)
+            .text("tone time scale"),
+    );
+    if tone.changed() || finished_changing(&time_scale) {
+        settings.save();
+    }
+
+    ui.separator();
+    let picked = (settings.pulse_scale, settings.pulse_key);
+    egui::ComboBox::from_label("pulse scale")
+        .selected_text(settings.pulse_scale.name())
+        .show_ui(ui, |ui| {
+            for scale in Scale::ALL {
+                ui.selectable_value(&mut settings.pulse_scale, scale, scale.name());
+            }
+        });
+    egui::ComboBox::from_label("pulse key")
+        .selected_text(KEY_NAMES[settings.pulse_key as usize % 12])
+        .show_ui(ui, |ui| {
+            for (key, name) in KEY_NAMES.iter().enumerate() {
+                ui.selectable_value(&mut settings.pulse_key, key as u8, *name);
+            }
+        });
+    let polyphony =
+        ui.add(egui::Slider::new(&mut settings.pulse_polyphony, 1..=16).text("pulse voices"));
+    if picked != (settings.pulse_scale, settings.pulse_key) || finished_changing(&polyphony) {
+        settings.save();
+    }
+}
+
+/// Whether a slider has been let go of after a change, so the settings aren't written every frame
+/// while it's dragged
+fn finished_changing(response: &egui::Response) -> bool {
+    response.drag_released() || (response.changed() && !response.dragged())
+}
+
 /// Initializes audio to be used later
 fn setup_audio(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<AudioSettings>,
+) {
+    let mut assets = AudioAssets {
+        assets: Vec::new(),
+        dismissed: false,
+    };
+    assets.load(&asset_server, AudioRole::Noise, &settings.noise_path);
+    assets.load(&asset_server, AudioRole::Music, &settings.music_path);
+    assets.load(&asset_server, AudioRole::Pulse, "taishi-piano-note.ogg");
+    commands.insert_resource(assets);
+    commands.insert_resource(MusicController(Handle::default(), Handle::default()));
+}
+
+/// Stops the noise and music and loads them again from the settings
+fn reload_audio(
+    mut events: EventReader<ReloadAudio>,
+    asset_server: Res<AssetServer>,
+    settings: Res<AudioSettings>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    mut assets: ResMut<AudioAssets>,
+    mut music_controller: ResMut<MusicController>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    for handle in [&music_controller.0, &music_controller.1] {
+        if let Some(sink) = audio_sinks.get(handle) {
+            sink.stop();
+        }
+    }
+    *music_controller = MusicController(Handle::default(), Handle::default());
+    assets.load(&asset_server, AudioRole::Noise, &settings.noise_path);
+    assets.load(&asset_server, AudioRole::Music, &settings.music_path);
+    assets.dismissed = false;
+}
+
+/// Checks on every loading audio file, starting the noise and music once they load, or their
+/// fallbacks if they fail
+fn start_loaded_audio(
     asset_server: Res<AssetServer>,
     audio: Res<Audio>,
+    noise_audio: Res<Audio<ProceduralNoise>>,
+    mut noise_sources: ResMut<Assets<ProceduralNoise>>,
     audio_sinks: Res<Assets<AudioSink>>,
+    mut assets: ResMut<AudioAssets>,
+    mut music_controller: ResMut<MusicController>,
 ) {
-    let static_noise = asset_server.load("01-White-Noise-10min-popgone.ogg");
-    let music = asset_server.load("taishi-reverie-loop.ogg");
-    let static_noise_handle =
-        audio_sinks.get_handle(audio.play_with_settings(static_noise, PlaybackSettings::LOOP));
-    let music_handle =
-        audio_sinks.get_handle(audio.play_with_settings(music, PlaybackSettings::LOOP));
-    commands.insert_resource(MusicController(static_noise_handle, music_handle));
-}
-
-/// implements dynamic volume music/sound effects
-fn volume(
-    query_circs: Query<&DLRCCircuit>,
+    for asset in assets.assets.iter_mut() {
+        if asset.status != AssetStatus::Loading {
+            continue;
+        }
+        let sink = match asset_server.get_load_state(&asset.handle) {
+            LoadState::Loaded => {
+                asset.status = AssetStatus::Loaded;
+                if asset.role == AudioRole::Pulse {
+                    continue;
+                }
+                audio.play_with_settings(asset.handle.clone(), PlaybackSettings::LOOP)
+            }
+            LoadState::Failed => {
+                asset.status = AssetStatus::Failed;
+                warn!(
+                    "couldn't load the {} from '{}', using {} instead",
+                    asset.role.name(),
+                    asset.path,
+                    asset.role.fallback()
+                );
+                if asset.role != AudioRole::Noise {
+                    continue;
+                }
+                //the noise never ends, so it doesn't need to loop
+                noise_audio.play(noise_sources.add(ProceduralNoise))
+            }
+            _ => continue,
+        };
+        let sink = audio_sinks.get_handle(sink);
+        match asset.role {
+            AudioRole::Noise => music_controller.0 = sink,
+            AudioRole::Music => music_controller.1 = sink,
+            AudioRole::Pulse => {}
+        }
+    }
+}
+
+/// creates a panel listing the audio files, shown in the settings or when any couldn't be loaded
+fn audio_status_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut assets: ResMut<AudioAssets>,
+    state: Res<State<AppState>>,
+) {
+    let in_settings = *state.current() == AppState::Settings;
+    let failed = assets
+        .assets
+        .iter()
+        .any(|asset| asset.status == AssetStatus::Failed);
+    if !in_settings && (assets.dismissed || !failed) {
+        return;
+    }
+    egui::Window::new("Audio")
+        .anchor(Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            audio_status(ui, &assets);
+            if !in_settings {
+                ui.label("Other files can be picked in the settings.");
+                if ui.button("Dismiss").clicked() {
+                    assets.dismissed = true;
+                }
+            }
+        });
+}
+
+/// Lists the audio files and how loading them went, along with what plays instead of any that
+/// failed
+///
+/// # Arguments
+/// * 'ui' - the ui to add the list to
+/// * 'assets' - the audio manifest
+fn audio_status(ui: &mut egui::Ui, assets: &AudioAssets) {
+    for asset in assets.assets.iter() {
+        let (text, color) = match asset.status {
+            AssetStatus::Loading => (format!("loading '{}'", asset.path), Color32::GRAY),
+            AssetStatus::Loaded => (format!("'{}'", asset.path), Color32::LIGHT_GREEN),
+            AssetStatus::Failed => (
+                format!(
+                    "couldn't load '{}', using {}",
+                    asset.path,
+                    asset.role.fallback()
+                ),
+                Color32::LIGHT_RED,
+            ),
+        };
+        ui.label(RichText::new(format!("{}: {}", asset.role.name(), text)).color(color));
+    }
+}
+
+/// Works out how far the noise has faded in since the app started
+///
+/// # Arguments
+/// * 'seconds' - the seconds since the app started
+///
+/// # Returns
+/// What the noise's volume is multiplied by, from 0 to 1
+pub fn noise_fade_in(seconds: f32) -> f32 {
+    1.0 - 0.04f32.powf(0.05 * seconds)
+}
+
+/// sets the volume and speed of the music and noise from the mapped audio parameters and the
+/// mixer, fading the noise in as the app starts
+///
+/// The music keeps its own tempo in rhythm mode, as the beat maps follow it.
+fn apply_audio_parameters(
+    parameters: Res<AudioParameters>,
+    settings: Res<AudioSettings>,
+    state: Res<State<AppState>>,
     time: Res<Time>,
     audio_sinks: Res<Assets<AudioSink>>,
     music_controller: Res<MusicController>,
 ) {
-    let mut circ_amount = 1;
-    let mut power_avg = 0.0;
-    for ddlc in query_circs.iter() {
-        circ_amount += 1;
-        power_avg += ddlc.0.lightbulb_power()
-    }
-    power_avg /= circ_amount as f64;
     if let Some(sink) = audio_sinks.get(&music_controller.0) {
-        let mut max_vol = 0.04f32;
-        max_vol = max_vol - max_vol.powf(0.05f32 * time.seconds_since_startup() as f32 + 1f32);
-        sink.set_volume(max_vol - (power_avg as f32).min(max_vol));
+        let fade_in = noise_fade_in(time.seconds_since_startup() as f32);
+        sink.set_volume(parameters.noise_volume * fade_in * settings.mixer.gain(Channel::Noise));
+    }
+    if let Some(sink) = audio_sinks.get(&music_controller.1) {
+        sink.set_volume(parameters.music_volume * settings.mixer.gain(Channel::Music));
+        if simulation_state(&state) == Some(AppState::Rhythm) {
+            sink.set_speed(1.0);
+        } else {
+            sink.set_speed(parameters.playback_speed);
+        }
+    }
+}
+
+/// pauses the music and noise along with the simulation
+fn pause_music(audio_sinks: Res<Assets<AudioSink>>, music_controller: Res<MusicController>) {
+    for handle in [&music_controller.0, &music_controller.1] {
+        if let Some(sink) = audio_sinks.get(handle) {
+            sink.pause();
+        }
+    }
+}
+
+/// picks the music and noise back up when the simulation is unpaused
+fn resume_music(audio_sinks: Res<Assets<AudioSink>>, music_controller: Res<MusicController>) {
+    for handle in [&music_controller.0, &music_controller.1] {
+        if let Some(sink) = audio_sinks.get(handle) {
+            sink.play();
+        }
     }
 }
@@ -0,0 +1,1119 @@
+//! the build mode, where the player places elements and wires on a grid and plays the loop they
+//! make
+
+use bevy::prelude::*;
+use bevy_egui::egui::{Align2, Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+use bevy_prototype_lyon::{
+    prelude::{DrawMode, FillMode, GeometryBuilder, StrokeMode},
+    shapes,
+};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::graphics::{
+    clear_circuits, element_geometry, switch_geometry, ActiveLevel, AppState, CircuitConstants,
+    Element, PlacedElement, Schematic, WIRE_WIDTH,
+};
+
+/// This plugin adds the build mode, with its grid, palette and the netlist it plays.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorCircuit>()
+            .init_resource::<EditorState>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Build)
+                    .with_system(clear_circuits)
+                    .with_system(spawn_editor_grid),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Build)
+                    .with_system(editor_input)
+                    .with_system(draw_editor.after(editor_input))
+                    .with_system(editor_frame),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Build).with_system(despawn_editor));
+    }
+}
+
+/* GRID */
+/// the pixels between two grid nodes
+const GRID_SPACING: f32 = 80.0;
+/// how many nodes across and down the grid is
+const GRID_SIZE: (i32, i32) = (13, 7);
+/// where the bottom left node is drawn, leaving room for the palette on the left
+const GRID_ORIGIN: (f32, f32) = (-360.0, -240.0);
+/// how close the cursor has to be to a node or the middle of an edge to pick it
+const PICK_DISTANCE: f32 = 25.0;
+/// the scale elements are drawn at, so they fit between two nodes
+const EDITOR_SCALE: f32 = 0.2;
+/// how far in front of everything the editor is drawn
+const EDITOR_DEPTH: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// A point on the grid where wires and elements join
+pub struct GridNode {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl GridNode {
+    /// Where the node is drawn
+    pub fn position(&self) -> Vec2 {
+        Vec2::new(
+            GRID_ORIGIN.0 + self.x as f32 * GRID_SPACING,
+            GRID_ORIGIN.1 + self.y as f32 * GRID_SPACING,
+        )
+    }
+
+    /// Finds the node closest to a point
+    ///
+    /// # Returns
+    /// The node, or none if the point is too far from the grid to pick one
+    pub fn nearest(point: Vec2) -> Option<Self> {
+        let node = Self {
+            x: ((point.x - GRID_ORIGIN.0) / GRID_SPACING).round() as i32,
+            y: ((point.y - GRID_ORIGIN.1) / GRID_SPACING).round() as i32,
+        };
+        (node.on_grid() && node.position().distance(point) < PICK_DISTANCE).then_some(node)
+    }
+
+    fn on_grid(&self) -> bool {
+        (0..GRID_SIZE.0).contains(&self.x) && (0..GRID_SIZE.1).contains(&self.y)
+    }
+}
+
+/// Finds the edge between two neighboring nodes whose middle is closest to a point
+///
+/// # Returns
+/// The nodes at either end of the edge, or none if the point is too far from any edge
+fn nearest_edge(point: Vec2) -> Option<(GridNode, GridNode)> {
+    let x = (point.x - GRID_ORIGIN.0) / GRID_SPACING;
+    let y = (point.y - GRID_ORIGIN.1) / GRID_SPACING;
+    let horizontal = GridNode {
+        x: x.floor() as i32,
+        y: y.round() as i32,
+    };
+    let vertical = GridNode {
+        x: x.round() as i32,
+        y: y.floor() as i32,
+    };
+    [
+        (
+            horizontal,
+            GridNode {
+                x: horizontal.x + 1,
+                ..horizontal
+            },
+        ),
+        (
+            vertical,
+            GridNode {
+                y: vertical.y + 1,
+                ..vertical
+            },
+        ),
+    ]
+    .into_iter()
+    .filter(|(a, b)| a.on_grid() && b.on_grid())
+    .map(|(a, b)| {
+        (
+            (a, b),
+            ((a.position() + b.position()) * 0.5).distance(point),
+        )
+    })
+    .filter(|(_, distance)| *distance < PICK_DISTANCE)
+    .min_by(|a, b| a.1.total_cmp(&b.1))
+    .map(|(edge, _)| edge)
+}
+
+/// Finds the steps of grid between two nodes, across first and then up or down
+fn grid_path(from: GridNode, to: GridNode) -> Vec<(GridNode, GridNode)> {
+    let mut steps = Vec::new();
+    let mut at = from;
+    while at != to {
+        let next = if at.x != to.x {
+            GridNode {
+                x: at.x + (to.x - at.x).signum(),
+                ..at
+            }
+        } else {
+            GridNode {
+                y: at.y + (to.y - at.y).signum(),
+                ..at
+            }
+        };
+        steps.push((at, next));
+        at = next;
+    }
+    steps
+}
+
+/* EDITOR CIRCUIT */
+#[derive(Debug, Clone)]
+/// A wire or element joining two neighboring grid nodes
+pub struct EditorItem {
+    /// the element, none for a plain wire
+    pub element: Option<Element>,
+    /// the node the item starts at, which is the negative side of a source
+    pub from: GridNode,
+    pub to: GridNode,
+    /// the resistance, inductance, capacitance or voltage of the element
+    pub value: f64,
+    /// whether a switch is closed
+    pub closed: bool,
+}
+
+impl EditorItem {
+    fn new(element: Option<Element>, from: GridNode, to: GridNode) -> Self {
+        Self {
+            element,
+            from,
+            to,
+            value: element.map_or(0.0, default_value),
+            closed: true,
+        }
+    }
+
+    /// Whether the item joins the same two nodes as an edge, in either direction
+    fn joins(&self, (a, b): (GridNode, GridNode)) -> bool {
+        (self.from == a && self.to == b) || (self.from == b && self.to == a)
+    }
+}
+
+/// The value an element starts with, the same as the sandbox circuit
+fn default_value(element: Element) -> f64 {
+    match element {
+        Element::Resistor | Element::Bulb => 0.2,
+        Element::Inductor => 4.0,
+        Element::Capacitor => 6.0,
+        Element::Source => 10.0 / 6.0,
+        Element::Switch => 0.0,
+    }
+}
+
+/// Everything placed on the editor's grid
+pub struct EditorCircuit {
+    pub items: Vec<EditorItem>,
+}
+
+impl Default for EditorCircuit {
+    /// a loop with the same circuit as the sandbox, started by a source through a switch
+    fn default() -> Self {
+        let node = |x, y| GridNode { x, y };
+        let mut items = vec![
+            EditorItem::new(Some(Element::Source), node(3, 4), node(4, 4)),
+            EditorItem::new(Some(Element::Bulb), node(5, 4), node(6, 4)),
+            EditorItem::new(Some(Element::Inductor), node(7, 4), node(8, 4)),
+            EditorItem::new(Some(Element::Capacitor), node(9, 2), node(9, 3)),
+            EditorItem::new(Some(Element::Switch), node(5, 1), node(6, 1)),
+        ];
+        let wires = [
+            (node(4, 4), node(5, 4)),
+            (node(6, 4), node(7, 4)),
+            (node(8, 4), node(9, 4)),
+            (node(9, 4), node(9, 3)),
+            (node(9, 2), node(9, 1)),
+            (node(9, 1), node(6, 1)),
+            (node(5, 1), node(3, 1)),
+            (node(3, 1), node(3, 4)),
+        ];
+        for (from, to) in wires {
+            for edge in grid_path(from, to) {
+                items.push(EditorItem::new(None, edge.0, edge.1));
+            }
+        }
+        Self { items }
+    }
+}
+
+impl EditorCircuit {
+    /// Places an item, replacing anything already on its edge
+    ///
+    /// # Returns
+    /// The index of the placed item
+    fn place(&mut self, item: EditorItem) -> usize {
+        self.items
+            .retain(|other| !other.joins((item.from, item.to)));
+        self.items.push(item);
+        self.items.len() - 1
+    }
+
+    /// Finds the item on an edge
+    fn item_at(&self, edge: (GridNode, GridNode)) -> Option<usize> {
+        self.items.iter().position(|item| item.joins(edge))
+    }
+
+    /// Works out the series circuit the items make
+    ///
+    /// Wires and closed switches join nodes into one, and open switches leave out the branch
+    /// they're on. What's left is reduced to a single loop: parts one after the other add up in
+    /// series, with the sources adding up to the loop's EMF, and parts of the same kind side by
+    /// side combine in parallel. Parts of different kinds side by side can't be reduced to a
+    /// series circuit, so they're refused. The loop is walked from the first placed part in it, the
+    /// way it faces.
+    ///
+    /// # Returns
+    /// The circuit, or why the items can't be simulated
+    pub fn netlist(&self) -> Result<BuiltCircuit, String> {
+        if self.items.is_empty() {
+            return Err("Place some elements and wires first.".to_string());
+        }
+        //numbered in the order they're placed, so the same items always reduce the same way
+        let mut nodes: HashMap<GridNode, usize> = HashMap::new();
+        let mut joined: Vec<usize> = Vec::new();
+        for item in self.items.iter() {
+            for node in [item.from, item.to] {
+                let index = *nodes.entry(node).or_insert(joined.len());
+                if index == joined.len() {
+                    joined.push(0);
+                }
+                joined[index] += 1;
+            }
+        }
+        if joined.contains(&1) {
+            return Err("A wire or element has a loose end.".to_string());
+        }
+
+        //every node joined by wires and closed switches is one node of the circuit
+        let mut parent: Vec<usize> = (0..joined.len()).collect();
+        for item in self.items.iter() {
+            let joins = match item.element {
+                None => true,
+                Some(Element::Switch) => item.closed,
+                Some(_) => false,
+            };
+            if joins {
+                let a = find_root(&mut parent, nodes[&item.from]);
+                let b = find_root(&mut parent, nodes[&item.to]);
+                parent[a] = b;
+            }
+        }
+
+        let mut branches = Vec::new();
+        let mut open_switch = false;
+        for (index, item) in self.items.iter().enumerate() {
+            let element = match item.element {
+                Some(Element::Switch) => {
+                    open_switch |= !item.closed;
+                    continue;
+                }
+                Some(element) => element,
+                None => continue,
+            };
+            let ends = (
+                find_root(&mut parent, nodes[&item.from]),
+                find_root(&mut parent, nodes[&item.to]),
+            );
+            branches.push(Branch::new(index, element, item.value, ends));
+        }
+
+        //branches left hanging by an open switch carry no current
+        loop {
+            let mut ends: HashMap<usize, usize> = HashMap::new();
+            for branch in branches.iter() {
+                *ends.entry(branch.ends.0).or_default() += 1;
+                *ends.entry(branch.ends.1).or_default() += 1;
+            }
+            let before = branches.len();
+            branches.retain(|branch| ends[&branch.ends.0] > 1 && ends[&branch.ends.1] > 1);
+            if branches.len() == before {
+                break;
+            }
+        }
+        if branches.is_empty() {
+            return Err(if open_switch {
+                "A switch is open, so no current can flow.".to_string()
+            } else {
+                "The loop needs an inductor and a capacitor.".to_string()
+            });
+        }
+
+        while branches.len() > 1 || branches.iter().any(|branch| !branch.is_loop()) {
+            let (a, b, in_series) =
+                match (series_pair(&branches), parallel_pair(&branches)) {
+                    (Some((a, b)), _) => (a, b, true),
+                    (None, Some((a, b))) => (a, b, false),
+                    (None, None) if branches.iter().any(Branch::is_loop) => {
+                        return Err("Everything has to be part of one loop.".to_string())
+                    }
+                    (None, None) => return Err(
+                        "Only parts of the same kind can sit side by side, like two capacitors \
+                         in parallel."
+                            .to_string(),
+                    ),
+                };
+            //a is always before b
+            let second = branches.remove(b);
+            let first = branches.remove(a);
+            branches.push(if in_series {
+                first.series(second)
+            } else {
+                first.parallel(&second)
+            });
+        }
+
+        let mut branch = branches.remove(0);
+        let first_part = branch.parts.iter().min_by_key(|part| part.item);
+        if matches!(first_part, Some(part) if !part.forwards) {
+            branch = branch.reversed();
+        }
+        //and starts from that part
+        if let Some(start) = branch
+            .parts
+            .iter()
+            .map(|part| part.item)
+            .enumerate()
+            .min_by_key(|&(_, item)| item)
+        {
+            branch.parts.rotate_left(start.0);
+        }
+        if branch.inductance <= 0.0 {
+            return Err("The loop needs an inductor.".to_string());
+        }
+        if branch.elastance <= 0.0 {
+            return Err("The loop needs a capacitor.".to_string());
+        }
+        Ok(BuiltCircuit {
+            constants: CircuitConstants {
+                //the capacitors start empty, for the sources to charge
+                startcharge: 0.0,
+                startcurrent: 0.0,
+                resistance: branch.resistance,
+                inductance: branch.inductance,
+                capacitance: branch.elastance.recip(),
+                emf: branch.emf,
+            },
+            elements: branch
+                .parts
+                .iter()
+                .map(|part| match part.element {
+                    Element::Source if !part.forwards => (part.element, Some(-part.value)),
+                    _ => (part.element, Some(part.value)),
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Finds the node a node has been joined into
+///
+/// # Arguments
+/// * 'parent' - the node each node was joined into, itself if it's the root
+/// * 'node' - the node to look up
+fn find_root(parent: &mut [usize], mut node: usize) -> usize {
+    while parent[node] != node {
+        parent[node] = parent[parent[node]];
+        node = parent[node];
+    }
+    node
+}
+
+#[derive(Debug, Clone, Copy)]
+/// An element along a branch
+struct BranchPart {
+    /// the index of the item it was placed as, the first of them if it's a parallel group
+    item: usize,
+    /// whether it faces along the branch, from its first end to its second
+    forwards: bool,
+    element: Element,
+    value: f64,
+}
+
+#[derive(Debug, Clone)]
+/// Part of a circuit between two nodes, made of elements one after the other
+struct Branch {
+    /// the nodes at either end, where a positive EMF pushes current from the first to the second
+    ends: (usize, usize),
+    resistance: f64,
+    inductance: f64,
+    /// the inverse of the capacitance, which adds up in series
+    elastance: f64,
+    emf: f64,
+    /// the elements in order from the first end
+    parts: Vec<BranchPart>,
+}
+
+impl Branch {
+    /// A branch of a single element, facing from its first node to its second
+    fn new(item: usize, element: Element, value: f64, ends: (usize, usize)) -> Self {
+        let mut branch = Self {
+            ends,
+            resistance: 0.0,
+            inductance: 0.0,
+            elastance: 0.0,
+            emf: 0.0,
+            parts: vec![BranchPart {
+                item,
+                forwards: true,
+                element,
+                value,
+            }],
+        };
+        match element {
+            Element::Resistor | Element::Bulb => branch.resistance = value,
+            Element::Inductor => branch.inductance = value,
+            Element::Capacitor => branch.elastance = value.recip(),
+            Element::Source => branch.emf = value,
+            Element::Switch => {}
+        }
+        branch
+    }
+
+    /// Whether the branch starts and ends at the same node, making a loop of its own
+    fn is_loop(&self) -> bool {
+        self.ends.0 == self.ends.1
+    }
+
+    /// The one kind of element in the branch, counting bulbs as resistors, or none if it mixes
+    /// kinds or has a source
+    fn kind(&self) -> Option<Element> {
+        let kind = |part: &BranchPart| match part.element {
+            Element::Bulb => Element::Resistor,
+            element => element,
+        };
+        let first = kind(self.parts.first()?);
+        (first != Element::Source && self.parts.iter().all(|part| kind(part) == first))
+            .then_some(first)
+    }
+
+    /// The same branch, facing the other way
+    fn reversed(mut self) -> Self {
+        self.ends = (self.ends.1, self.ends.0);
+        self.emf = -self.emf;
+        self.parts.reverse();
+        for part in self.parts.iter_mut() {
+            part.forwards = !part.forwards;
+        }
+        self
+    }
+
+    /// Joins a branch on after this one, at a node they share
+    fn series(self, other: Self) -> Self {
+        let shared = if self.ends.1 == other.ends.0 || self.ends.1 == other.ends.1 {
+            self.ends.1
+        } else {
+            self.ends.0
+        };
+        let first = if self.ends.1 == shared {
+            self
+        } else {
+            self.reversed()
+        };
+        let mut second = if other.ends.0 == shared {
+            other
+        } else {
+            other.reversed()
+        };
+        let mut parts = first.parts;
+        parts.append(&mut second.parts);
+        Self {
+            ends: (first.ends.0, second.ends.1),
+            resistance: first.resistance + second.resistance,
+            inductance: first.inductance + second.inductance,
+            elastance: first.elastance + second.elastance,
+            emf: first.emf + second.emf,
+            parts,
+        }
+    }
+
+    /// Puts a branch of the same kind side by side with this one, as a single element
+    fn parallel(self, other: &Self) -> Self {
+        //resistances, inductances and elastances all combine as x y / (x + y) side by side
+        let combine = |x: f64, y: f64| if x + y > 0.0 { x * y / (x + y) } else { 0.0 };
+        let resistance = combine(self.resistance, other.resistance);
+        let inductance = combine(self.inductance, other.inductance);
+        let elastance = combine(self.elastance, other.elastance);
+        let parts = || self.parts.iter().chain(other.parts.iter());
+        let (element, value) = match self.kind() {
+            Some(Element::Inductor) => (Element::Inductor, inductance),
+            Some(Element::Capacitor) => (Element::Capacitor, elastance.recip()),
+            //a bulb keeps its light, even with other resistors beside it
+            _ if parts().any(|part| part.element == Element::Bulb) => (Element::Bulb, resistance),
+            _ => (Element::Resistor, resistance),
+        };
+        Self {
+            ends: self.ends,
+            resistance,
+            inductance,
+            elastance,
+            emf: 0.0,
+            parts: vec![BranchPart {
+                item: parts().map(|part| part.item).min().unwrap_or(0),
+                forwards: true,
+                element,
+                value,
+            }],
+        }
+    }
+}
+
+/// Finds two branches meeting at a node nothing else joins, so they're in series
+///
+/// # Returns
+/// The indices of both branches, in order
+fn series_pair(branches: &[Branch]) -> Option<(usize, usize)> {
+    let mut ends: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (index, branch) in branches.iter().enumerate() {
+        ends.entry(branch.ends.0).or_default().push(index);
+        ends.entry(branch.ends.1).or_default().push(index);
+    }
+    ends.values().find_map(|joined| match joined[..] {
+        [a, b] if a != b => Some((a.min(b), a.max(b))),
+        _ => None,
+    })
+}
+
+/// Finds two branches of the same kind between the same two nodes, so they're in parallel
+///
+/// # Returns
+/// The indices of both branches, in order
+fn parallel_pair(branches: &[Branch]) -> Option<(usize, usize)> {
+    let side_by_side = |a: &Branch, b: &Branch| {
+        !a.is_loop()
+            && (a.ends == b.ends || a.ends == (b.ends.1, b.ends.0))
+            && a.kind().is_some()
+            && a.kind() == b.kind()
+    };
+    (0..branches.len()).find_map(|a| {
+        ((a + 1)..branches.len())
+            .find(|&b| side_by_side(&branches[a], &branches[b]))
+            .map(|b| (a, b))
+    })
+}
+
+/// A loop from the editor, reduced to a series circuit
+#[derive(Debug, Clone)]
+pub struct BuiltCircuit {
+    pub constants: CircuitConstants,
+    /// the elements in the order the current passes through them, with their own values, none
+    /// for those labelled with the circuit's
+    pub elements: Vec<(Element, Option<f64>)>,
+}
+
+impl BuiltCircuit {
+    /// The usual bulb, inductor and capacitor, labelled with the circuit's values
+    ///
+    /// # Arguments
+    /// * 'constants' - the circuit's values
+    pub fn light_circuit(constants: CircuitConstants) -> Self {
+        Self {
+            constants,
+            elements: vec![
+                (Element::Bulb, None),
+                (Element::Inductor, None),
+                (Element::Capacitor, None),
+            ],
+        }
+    }
+
+    /// Lays the loop's elements out in the order they're connected
+    pub fn schematic(&self) -> Schematic {
+        Schematic::series(&self.elements)
+    }
+}
+
+/* EDITOR STATE */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What dragging across the grid does
+pub enum EditorTool {
+    Wire,
+    /// places the element on the first step of the drag, with wire for the rest
+    Place(Element),
+    /// removes whatever is clicked
+    Erase,
+}
+
+/// What's being dragged across the grid
+enum Drag {
+    /// laying wire or an element from a node
+    Laying(GridNode),
+    /// moving an item to another edge
+    Moving(usize),
+}
+
+/// The tool, selection and drag of the editor
+pub struct EditorState {
+    pub tool: EditorTool,
+    /// the index of the selected item
+    pub selected: Option<usize>,
+    drag: Option<Drag>,
+    /// where the cursor is while dragging
+    cursor: Option<Vec2>,
+    /// why the circuit couldn't be played
+    pub error: Option<String>,
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        Self {
+            tool: EditorTool::Wire,
+            selected: None,
+            drag: None,
+            cursor: None,
+            error: None,
+        }
+    }
+}
+
+/// Finds where the cursor is in the world
+///
+/// # Returns
+/// The point, where the 2d camera puts the origin in the middle of the window, or none if the
+/// cursor is outside the window
+pub fn cursor_world(windows: &Windows) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()?;
+    Some(cursor - Vec2::new(window.width(), window.height()) * 0.5)
+}
+
+/// Places, moves, selects and erases items with the mouse
+fn editor_input(
+    windows: Res<Windows>,
+    buttons: Res<Input<MouseButton>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut editor: ResMut<EditorCircuit>,
+    mut state: ResMut<EditorState>,
+) {
+    let cursor = match cursor_world(&windows) {
+        Some(cursor) => cursor,
+        None => {
+            //a drag let go of outside the window is dropped
+            if buttons.just_released(MouseButton::Left) {
+                state.drag = None;
+                state.cursor = None;
+            }
+            return;
+        }
+    };
+    if state.drag.is_some() && state.cursor != Some(cursor) {
+        state.cursor = Some(cursor);
+    }
+
+    if buttons.just_pressed(MouseButton::Left) && !egui_context.ctx_mut().wants_pointer_input() {
+        let node = GridNode::nearest(cursor);
+        let item = nearest_edge(cursor).and_then(|edge| editor.item_at(edge));
+        if state.tool == EditorTool::Erase {
+            if let Some(index) = item {
+                editor.items.remove(index);
+                state.selected = None;
+            }
+        } else if let Some(index) = item.filter(|_| node.is_none()) {
+            state.selected = Some(index);
+            state.drag = Some(Drag::Moving(index));
+        } else if let Some(node) = node {
+            state.selected = None;
+            state.drag = Some(Drag::Laying(node));
+        }
+    }
+
+    if buttons.just_released(MouseButton::Left) {
+        let drag = state.drag.take();
+        state.cursor = None;
+        match drag {
+            Some(Drag::Laying(start)) => {
+                let end = match GridNode::nearest(cursor) {
+                    Some(end) => end,
+                    None => return,
+                };
+                let mut placed = None;
+                for (step, (from, to)) in grid_path(start, end).into_iter().enumerate() {
+                    let element = match state.tool {
+                        EditorTool::Place(element) if step == 0 => Some(element),
+                        _ => None,
+                    };
+                    let index = editor.place(EditorItem::new(element, from, to));
+                    if element.is_some() {
+                        placed = Some(index);
+                    }
+                }
+                if placed.is_some() {
+                    //the indices before it may have shifted as the wires replaced things
+                    state.selected = editor
+                        .items
+                        .iter()
+                        .rposition(|item| item.element.is_some() && item.from == start);
+                }
+            }
+            Some(Drag::Moving(index)) => {
+                if let Some(edge) = nearest_edge(cursor) {
+                    if !editor.items[index].joins(edge) {
+                        let mut item = editor.items.remove(index);
+                        item.from = edge.0;
+                        item.to = edge.1;
+                        state.selected = Some(editor.place(item));
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+/* DRAWING */
+#[derive(Component)]
+/// Marks everything the editor spawns, so it can all be removed when leaving
+pub struct EditorEntity;
+
+#[derive(Component)]
+/// Marks the drawings of items and the drag preview, which are redrawn whenever they change
+pub struct EditorDrawing;
+
+/// draws a dot at every node of the grid
+fn spawn_editor_grid(mut commands: Commands) {
+    for x in 0..GRID_SIZE.0 {
+        for y in 0..GRID_SIZE.1 {
+            let position = GridNode { x, y }.position();
+            commands
+                .spawn_bundle(GeometryBuilder::build_as(
+                    &shapes::Circle {
+                        radius: 3.0,
+                        ..shapes::Circle::default()
+                    },
+                    DrawMode::Fill(FillMode::color(Color::GRAY)),
+                    Transform::from_translation(position.extend(EDITOR_DEPTH)),
+                ))
+                .insert(EditorEntity);
+        }
+    }
+}
+
+/// Redraws the items and the drag preview whenever they change
+fn draw_editor(
+    mut commands: Commands,
+    editor: Res<EditorCircuit>,
+    state: Res<EditorState>,
+    query_drawings: Query<Entity, With<EditorDrawing>>,
+) {
+    if !editor.is_changed() && !state.is_changed() {
+        return;
+    }
+    for entity in query_drawings.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    //drawn along the x axis, then turned and shrunk onto the edge
+    let half_edge = GRID_SPACING * 0.5 / EDITOR_SCALE;
+    for (index, item) in editor.items.iter().enumerate() {
+        let mut builder = GeometryBuilder::new();
+        match item.element {
+            Some(element) => {
+                let placed = PlacedElement {
+                    element,
+                    start: -element.length() * 0.5,
+                    end: element.length() * 0.5,
+                    value: None,
+                };
+                builder = builder
+                    .add(&shapes::Line(
+                        Vec2::new(-half_edge, 0.0),
+                        Vec2::new(placed.start, 0.0),
+                    ))
+                    .add(&shapes::Line(
+                        Vec2::new(placed.end, 0.0),
+                        Vec2::new(half_edge, 0.0),
+                    ));
+                builder = if element == Element::Switch {
+                    switch_geometry(builder, &placed, 0.0, item.closed)
+                } else {
+                    element_geometry(builder, &placed, 0.0)
+                };
+            }
+            None => {
+                builder = builder.add(&shapes::Line(
+                    Vec2::new(-half_edge, 0.0),
+                    Vec2::new(half_edge, 0.0),
+                ));
+            }
+        }
+        let color = if state.selected == Some(index) {
+            Color::hsla(50.0, 1.0, 0.6, 1.0)
+        } else {
+            Color::WHITE
+        };
+        let (from, to) = (item.from.position(), item.to.position());
+        let direction = to - from;
+        let transform = Transform::from_translation(((from + to) * 0.5).extend(EDITOR_DEPTH))
+            .with_rotation(Quat::from_rotation_z(direction.y.atan2(direction.x)))
+            .with_scale(Vec3::splat(EDITOR_SCALE));
+        commands
+            .spawn_bundle(builder.build(
+                DrawMode::Stroke(StrokeMode::new(color, WIRE_WIDTH)),
+                transform,
+            ))
+            .insert(EditorEntity)
+            .insert(EditorDrawing);
+    }
+
+    if let (Some(Drag::Laying(start)), Some(cursor)) = (&state.drag, state.cursor) {
+        let end = GridNode::nearest(cursor).unwrap_or(*start);
+        let mut points = vec![start.position()];
+        points.extend(grid_path(*start, end).iter().map(|(_, to)| to.position()));
+        points.push(cursor);
+        commands
+            .spawn_bundle(GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points,
+                    closed: false,
+                },
+                DrawMode::Stroke(StrokeMode::new(Color::GRAY, 2.0)),
+                Transform::from_xyz(0.0, 0.0, EDITOR_DEPTH),
+            ))
+            .insert(EditorEntity)
+            .insert(EditorDrawing);
+    }
+}
+
+/// removes the grid and everything drawn on it
+fn despawn_editor(mut commands: Commands, query: Query<Entity, With<EditorEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// The palette, the selected item's value, and the buttons to play or leave
+fn editor_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut editor: ResMut<EditorCircuit>,
+    mut editor_state: ResMut<EditorState>,
+    mut active: ResMut<ActiveLevel>,
+    mut state: ResMut<State<AppState>>,
+) {
+    egui::Window::new("Build")
+        .anchor(Align2::LEFT_TOP, [10.0, 10.0])
+        .fixed_size([200.0, 400.0])
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            let mut tool = editor_state.tool;
+            ui.selectable_value(&mut tool, EditorTool::Wire, "Wire");
+            for element in Element::ALL {
+                ui.selectable_value(&mut tool, EditorTool::Place(element), element.name());
+            }
+            ui.selectable_value(&mut tool, EditorTool::Erase, "Erase");
+            if tool != editor_state.tool {
+                editor_state.tool = tool;
+            }
+            ui.label(
+                RichText::new(
+                    "Drag between dots to lay the tool, drag an item to move it, click an item \
+                     to select it.",
+                )
+                .color(Color32::GRAY),
+            );
+            ui.separator();
+
+            let selected = editor_state
+                .selected
+                .filter(|&index| index < editor.items.len());
+            if let Some(index) = selected {
+                let item = &mut editor.items[index];
+                match item.element {
+                    Some(Element::Switch) => {
+                        ui.checkbox(&mut item.closed, "Switch closed");
+                    }
+                    Some(element) => {
+                        ui.label(element.name());
+                        ui.add(
+                            egui::DragValue::new(&mut item.value)
+                                .speed(0.01)
+                                .clamp_range(match element {
+                                    Element::Source => -100.0..=100.0,
+                                    Element::Resistor | Element::Bulb => 0.0..=100.0,
+                                    _ => 0.01..=100.0,
+                                })
+                                .suffix(format!(" {}", element.unit())),
+                        );
+                    }
+                    None => {
+                        ui.label("Wire");
+                    }
+                }
+                if ui.button("Remove").clicked() {
+                    editor.items.remove(index);
+                    editor_state.selected = None;
+                }
+                ui.separator();
+            }
+
+            if let Some(error) = &editor_state.error {
+                ui.label(RichText::new(error).color(Color32::LIGHT_RED));
+            }
+            ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                if ui.button("Play").clicked() {
+                    match editor.netlist() {
+                        Ok(built) => {
+                            editor_state.error = None;
+                            *active = ActiveLevel::built(&built);
+                            let _ = state.overwrite_replace(AppState::Sandbox);
+                        }
+                        Err(error) => editor_state.error = Some(error),
+                    }
+                }
+                if ui.button("Clear").clicked() {
+                    editor.items.clear();
+                    editor_state.selected = None;
+                }
+                if ui.button("Example").clicked() {
+                    *editor = EditorCircuit::default();
+                    editor_state.selected = None;
+                }
+            });
+            if ui.button("Main menu").clicked() {
+                let _ = state.overwrite_replace(AppState::MainMenu);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an item's element, none for a wire, and the nodes it joins
+    type Placement = (Option<Element>, (i32, i32), (i32, i32));
+
+    /// Places items on the grid, each with its usual value
+    ///
+    /// # Arguments
+    /// * 'items' - where each item goes
+    fn circuit_of(items: &[Placement]) -> EditorCircuit {
+        let node = |(x, y)| GridNode { x, y };
+        EditorCircuit {
+            items: items
+                .iter()
+                .map(|&(element, from, to)| EditorItem::new(element, node(from), node(to)))
+                .collect(),
+        }
+    }
+
+    /// A square loop of an inductor, a capacitor, a source and a wire
+    fn square_loop() -> EditorCircuit {
+        circuit_of(&[
+            (Some(Element::Inductor), (0, 0), (1, 0)),
+            (Some(Element::Capacitor), (1, 0), (1, 1)),
+            (Some(Element::Source), (1, 1), (0, 1)),
+            (None, (0, 1), (0, 0)),
+        ])
+    }
+
+    #[test]
+    fn example_matches_the_sandbox() {
+        let built = EditorCircuit::default().netlist().unwrap();
+        let constants = built.constants;
+        assert_eq!(constants.resistance, 0.2);
+        assert_eq!(constants.inductance, 4.0);
+        assert!((constants.capacitance - 6.0).abs() < 1e-12);
+        //the sources charge the capacitor up to the sandbox's starting charge, CE = 10
+        assert_eq!(constants.startcharge, 0.0);
+        assert!((constants.capacitance * constants.emf - 10.0).abs() < 1e-9);
+        //the closed switch is just wire
+        let elements: Vec<Element> = built.elements.iter().map(|&(element, _)| element).collect();
+        assert_eq!(
+            elements,
+            vec![
+                Element::Source,
+                Element::Bulb,
+                Element::Inductor,
+                Element::Capacitor
+            ]
+        );
+    }
+
+    #[test]
+    fn loose_end_is_refused() {
+        let mut editor = square_loop();
+        editor.items.push(EditorItem::new(
+            None,
+            GridNode { x: 1, y: 1 },
+            GridNode { x: 2, y: 1 },
+        ));
+        assert_eq!(
+            editor.netlist().unwrap_err(),
+            "A wire or element has a loose end."
+        );
+    }
+
+    #[test]
+    fn same_parts_side_by_side_combine() {
+        let mut editor = square_loop();
+        //a second capacitor beside the first
+        editor.items.extend(
+            circuit_of(&[
+                (None, (1, 0), (2, 0)),
+                (Some(Element::Capacitor), (2, 0), (2, 1)),
+                (None, (2, 1), (1, 1)),
+            ])
+            .items,
+        );
+        let built = editor.netlist().unwrap();
+        assert!((built.constants.capacitance - 12.0).abs() < 1e-12);
+        assert_eq!(built.elements.len(), 3);
+        assert!(built
+            .elements
+            .contains(&(Element::Capacitor, Some(built.constants.capacitance))));
+    }
+
+    #[test]
+    fn mixed_parts_side_by_side_are_refused() {
+        let mut editor = square_loop();
+        //a resistor beside the capacitor
+        editor.items.extend(
+            circuit_of(&[
+                (None, (1, 0), (2, 0)),
+                (Some(Element::Resistor), (2, 0), (2, 1)),
+                (None, (2, 1), (1, 1)),
+            ])
+            .items,
+        );
+        assert_eq!(
+            editor.netlist().unwrap_err(),
+            "Only parts of the same kind can sit side by side, like two capacitors in parallel."
+        );
+    }
+
+    #[test]
+    fn open_switch_stops_the_current() {
+        let mut editor = EditorCircuit::default();
+        let switch = editor
+            .items
+            .iter()
+            .position(|item| item.element == Some(Element::Switch))
+            .unwrap();
+        editor.items[switch].closed = false;
+        assert_eq!(
+            editor.netlist().unwrap_err(),
+            "A switch is open, so no current can flow."
+        );
+    }
+
+    #[test]
+    fn series_capacitors_combine() {
+        let built = circuit_of(&[
+            (Some(Element::Inductor), (0, 0), (1, 0)),
+            (Some(Element::Capacitor), (1, 0), (1, 1)),
+            (Some(Element::Capacitor), (1, 1), (0, 1)),
+            (None, (0, 1), (0, 0)),
+        ])
+        .netlist()
+        .unwrap();
+        assert!((built.constants.capacitance - 3.0).abs() < 1e-12);
+        assert_eq!(built.constants.emf, 0.0);
+    }
+
+    #[test]
+    fn turning_the_source_around_flips_the_emf() {
+        let forwards = square_loop().netlist().unwrap();
+        let mut editor = square_loop();
+        let source = &mut editor.items[2];
+        std::mem::swap(&mut source.from, &mut source.to);
+        let backwards = editor.netlist().unwrap();
+
+        let voltage = default_value(Element::Source);
+        assert_eq!(forwards.constants.emf.abs(), voltage);
+        assert_eq!(backwards.constants.emf, -forwards.constants.emf);
+        //the source is labelled with the voltage it has going around the loop
+        assert!(backwards
+            .elements
+            .contains(&(Element::Source, Some(backwards.constants.emf))));
+    }
+}
@@ -4,13 +4,13 @@
 use bevy::{
     core::Time,
     ecs::{
-        event::Events,
+        schedule::ParallelSystemDescriptorCoercion,
         system::{lifetimeless::SRes, SystemParamItem},
     },
-    math::Vec3,
+    math::{Vec3, Vec4},
     prelude::{
-        default, shape, Assets, Bundle, Commands, Component, Mesh, Plugin, Query, Res, ResMut,
-        Transform,
+        default, shape, Assets, BuildChildren, Bundle, Commands, Component, Entity, Handle, Mesh,
+        Parent, Plugin, Query, Res, ResMut, Transform, With, Without,
     },
     reflect::TypeUuid,
     render::{
@@ -25,18 +25,28 @@ use bevy::{
         RenderApp, RenderStage,
     },
     sprite::{Material2d, Material2dPipeline, Material2dPlugin, MaterialMesh2dBundle},
-    window::{WindowResized, Windows},
 };
+use bevy_egui::egui::{Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
 
-use super::DLRCCircuit;
+use super::{
+    average_quantity, curve_combo, not_paused, quantity_combo, simulation_shown, CircuitQuantity,
+    Curve, DLRCCircuit, Schematic,
+};
+use crate::storage::SaveFile;
 
 pub struct EffectsPlugin;
 
 impl Plugin for EffectsPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_plugin(Material2dPlugin::<NoiseMaterial>::default())
-            .add_startup_system(spawn_foreground)
-            .add_system(resize_notificator);
+            .init_resource::<EffectsClock>()
+            .add_system(spawn_effect_regions)
+            .add_system(tick_effects_clock.with_run_criteria(not_paused))
+            .insert_resource(EffectSettings::load())
+            .add_system(effects_frame.with_run_criteria(simulation_shown));
 
         app.sub_app_mut(RenderApp)
             .add_system_to_stage(RenderStage::Extract, extract_info)
@@ -44,58 +54,74 @@ impl Plugin for EffectsPlugin {
     }
 }
 
+/// seconds of animation the effects have played, which stops while the app is paused
+#[derive(Default)]
+pub struct EffectsClock(pub f32);
+
+fn tick_effects_clock(time: Res<Time>, mut clock: ResMut<EffectsClock>) {
+    clock.0 += time.delta_seconds();
+}
+
+/// how far an effect region reaches past its circuit's schematic, in the circuit's space, so it
+/// covers the labels too
+const REGION_MARGIN: f32 = 300.0;
+/// how far in front of its circuit an effect region sits, in the circuit's space
+const REGION_DEPTH: f32 = 20.0;
+
 #[derive(Bundle)]
-pub struct ForegroundBundle {
-    foreground: Foreground,
+pub struct EffectRegionBundle {
+    region: EffectRegion,
     #[bundle]
     mesh2dbundle: MaterialMesh2dBundle<NoiseMaterial>,
 }
 
 #[derive(Component)]
-pub struct Foreground;
+/// A rectangle of noise over one circuit, fed by that circuit alone
+pub struct EffectRegion;
 
-/// makes rectangle to house the noise shader
-fn spawn_foreground(
+#[derive(Component)]
+/// Marks a circuit that has been given its effect region
+pub struct HasEffectRegion;
+
+/// gives every new circuit a rectangle with its own noise material
+fn spawn_effect_regions(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<NoiseMaterial>>,
-    windows: Res<Windows>,
-) {
-    let window = windows.get_primary().unwrap();
-    commands.spawn_bundle(ForegroundBundle {
-        foreground: Foreground,
-        mesh2dbundle: MaterialMesh2dBundle {
-            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-            material: materials.add(NoiseMaterial {
-                time: 0.0,
-                alpha: 0.75,
-            }),
-            transform: Transform::from_scale(Vec3::new(window.width(), window.height(), 25.0))
-                .with_translation(Vec3::new(0.0, 0.0, 10.0)),
-            ..default()
-        },
-    });
-}
-
-/// scales the noise texture with size of the screen
-fn resize_notificator(
-    resize_event: Res<Events<WindowResized>>,
-    mut query: Query<(&Foreground, &mut Transform)>,
+    query_circs: Query<(Entity, &Schematic), Without<HasEffectRegion>>,
 ) {
-    let mut reader = resize_event.get_reader();
-    for (_foreground, mut transform) in query.iter_mut() {
-        for e in reader.iter(&resize_event) {
-            *transform = Transform::from_scale(Vec3::new(e.width, e.height, 25.0))
-                .with_translation(Vec3::new(0.0, 0.0, 10.0));
-        }
+    for (entity, schematic) in query_circs.iter() {
+        let size = Vec3::new(
+            schematic.width + REGION_MARGIN * 2.0,
+            schematic.height + REGION_MARGIN * 2.0,
+            1.0,
+        );
+        commands
+            .entity(entity)
+            .insert(HasEffectRegion)
+            .with_children(|parent| {
+                parent.spawn_bundle(EffectRegionBundle {
+                    region: EffectRegion,
+                    mesh2dbundle: MaterialMesh2dBundle {
+                        mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+                        material: materials.add(NoiseMaterial::default()),
+                        transform: Transform::from_scale(size).with_translation(Vec3::new(
+                            0.0,
+                            0.0,
+                            REGION_DEPTH,
+                        )),
+                        ..default()
+                    },
+                });
+            });
     }
 }
+
 /// A transparent material that should scramble background
-#[derive(TypeUuid, Clone, AsStd140)]
+#[derive(TypeUuid, Clone, Default)]
 #[uuid = "cd3d98e9-bc74-4e0b-9f2e-cd9372bfcdcb"]
 pub struct NoiseMaterial {
-    time: f32,
-    alpha: f32,
+    info: ExtractedInfo,
 }
 
 pub struct NoiseMaterialGPU {
@@ -121,7 +147,7 @@ impl Material2d for NoiseMaterial {
                 ty: BindingType::Buffer {
                     ty: BufferBindingType::Uniform,
                     has_dynamic_offset: false,
-                    min_binding_size: BufferSize::new(NoiseMaterial::std140_size_static() as u64),
+                    min_binding_size: BufferSize::new(ExtractedInfo::std140_size_static() as u64),
                 },
                 count: None,
             }],
@@ -152,7 +178,7 @@ impl RenderAsset for NoiseMaterial {
         let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
             label: Some("Time Buffer"),
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            contents: extracted_asset.as_std140().as_bytes(),
+            contents: extracted_asset.info.as_std140().as_bytes(),
         });
 
         let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
@@ -168,29 +194,339 @@ impl RenderAsset for NoiseMaterial {
     }
 }
 
-#[derive(AsStd140)]
+/// the uniforms of the noise shader, laid out the same as `Info` in random.wgsl
+#[derive(AsStd140, Clone, Default)]
 struct ExtractedInfo {
+    tint_color: Vec4,
     seconds_since_startup: f32,
-    power: f32,
+    opacity: f32,
+    grain_size: f32,
+    speed: f32,
+    tint: f32,
+    vignette: f32,
+    scanlines: f32,
+    chromatic_offset: f32,
 }
 
-fn extract_info(mut commands: Commands, time: Res<Time>, query_circs: Query<&DLRCCircuit>) {
-    let total_power = query_circs
+/// the uniforms of every effect region, found from each region's own circuit
+struct ExtractedRegions(Vec<(Handle<NoiseMaterial>, ExtractedInfo)>);
+
+fn extract_info(
+    mut commands: Commands,
+    clock: Res<EffectsClock>,
+    settings: Res<EffectSettings>,
+    query_regions: Query<(&Parent, &Handle<NoiseMaterial>), With<EffectRegion>>,
+    query_circs: Query<&DLRCCircuit>,
+) {
+    let regions = query_regions
         .iter()
-        .map(|circuit| circuit.0.lightbulb_power())
-        .sum::<f64>() as f32;
-    commands.insert_resource(ExtractedInfo {
-        seconds_since_startup: time.seconds_since_startup() as f32,
-        power: total_power,
-    });
+        .filter_map(|(parent, material)| {
+            let circuit = query_circs.get(parent.0).ok()?;
+            Some((
+                material.clone_weak(),
+                region_info(&settings, circuit, clock.0),
+            ))
+        })
+        .collect();
+    commands.insert_resource(ExtractedRegions(regions));
+}
+
+/// works out the uniforms of a region from its circuit
+fn region_info(settings: &EffectSettings, circuit: &DLRCCircuit, time: f32) -> ExtractedInfo {
+    let value = |uniform| settings.get(uniform).evaluate(&[circuit]);
+    let [red, green, blue] = settings.tint_color;
+    ExtractedInfo {
+        tint_color: Vec4::new(red, green, blue, 1.0),
+        seconds_since_startup: time,
+        opacity: value(EffectUniform::Opacity),
+        grain_size: value(EffectUniform::GrainSize),
+        speed: value(EffectUniform::Speed),
+        tint: value(EffectUniform::Tint),
+        vignette: value(EffectUniform::Vignette),
+        scanlines: value(EffectUniform::Scanlines),
+        chromatic_offset: value(EffectUniform::ChromaticOffset),
+    }
 }
 
 fn prepare_material(
-    extracted_info: Res<ExtractedInfo>,
-    mut materials: ResMut<RenderAssets<NoiseMaterial>>,
+    extracted_regions: Res<ExtractedRegions>,
+    materials: Res<RenderAssets<NoiseMaterial>>,
     render_queue: Res<RenderQueue>,
 ) {
-    for material in materials.values_mut() {
-        render_queue.write_buffer(&material.buffer, 0, extracted_info.as_std140().as_bytes());
+    for (handle, info) in extracted_regions.0.iter() {
+        //a new material isn't prepared until the frame after it's extracted
+        if let Some(material) = materials.get(handle) {
+            render_queue.write_buffer(&material.buffer, 0, info.as_std140().as_bytes());
+        }
+    }
+}
+
+/* EFFECT SETTINGS */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A value of the noise shader that can be set or driven by the circuit
+pub enum EffectUniform {
+    /// how much the noise covers the screen
+    Opacity,
+    /// the size of a grain of noise, in pixels
+    GrainSize,
+    /// how fast the noise changes, where 1 is every frame
+    Speed,
+    /// how much the noise takes the tint color
+    Tint,
+    /// how much the edges of the screen darken
+    Vignette,
+    /// how dark the scanlines are
+    Scanlines,
+    /// how far the red and blue of the noise are pulled apart, in pixels
+    ChromaticOffset,
+}
+
+impl EffectUniform {
+    pub const ALL: [EffectUniform; 7] = [
+        EffectUniform::Opacity,
+        EffectUniform::GrainSize,
+        EffectUniform::Speed,
+        EffectUniform::Tint,
+        EffectUniform::Vignette,
+        EffectUniform::Scanlines,
+        EffectUniform::ChromaticOffset,
+    ];
+
+    /// The name of the uniform, as shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectUniform::Opacity => "Opacity",
+            EffectUniform::GrainSize => "Grain size",
+            EffectUniform::Speed => "Speed",
+            EffectUniform::Tint => "Tint",
+            EffectUniform::Vignette => "Vignette",
+            EffectUniform::Scanlines => "Scanlines",
+            EffectUniform::ChromaticOffset => "Chromatic offset",
+        }
+    }
+
+    /// The values the uniform can be set to
+    pub fn range(&self) -> RangeInclusive<f32> {
+        match self {
+            EffectUniform::GrainSize => 1.0..=32.0,
+            EffectUniform::Speed => 0.0..=2.0,
+            EffectUniform::ChromaticOffset => 0.0..=16.0,
+            _ => 0.0..=1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Binds a circuit quantity to a uniform of the noise shader
+pub struct EffectBinding {
+    pub quantity: CircuitQuantity,
+    pub curve: Curve,
+    /// the quantity's values mapped to the start and end of the output, clamped outside of them
+    pub input: (f64, f64),
+    /// the uniform's values at the start and end of the input, which can be reversed
+    pub output: (f32, f32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The value of one uniform, which is either fixed or bound to the circuit
+pub struct UniformSetting {
+    /// the value used while the uniform isn't bound
+    pub value: f32,
+    pub binding: Option<EffectBinding>,
+}
+
+impl UniformSetting {
+    /// A uniform fixed at a value
+    fn fixed(value: f32) -> Self {
+        Self {
+            value,
+            binding: None,
+        }
+    }
+
+    /// Works out the uniform's value
+    ///
+    /// # Arguments
+    /// * 'circuits' - the circuits a binding measures, averaged if there are several
+    ///
+    /// # Returns
+    /// The fixed value, or the bound quantity mapped onto the uniform
+    pub fn evaluate(&self, circuits: &[&DLRCCircuit]) -> f32 {
+        match &self.binding {
+            Some(binding) => binding.curve.map(
+                average_quantity(binding.quantity, circuits),
+                binding.input,
+                binding.output,
+            ),
+            None => self.value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+/// How the noise over the screen looks, saved between runs
+pub struct EffectSettings {
+    /// the color the noise is tinted towards, in linear rgb
+    pub tint_color: [f32; 3],
+    pub opacity: UniformSetting,
+    pub grain_size: UniformSetting,
+    pub speed: UniformSetting,
+    pub tint: UniformSetting,
+    pub vignette: UniformSetting,
+    pub scanlines: UniformSetting,
+    pub chromatic_offset: UniformSetting,
+}
+
+impl Default for EffectSettings {
+    fn default() -> Self {
+        Self {
+            tint_color: [0.2, 0.6, 1.0],
+            //the noise clears up as the lightbulb brightens, gone at 0.15 W
+            opacity: UniformSetting {
+                value: 0.6,
+                binding: Some(EffectBinding {
+                    quantity: CircuitQuantity::Power,
+                    curve: Curve::Linear,
+                    input: (0.0, 0.6 * 255.0 / 1024.0),
+                    output: (0.6, 0.0),
+                }),
+            },
+            grain_size: UniformSetting::fixed(1.0),
+            speed: UniformSetting::fixed(1.0),
+            tint: UniformSetting::fixed(0.0),
+            vignette: UniformSetting::fixed(0.0),
+            scanlines: UniformSetting::fixed(0.0),
+            chromatic_offset: UniformSetting::fixed(0.0),
+        }
+    }
+}
+
+impl SaveFile for EffectSettings {
+    const FILE: &'static str = "effects.ron";
+    const NAME: &'static str = "effect settings";
+}
+
+impl EffectSettings {
+    pub fn get(&self, uniform: EffectUniform) -> &UniformSetting {
+        match uniform {
+            EffectUniform::Opacity => &self.opacity,
+            EffectUniform::GrainSize => &self.grain_size,
+            EffectUniform::Speed => &self.speed,
+            EffectUniform::Tint => &self.tint,
+            EffectUniform::Vignette => &self.vignette,
+            EffectUniform::Scanlines => &self.scanlines,
+            EffectUniform::ChromaticOffset => &self.chromatic_offset,
+        }
+    }
+
+    fn get_mut(&mut self, uniform: EffectUniform) -> &mut UniformSetting {
+        match uniform {
+            EffectUniform::Opacity => &mut self.opacity,
+            EffectUniform::GrainSize => &mut self.grain_size,
+            EffectUniform::Speed => &mut self.speed,
+            EffectUniform::Tint => &mut self.tint,
+            EffectUniform::Vignette => &mut self.vignette,
+            EffectUniform::Scanlines => &mut self.scanlines,
+            EffectUniform::ChromaticOffset => &mut self.chromatic_offset,
+        }
+    }
+}
+
+/// Lets the player set every uniform of the noise, or bind it to the circuit
+fn effects_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut settings: ResMut<EffectSettings>,
+    query_circs: Query<&DLRCCircuit>,
+) {
+    let circuits: Vec<&DLRCCircuit> = query_circs.iter().collect();
+    egui::Window::new("Effects")
+        .title_bar(false)
+        .default_pos([800.0, 560.0])
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::CollapsingHeader::new("Effects")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        ui.label("Tint color");
+                        ui.color_edit_button_rgb(&mut settings.tint_color);
+                    });
+                    ui.separator();
+
+                    for uniform in EffectUniform::ALL {
+                        let setting = settings.get_mut(uniform);
+                        ui.push_id(uniform.name(), |ui| uniform_row(ui, uniform, setting));
+                        //each circuit's region has its own value
+                        let values: Vec<String> = circuits
+                            .iter()
+                            .map(|circuit| format!("{:.3}", setting.evaluate(&[circuit])))
+                            .collect();
+                        ui.label(
+                            RichText::new(format!("now {}", values.join(", ")))
+                                .color(Color32::GRAY),
+                        );
+                        ui.separator();
+                    }
+
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        if ui.button("Defaults").clicked() {
+                            *settings = EffectSettings::default();
+                        }
+                        if ui.button("Save").clicked() {
+                            settings.save();
+                        }
+                    });
+                });
+        });
+}
+
+/// Adds the controls for one uniform
+///
+/// # Arguments
+/// * 'ui' - the ui to add the controls to
+/// * 'uniform' - which uniform is being edited
+/// * 'setting' - the uniform's setting
+fn uniform_row(ui: &mut egui::Ui, uniform: EffectUniform, setting: &mut UniformSetting) {
+    let range = uniform.range();
+    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+        let mut bound = setting.binding.is_some();
+        ui.label(uniform.name());
+        if ui.checkbox(&mut bound, "follows").changed() {
+            setting.binding = bound.then(|| EffectBinding {
+                quantity: CircuitQuantity::Power,
+                curve: Curve::Linear,
+                input: (0.0, 0.15),
+                output: (*range.start(), *range.end()),
+            });
+        }
+        match &mut setting.binding {
+            Some(binding) => {
+                quantity_combo(ui, &mut binding.quantity);
+                curve_combo(ui, &mut binding.curve);
+            }
+            None => {
+                ui.add(egui::Slider::new(&mut setting.value, range.clone()));
+            }
+        }
+    });
+    if let Some(binding) = &mut setting.binding {
+        ui.with_layout(egui::Layout::left_to_right(), |ui| {
+            ui.label("from");
+            ui.add(egui::DragValue::new(&mut binding.input.0).speed(0.01));
+            ui.label("to");
+            ui.add(egui::DragValue::new(&mut binding.input.1).speed(0.01));
+            ui.label("gives");
+            ui.add(
+                egui::DragValue::new(&mut binding.output.0)
+                    .speed(0.01)
+                    .clamp_range(range.clone()),
+            );
+            ui.label("to");
+            ui.add(
+                egui::DragValue::new(&mut binding.output.1)
+                    .speed(0.01)
+                    .clamp_range(range),
+            );
+        });
     }
 }
@@ -0,0 +1,444 @@
+//! rendering a run of the circuit, with its noise, music, tone and pulse notes, to a WAV file
+//! without opening a window
+
+use bevy::{asset::FileAssetIo, audio::Decodable};
+use rodio::Source;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::graphics::{
+    evaluate_mappings, is_current_peak, largest_current, noise_fade_in, pulse_note, tone_frequency,
+    tone_loudness, ActiveLevel, AudioSettings, Channel, CircuitConstants, CircuitTimer,
+    CircuitTone, DLRCCircuit, LastCurrentRateSignum, ProceduralNoise, SpawnedThisSignum,
+    ToneParameters, DELTA_T, MIN_CIRCUIT_TIME, SAMPLE_RATE,
+};
+
+/// the frames simulated for every second of audio, as if the app ran at 60 fps
+const FRAME_RATE: f64 = 60.0;
+/// the longest render allowed, ten minutes, which keeps the samples to about 100 MB in memory
+const MAX_SECONDS: f64 = 600.0;
+
+const USAGE: &str = "usage: syncing_circuits --render-wav <file> [--seconds <seconds, up to 600>] \
+                     [--circuit <Q0>,<R>,<L>,<C>[,<I0>]]";
+
+/// What to render, read from the command line
+pub struct RenderOptions {
+    pub path: PathBuf,
+    /// the length of the audio, in real seconds
+    pub seconds: f64,
+    pub circuit: CircuitConstants,
+}
+
+impl RenderOptions {
+    /// Reads the render options from the command line arguments
+    ///
+    /// # Arguments
+    /// * 'args' - the arguments, without the program name
+    ///
+    /// # Returns
+    /// The options, none if no render was asked for, or the usage if the arguments are wrong
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut path = None;
+        let mut seconds = 20.0;
+        let mut circuit = ActiveLevel::sandbox().level.start;
+        let args: Vec<String> = args.collect();
+        if !args.iter().any(|arg| arg == "--render-wav") {
+            return Ok(None);
+        }
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| USAGE.to_string());
+            match arg.as_str() {
+                "--render-wav" => path = Some(PathBuf::from(value()?)),
+                "--seconds" => {
+                    seconds = value()?
+                        .parse()
+                        .ok()
+                        .filter(|&seconds: &f64| {
+                            seconds.is_finite() && seconds > 0.0 && seconds <= MAX_SECONDS
+                        })
+                        .ok_or_else(|| USAGE.to_string())?;
+                }
+                "--circuit" => {
+                    let constants: Vec<f64> = value()?
+                        .split(',')
+                        .map(|constant| constant.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| USAGE.to_string())?;
+                    //the starting current can be left off, for a circuit starting at rest
+                    let (constants, startcurrent) = match constants[..] {
+                        [ref constants @ .., startcurrent] if constants.len() == 4 => {
+                            (constants, startcurrent)
+                        }
+                        ref constants => (constants, 0.0),
+                    };
+                    match *constants {
+                        [startcharge, resistance, inductance, capacitance]
+                            if inductance > 0.0 && capacitance > 0.0 =>
+                        {
+                            circuit = CircuitConstants {
+                                startcharge,
+                                startcurrent,
+                                resistance,
+                                inductance,
+                                capacitance,
+                                emf: 0.0,
+                            };
+                        }
+                        _ => return Err(USAGE.to_string()),
+                    }
+                }
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        match path {
+            Some(path) => Ok(Some(Self {
+                path,
+                seconds,
+                circuit,
+            })),
+            None => Err(USAGE.to_string()),
+        }
+    }
+}
+
+/// A decoded sound, mixed down to one channel
+struct Sample {
+    data: Vec<f32>,
+    sample_rate: f64,
+}
+
+impl Sample {
+    /// Decodes a sound from the assets folder, or from an absolute path
+    ///
+    /// # Returns
+    /// The sound, or none if it's missing or can't be decoded
+    fn load(path: &str) -> Option<Self> {
+        let full_path = FileAssetIo::get_root_path().join("assets").join(path);
+        let file = fs::File::open(full_path).ok()?;
+        let decoder = rodio::Decoder::new(BufReader::new(file)).ok()?;
+        let channels = decoder.channels().max(1) as usize;
+        let sample_rate = decoder.sample_rate() as f64;
+        let interleaved: Vec<f32> = decoder.map(|sample| sample as f32 / 32768.0).collect();
+        let data: Vec<f32> = interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect();
+        (!data.is_empty()).then_some(Self { data, sample_rate })
+    }
+
+    /// Reads the sound between its samples
+    ///
+    /// # Arguments
+    /// * 'position' - the position in samples, which can fall between two of them
+    ///
+    /// # Returns
+    /// The sound at the position, or silence past its end
+    fn at(&self, position: f64) -> f32 {
+        let index = position as usize;
+        let fraction = (position - index as f64) as f32;
+        let here = self.data.get(index).copied().unwrap_or(0.0);
+        let next = self.data.get(index + 1).copied().unwrap_or(0.0);
+        here + (next - here) * fraction
+    }
+
+    /// Reads the sound as if it looped forever
+    fn looped(&self, position: f64) -> f32 {
+        self.at(position % self.data.len() as f64)
+    }
+}
+
+/// A pulse note ringing in the render
+struct Voice {
+    position: f64,
+    /// how far through the sample the note moves every output sample
+    step: f64,
+    volume: f32,
+}
+
+/// Renders a run of a circuit into audio samples, the way the app would play it
+///
+/// The noise, music and pulse note are read from the files in the settings, falling back to
+/// procedural noise and silence the same way the app does. The same options and settings always
+/// give the same samples.
+///
+/// # Arguments
+/// * 'options' - the circuit and how long to render it for
+/// * 'settings' - the audio settings, including the mixer and mappings
+///
+/// # Returns
+/// Mono samples at `SAMPLE_RATE`
+pub fn render_samples(options: &RenderOptions, settings: &AudioSettings) -> Vec<f32> {
+    let noise_file = Sample::load(&settings.noise_path);
+    let music = Sample::load(&settings.music_path);
+    let pulse = Sample::load("taishi-piano-note.ogg");
+    let mut procedural_noise = ProceduralNoise.decoder();
+
+    let tone = Arc::new(ToneParameters::default());
+    let mut tone_decoder = CircuitTone {
+        parameters: tone.clone(),
+    }
+    .decoder();
+
+    let mut circuit = DLRCCircuit(options.circuit.light_calculator());
+    let largest_current = largest_current(std::iter::once(&circuit));
+    let output_rate = SAMPLE_RATE as f64;
+    let frames = (options.seconds * FRAME_RATE).ceil() as usize;
+    let mut samples = Vec::with_capacity((options.seconds * output_rate) as usize + 1);
+    let (mut noise_position, mut music_position) = (0.0, 0.0);
+    let mut voices: VecDeque<Voice> = VecDeque::new();
+    //the usual timer, ending with the render
+    let mut timer = CircuitTimer {
+        end: MIN_CIRCUIT_TIME + frames as f64 * DELTA_T,
+        ..CircuitTimer::default()
+    };
+    let mut sts = SpawnedThisSignum(0.0, false);
+
+    for frame in 0..frames {
+        //step the circuit and find its peaks the way update_time and detect_current_peaks do
+        let lcrs = LastCurrentRateSignum(circuit.0.circuit.current_rate().signum());
+        circuit.0.circuit.tick(timer.delta_t);
+        timer.time += timer.delta_t;
+        let current = circuit.0.circuit.current();
+        sts.follow(current);
+
+        let parameters = evaluate_mappings(&settings.mappings, &[&circuit]);
+        if is_current_peak(&timer, &circuit.0.circuit, &sts, &lcrs) {
+            sts.1 = true;
+            let note = pulse_note(current, largest_current, settings, parameters.pulse_pitch);
+            let gain = settings.mixer.gain(Channel::Effects);
+            if let Some(pulse) = &pulse {
+                let mut play = |speed: f32, volume: f32| {
+                    while voices.len() >= settings.pulse_polyphony.max(1) {
+                        voices.pop_front();
+                    }
+                    voices.push_back(Voice {
+                        position: 0.0,
+                        step: speed as f64 * pulse.sample_rate / output_rate,
+                        volume: volume * gain,
+                    });
+                };
+                play(note.speed, note.volume);
+                if note.doubled {
+                    play(note.speed * 0.5, note.volume * 0.5);
+                }
+            }
+        }
+
+        let frequency =
+            tone_frequency(&circuit, settings.tone_time_scale).unwrap_or_else(|| tone.frequency());
+        tone.set(
+            tone_loudness(&circuit, largest_current, settings),
+            frequency,
+        );
+
+        let noise_gain = parameters.noise_volume
+            * noise_fade_in((frame as f64 / FRAME_RATE) as f32)
+            * settings.mixer.gain(Channel::Noise);
+        let music_gain = parameters.music_volume * settings.mixer.gain(Channel::Music);
+        let frame_end = ((frame + 1) as f64 / FRAME_RATE * output_rate).round() as usize;
+        while samples.len() < frame_end {
+            let noise = match &noise_file {
+                Some(noise_file) => {
+                    noise_position += noise_file.sample_rate / output_rate;
+                    noise_file.looped(noise_position)
+                }
+                None => procedural_noise.next().unwrap_or(0.0),
+            };
+            let mut mixed = noise * noise_gain + tone_decoder.next().unwrap_or(0.0);
+            if let Some(music) = &music {
+                mixed += music.looped(music_position) * music_gain;
+                music_position +=
+                    parameters.playback_speed as f64 * music.sample_rate / output_rate;
+            }
+            if let Some(pulse) = &pulse {
+                for voice in voices.iter_mut() {
+                    mixed += pulse.at(voice.position) * voice.volume;
+                    voice.position += voice.step;
+                }
+                voices.retain(|voice| voice.position < pulse.data.len() as f64);
+            }
+            samples.push(mixed.clamp(-1.0, 1.0));
+        }
+    }
+    samples
+}
+
+/// Writes mono samples to a 16 bit WAV file
+///
+/// # Arguments
+/// * 'path' - where to write the file
+/// * 'samples' - the samples at `SAMPLE_RATE`, from -1 to 1
+///
+/// # Returns
+/// An error if writing fails, or if there are too many samples for a WAV file to hold
+pub fn write_wav(path: &Path, samples: &[f32]) -> std::io::Result<()> {
+    //the RIFF chunk's length has to fit in 32 bits along with the 36 bytes of header after it
+    let data_length = samples
+        .len()
+        .checked_mul(2)
+        .and_then(|length| u32::try_from(length).ok())
+        .filter(|length| length.checked_add(36).is_some())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the render is too long for a WAV file",
+            )
+        })?;
+    let mut bytes = Vec::with_capacity(44 + samples.len() * 2);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_length).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    //the format chunk: 16 bytes of uncompressed mono audio, 2 bytes per sample
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_length.to_le_bytes());
+    for sample in samples {
+        bytes
+            .extend_from_slice(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    fs::write(path, bytes)
+}
+
+/// Renders a run of a circuit to a WAV file
+///
+/// # Arguments
+/// * 'options' - the circuit, how long to render it for and where to write it
+/// * 'settings' - the audio settings, including the mixer and mappings
+pub fn render_wav(options: &RenderOptions, settings: &AudioSettings) -> Result<(), Box<dyn Error>> {
+    let samples = render_samples(options, settings);
+    write_wav(&options.path, &samples)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads render options from arguments written like a command line
+    fn parse(args: &str) -> Result<Option<RenderOptions>, String> {
+        RenderOptions::from_args(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn no_render_without_the_flag() {
+        assert!(matches!(parse(""), Ok(None)));
+        assert!(matches!(parse("--seconds 5"), Ok(None)));
+    }
+
+    #[test]
+    fn options_are_read() {
+        let options = parse("--render-wav out.wav").unwrap().unwrap();
+        assert_eq!(options.path, PathBuf::from("out.wav"));
+        assert_eq!(options.seconds, 20.0);
+        assert_eq!(
+            options.circuit.startcharge,
+            ActiveLevel::sandbox().level.start.startcharge
+        );
+
+        let options = parse("--seconds 600 --circuit 1,0.5,2,3 --render-wav out.wav")
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.seconds, 600.0);
+        let circuit = options.circuit;
+        assert_eq!(
+            (
+                circuit.startcharge,
+                circuit.resistance,
+                circuit.inductance,
+                circuit.capacitance,
+                circuit.startcurrent
+            ),
+            (1.0, 0.5, 2.0, 3.0, 0.0)
+        );
+
+        let options = parse("--render-wav out.wav --circuit 0,0.2,4,6,-2")
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.circuit.startcurrent, -2.0);
+    }
+
+    #[test]
+    fn bad_seconds_are_refused() {
+        for seconds in ["0", "-1", "600.5", "NaN", "inf", "ten"] {
+            let args = format!("--render-wav out.wav --seconds {}", seconds);
+            assert_eq!(parse(&args).err(), Some(USAGE.to_string()), "{}", seconds);
+        }
+        assert!(parse("--render-wav out.wav --seconds").is_err());
+    }
+
+    #[test]
+    fn bad_circuits_are_refused() {
+        for circuit in [
+            "1,0.2,4",
+            "1,0.2,4,6,0,7",
+            "1,0.2,0,6",
+            "1,0.2,4,-6",
+            "1,0.2,four,6",
+            "",
+        ] {
+            let args = format!("--render-wav out.wav --circuit {}", circuit);
+            assert_eq!(parse(&args).err(), Some(USAGE.to_string()), "{}", circuit);
+        }
+        assert!(parse("--render-wav").is_err());
+        assert!(parse("--render-wav out.wav --loud").is_err());
+    }
+
+    #[test]
+    fn wav_header_and_samples() {
+        let path =
+            std::env::temp_dir().join(format!("syncing-circuits-{}.wav", std::process::id()));
+        let samples = [0.0, 1.0, -1.0, 2.0, 0.5];
+        write_wav(&path, &samples).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let u16_at = |at: usize| u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap());
+        assert_eq!(bytes.len(), 44 + 2 * samples.len());
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(4) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(16), 16);
+        //uncompressed, mono, 16 bits
+        assert_eq!((u16_at(20), u16_at(22)), (1, 1));
+        assert_eq!(u32_at(24), SAMPLE_RATE);
+        assert_eq!(u32_at(28), SAMPLE_RATE * 2);
+        assert_eq!((u16_at(32), u16_at(34)), (2, 16));
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(40) as usize, 2 * samples.len());
+
+        let written: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        //samples past full scale are clipped
+        assert_eq!(
+            written,
+            vec![0, i16::MAX, -i16::MAX, i16::MAX, i16::MAX / 2]
+        );
+    }
+
+    #[test]
+    fn renders_are_the_same_every_time() {
+        let options = RenderOptions {
+            path: PathBuf::new(),
+            seconds: 2.0,
+            circuit: ActiveLevel::sandbox().level.start,
+        };
+        let settings = AudioSettings::default();
+        let first = render_samples(&options, &settings);
+        assert_eq!(first.len(), 2 * SAMPLE_RATE as usize);
+        assert_eq!(first, render_samples(&options, &settings));
+    }
+}
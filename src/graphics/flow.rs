@@ -0,0 +1,259 @@
+//! showing what happens inside a circuit: dots of charge flowing along the wires, the charge
+//! filling the capacitor plates and the magnetic field around the inductor
+
+use bevy::prelude::*;
+use bevy_prototype_lyon::{
+    entity::ShapeBundle,
+    prelude::{DrawMode, FillMode, GeometryBuilder, StrokeMode},
+    shapes,
+};
+
+use crate::graphics::{
+    capacitor_plates, largest_current, simulation_running, CircuitTimer, DLRCCircuit, Element,
+    Schematic, PLATE_HALF_HEIGHT, PLOT_CURRENT_RANGE,
+};
+
+/// This plugin gives every circuit flowing charge, a capacitor fill and an inductor field, all
+/// following the circuit's state.
+pub struct FlowPlugin;
+
+impl Plugin for FlowPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_flow_indicators)
+            .add_system(move_flow_dots.with_run_criteria(simulation_running))
+            .add_system(update_capacitor_fill.with_run_criteria(simulation_running))
+            .add_system(update_field_lines.with_run_criteria(simulation_running));
+    }
+}
+
+/* FLOW DOTS */
+/// the space between two dots along the wire
+const DOT_SPACING: f32 = 80.0;
+/// how far the dots move every frame for each amp of current, at the usual plot range
+const FLOW_SPEED: f32 = 8.0;
+/// the furthest the dots can move in a frame, so fast currents don't look like they go backwards
+const MAX_FLOW_STEP: f32 = DOT_SPACING * 0.4;
+/// how far in front of the circuit sprite the indicators sit
+const INDICATOR_DEPTH: f32 = 5.0;
+
+#[derive(Bundle)]
+/// A dot of charge flowing along a circuit's wire
+pub struct FlowDotBundle {
+    pub dot: FlowDot,
+    #[bundle]
+    pub shape_bundle: ShapeBundle,
+}
+
+#[derive(Component)]
+/// How far along the wire a dot is, going clockwise from the top left corner
+pub struct FlowDot(pub f32);
+
+#[derive(Component)]
+/// Marks a circuit that has been given its flow dots, capacitor fill and field lines
+pub struct HasFlowIndicators;
+
+/// gives every new circuit its dots, capacitor fill and field lines, placed from its schematic
+fn spawn_flow_indicators(
+    mut commands: Commands,
+    query_circs: Query<(Entity, &Schematic), Without<HasFlowIndicators>>,
+) {
+    for (entity, schematic) in query_circs.iter() {
+        commands
+            .entity(entity)
+            .insert(HasFlowIndicators)
+            .with_children(|parent| {
+                let dots = (schematic.wire_length() / DOT_SPACING) as usize;
+                for index in 0..dots {
+                    let distance = index as f32 * DOT_SPACING;
+                    parent.spawn_bundle(FlowDotBundle {
+                        dot: FlowDot(distance),
+                        shape_bundle: GeometryBuilder::build_as(
+                            &shapes::Circle {
+                                radius: 10.0,
+                                ..shapes::Circle::default()
+                            },
+                            DrawMode::Fill(FillMode::color(Color::hsla(50.0, 1.0, 0.6, 1.0))),
+                            Transform::from_translation(
+                                schematic.wire_point(distance).extend(INDICATOR_DEPTH),
+                            ),
+                        ),
+                    });
+                }
+
+                for capacitor in schematic
+                    .elements
+                    .iter()
+                    .filter(|p| p.element == Element::Capacitor)
+                {
+                    let (left_plate, right_plate) = capacitor_plates(capacitor);
+                    for (plate, x) in [
+                        (Plate::Left, left_plate - FILL_OFFSET),
+                        (Plate::Right, right_plate + FILL_OFFSET),
+                    ] {
+                        parent.spawn_bundle(CapacitorFillBundle {
+                            plate,
+                            shape_bundle: GeometryBuilder::build_as(
+                                &shapes::Rectangle {
+                                    extents: Vec2::new(16.0, PLATE_HALF_HEIGHT * 2.0),
+                                    origin: shapes::RectangleOrigin::Center,
+                                },
+                                DrawMode::Fill(FillMode::color(Color::NONE)),
+                                Transform::from_xyz(x, schematic.top(), INDICATOR_DEPTH)
+                                    .with_scale(Vec3::new(1.0, 0.0, 1.0)),
+                            ),
+                        });
+                    }
+                }
+
+                for inductor in schematic
+                    .elements
+                    .iter()
+                    .filter(|p| p.element == Element::Inductor)
+                {
+                    //the coil rises above the wire, so the field is centered a little higher
+                    let center = Vec3::new(inductor.center(), schematic.top() + 35.0, 0.0);
+                    let half_length = (inductor.end - inductor.start) * 0.5;
+                    for index in 0..FIELD_LINES {
+                        let growth = index as f32;
+                        parent.spawn_bundle(FieldLineBundle {
+                            line: FieldLine(index),
+                            shape_bundle: GeometryBuilder::build_as(
+                                &shapes::Ellipse {
+                                    radii: Vec2::new(
+                                        half_length + 20.0 + 40.0 * growth,
+                                        60.0 + 25.0 * growth,
+                                    ),
+                                    center: Vec2::ZERO,
+                                },
+                                DrawMode::Stroke(StrokeMode::new(Color::NONE, 4.0)),
+                                Transform::from_translation(center + Vec3::Z * INDICATOR_DEPTH),
+                            ),
+                        });
+                    }
+                }
+            });
+    }
+}
+
+/// Moves the dots along the wire with the current, hiding the ones inside components
+///
+/// Positive current flows counterclockwise, the same way the current arrow turns.
+fn move_flow_dots(
+    timer: Res<CircuitTimer>,
+    query_circs: Query<(&DLRCCircuit, &Schematic)>,
+    mut query_dots: Query<(&Parent, &mut FlowDot, &mut Transform, &mut Visibility)>,
+) {
+    for (parent, mut dot, mut transform, mut visibility) in query_dots.iter_mut() {
+        let (circuit, schematic) = match query_circs.get(parent.0) {
+            Ok(circuit) => circuit,
+            Err(_) => continue,
+        };
+        //measured against the plot's range, so rescaled circuits with tiny currents still flow
+        let current =
+            (circuit.0.circuit.current() * PLOT_CURRENT_RANGE / timer.current_range) as f32;
+        let step = (-current * FLOW_SPEED).clamp(-MAX_FLOW_STEP, MAX_FLOW_STEP);
+        dot.0 = (dot.0 + step).rem_euclid(schematic.wire_length());
+
+        let point = schematic.wire_point(dot.0);
+        transform.translation = point.extend(INDICATOR_DEPTH);
+        visibility.is_visible = !schematic.in_element(point);
+    }
+}
+
+/* CAPACITOR FILL */
+/// how far outside its plate a fill bar sits
+const FILL_OFFSET: f32 = 25.0;
+
+#[derive(Component, Clone, Copy)]
+/// Which capacitor plate a fill bar sits beside
+pub enum Plate {
+    /// the plate the circuit's charge is measured on
+    Left,
+    /// the plate holding the opposite charge
+    Right,
+}
+
+#[derive(Bundle)]
+/// A bar beside a capacitor plate showing the charge it holds
+pub struct CapacitorFillBundle {
+    pub plate: Plate,
+    #[bundle]
+    pub shape_bundle: ShapeBundle,
+}
+
+/// Fills the bars beside the capacitor plates with the charge on them, red for positive and blue
+/// for negative
+fn update_capacitor_fill(
+    query_circs: Query<&DLRCCircuit>,
+    mut query_plates: Query<(&Parent, &Plate, &mut Transform, &mut DrawMode)>,
+) {
+    //compared to the largest charge any circuit starts with, so an uncharged circuit still fills
+    let largest_charge = query_circs
+        .iter()
+        .map(|circuit| {
+            //the charge swings around CE by the peak current over w0
+            let rlc = &circuit.0.circuit;
+            (rlc.capacitance * rlc.emf).abs() + rlc.peak_current() / rlc.natural_angular_frequency()
+        })
+        .fold(0.0, f64::max);
+    for (parent, plate, mut transform, mut draw_mode) in query_plates.iter_mut() {
+        let circuit = match query_circs.get(parent.0) {
+            Ok(circuit) => circuit,
+            Err(_) => continue,
+        };
+        let charge = match plate {
+            Plate::Left => circuit.0.circuit.charge(),
+            Plate::Right => -circuit.0.circuit.charge(),
+        };
+        let fill = if largest_charge > 0.0 {
+            (charge.abs() / largest_charge).min(1.0) as f32
+        } else {
+            0.0
+        };
+        transform.scale.y = fill;
+        let hue = if charge >= 0.0 { 0.0 } else { 220.0 };
+        *draw_mode = DrawMode::Fill(FillMode::color(Color::hsla(hue, 0.8, 0.55, 0.9)));
+    }
+}
+
+/* INDUCTOR FIELD */
+/// how many field lines surround the inductor
+const FIELD_LINES: usize = 3;
+#[derive(Component)]
+/// One of the field lines around the inductor, numbered from the inside out
+pub struct FieldLine(pub usize);
+
+#[derive(Bundle)]
+/// A loop around the inductor showing the strength of its magnetic field
+pub struct FieldLineBundle {
+    pub line: FieldLine,
+    #[bundle]
+    pub shape_bundle: ShapeBundle,
+}
+
+/// Grows and brightens the field lines with the current through the inductor, cyan for positive
+/// current and orange for negative
+fn update_field_lines(
+    query_circs: Query<&DLRCCircuit>,
+    mut query_lines: Query<(&Parent, &FieldLine, &mut Transform, &mut DrawMode)>,
+) {
+    let largest_current = largest_current(query_circs.iter());
+    for (parent, line, mut transform, mut draw_mode) in query_lines.iter_mut() {
+        let circuit = match query_circs.get(parent.0) {
+            Ok(circuit) => circuit,
+            Err(_) => continue,
+        };
+        let current = circuit.0.circuit.current();
+        let strength = if largest_current > 0.0 {
+            (current.abs() / largest_current).min(1.0) as f32
+        } else {
+            0.0
+        };
+        //the outer lines fade first, as the field is weaker further out
+        let alpha = strength * (1.0 - line.0 as f32 / FIELD_LINES as f32);
+        let scale = 0.6 + 0.4 * strength;
+        transform.scale = Vec3::new(scale, scale, 1.0);
+        let hue = if current >= 0.0 { 185.0 } else { 30.0 };
+        *draw_mode = DrawMode::Stroke(StrokeMode::new(Color::hsla(hue, 0.9, 0.6, alpha), 4.0));
+    }
+}
@@ -0,0 +1,432 @@
+//! the synchronization game, where the player tunes their circuit until its current matches a
+//! target trace
+
+use bevy::prelude::*;
+use bevy_egui::egui::{Align2, Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+
+use crate::graphics::{
+    plot_start, rhythm_level, simulation_running, simulation_shown, simulation_state,
+    spawn_pulse_circle, star_text, ActiveLevel, AppState, CircuitTimer, CurrentTimePlot, Light,
+    DELTA_T, MAX_CIRCUIT_TIME, MIN_CIRCUIT_TIME,
+};
+use crate::RLCCalculator;
+
+/// This plugin adds the match-the-waveform challenge window and scores finished runs.
+pub struct SyncGamePlugin;
+
+impl Plugin for SyncGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SyncChallenge>()
+            .add_system(sync_game_frame.with_run_criteria(simulation_shown))
+            .add_system(score_challenge.with_run_criteria(simulation_running));
+    }
+}
+
+/// how long, in real seconds, the player has to match a target
+pub const CHALLENGE_TIME_LIMIT: f64 = 120.0;
+
+/* CHALLENGE RESOURCE */
+#[derive(Default)]
+/// The state of the current synchronization challenge
+pub struct SyncChallenge {
+    /// the current, time pairs the player is trying to match, none when no challenge is running
+    pub target: Option<Vec<(f64, f64)>>,
+    /// the real time left to finish a run, in seconds
+    pub time_left: f64,
+    /// the score of the last finished run
+    pub result: Option<ChallengeResult>,
+    /// the RMS error earning three stars, none to rate against the size of the target instead
+    pub par: Option<f64>,
+    seed: u64,
+}
+
+/// The score of a finished run
+pub struct ChallengeResult {
+    /// the root mean square difference between the player's current and the target current
+    pub rms_error: f64,
+    /// from 0 to 3, how well the run matched the target
+    pub stars: u8,
+}
+
+impl SyncChallenge {
+    /// Starts a challenge with a new randomly generated target
+    ///
+    /// # Arguments
+    /// * 'entropy' - a value mixed into the random generator so every challenge differs
+    pub fn start(&mut self, entropy: u64) {
+        self.seed ^= entropy | 1;
+        let startcharge = 5.0 + 15.0 * next_random(&mut self.seed);
+        let resistance = 0.05 + 0.45 * next_random(&mut self.seed);
+        let inductance = 1.0 + 7.0 * next_random(&mut self.seed);
+        let capacitance = 1.0 + 7.0 * next_random(&mut self.seed);
+        let mut target =
+            RLCCalculator::with_constants(startcharge, resistance, inductance, capacitance);
+        self.start_with_target(simulate_trace(&mut target), None);
+    }
+
+    /// Starts a challenge with the given target
+    ///
+    /// # Arguments
+    /// * 'target' - the current, time pairs to match
+    /// * 'par' - the RMS error earning three stars, or none to rate against the target's size
+    pub fn start_with_target(&mut self, target: Vec<(f64, f64)>, par: Option<f64>) {
+        self.target = Some(target);
+        self.par = par;
+        self.retry();
+    }
+
+    /// Clears the last result and restarts the clock, keeping the same target
+    pub fn retry(&mut self) {
+        self.time_left = CHALLENGE_TIME_LIMIT;
+        self.result = None;
+    }
+
+    /// Ends the challenge
+    pub fn quit(&mut self) {
+        self.target = None;
+        self.result = None;
+        self.par = None;
+    }
+
+    /// Rates a run's RMS error, against par if there is one
+    ///
+    /// # Returns
+    /// From 0 to 3 stars
+    pub fn rate(&self, rms_error: f64) -> u8 {
+        match (self.par, &self.target) {
+            (Some(par), _) => par_rating(rms_error, par),
+            (None, Some(target)) => star_rating(rms_error, target),
+            (None, None) => 0,
+        }
+    }
+}
+
+/// Steps a xorshift generator, good enough to pick targets
+///
+/// # Returns
+/// A floating point number between 0 and 1
+fn next_random(seed: &mut u64) -> f64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    (*seed >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Runs a circuit over the whole simulation, the same way `update_time` would
+///
+/// # Arguments
+/// * 'circuit' - the circuit to simulate, which is reset first
+///
+/// # Returns
+/// The current, time pairs the circuit would plot
+pub fn simulate_trace(circuit: &mut RLCCalculator) -> Vec<(f64, f64)> {
+    circuit.reset();
+    let mut time = MIN_CIRCUIT_TIME;
+    let mut trace = plot_start(circuit);
+    while time <= MAX_CIRCUIT_TIME {
+        time += DELTA_T;
+        circuit.tick(DELTA_T);
+        trace.push((time, circuit.current()));
+    }
+    trace
+}
+
+/// Reads a plot's current at a time, between its points
+///
+/// # Arguments
+/// * 'plot' - the current, time pairs, in order of time
+/// * 'time' - the time to read the current at
+///
+/// # Returns
+/// The current, or zero outside the plot
+pub fn current_at(plot: &[(f64, f64)], time: f64) -> f64 {
+    let (first, last) = match (plot.first(), plot.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return 0.0,
+    };
+    //a run stepped differently can end a rounding error short of the target
+    let tolerance = 1e-9 * time.abs().max(1.0);
+    if time < first.0 - tolerance || time > last.0 + tolerance {
+        return 0.0;
+    }
+    let after = plot.partition_point(|&(t, _)| t < time);
+    match (
+        after.checked_sub(1).map(|before| plot[before]),
+        plot.get(after),
+    ) {
+        //right on a point
+        (_, Some(&(t1, i1))) if t1 <= time => i1,
+        (Some((t0, i0)), Some(&(t1, i1))) => i0 + (i1 - i0) * (time - t0) / (t1 - t0),
+        (_, Some(&(_, current))) | (Some((_, current)), None) => current,
+        (None, None) => 0.0,
+    }
+}
+
+/// Calculates the root mean square difference between a plot and a target
+///
+/// The plot is read at the target's times, so runs stepped at a different rate can still be
+/// compared. Times the plot hasn't reached, because the run hasn't gotten there, count as zero
+/// current.
+///
+/// # Arguments
+/// * 'plot' - the current, time pairs of the player's circuit
+/// * 'target' - the current, time pairs being matched
+///
+/// # Returns
+/// A floating point number representing the RMS error, in amps
+pub fn rms_error(plot: &[(f64, f64)], target: &[(f64, f64)]) -> f64 {
+    if target.is_empty() {
+        return 0.0;
+    }
+    let squared_sum: f64 = target
+        .iter()
+        .map(|&(time, target_current)| (current_at(plot, time) - target_current).powi(2))
+        .sum();
+    (squared_sum / target.len() as f64).sqrt()
+}
+
+/// Rates an RMS error relative to the size of the target current
+///
+/// # Arguments
+/// * 'rms_error' - the RMS error of the run
+/// * 'target' - the current, time pairs being matched
+///
+/// # Returns
+/// From 0 to 3 stars
+pub fn star_rating(rms_error: f64, target: &[(f64, f64)]) -> u8 {
+    let target_rms = self::rms_error(&[], target);
+    if target_rms == 0.0 {
+        return 3;
+    }
+    match rms_error / target_rms {
+        r if r < 0.05 => 3,
+        r if r < 0.15 => 2,
+        r if r < 0.35 => 1,
+        _ => 0,
+    }
+}
+
+/// Rates an RMS error against a par score
+///
+/// # Arguments
+/// * 'rms_error' - the RMS error of the run
+/// * 'par' - the RMS error at or under which a run earns three stars
+///
+/// # Returns
+/// From 0 to 3 stars
+pub fn par_rating(rms_error: f64, par: f64) -> u8 {
+    match rms_error {
+        r if r <= par => 3,
+        r if r <= 2.0 * par => 2,
+        r if r <= 4.0 * par => 1,
+        _ => 0,
+    }
+}
+
+/// Counts down the clock and scores the run once the simulation ends or time runs out
+fn score_challenge(
+    mut commands: Commands,
+    time: Res<Time>,
+    circuit_timer: Res<CircuitTimer>,
+    mut challenge: ResMut<SyncChallenge>,
+    query_plots: Query<&CurrentTimePlot>,
+    query_lights: Query<Entity, With<Light>>,
+) {
+    let challenge = &mut *challenge;
+    let target = match (&challenge.target, &challenge.result) {
+        (Some(target), None) => target,
+        _ => return,
+    };
+    challenge.time_left = (challenge.time_left - time.delta_seconds_f64()).max(0.0);
+    if circuit_timer.time < circuit_timer.end && challenge.time_left > 0.0 {
+        return;
+    }
+
+    //with several circuits, the closest one counts
+    let rms = query_plots
+        .iter()
+        .map(|plot| rms_error(&plot.0, target))
+        .fold(f64::INFINITY, f64::min);
+    let stars = if challenge.time_left > 0.0 {
+        challenge.rate(rms)
+    } else {
+        0
+    };
+
+    //one ring per star
+    for light in query_lights.iter() {
+        for star in 0..stars {
+            spawn_pulse_circle(&mut commands, light, 10.0 * (star + 1) as f32);
+        }
+    }
+    challenge.result = Some(ChallengeResult {
+        rms_error: rms,
+        stars,
+    });
+}
+
+/// creates a window to start, follow, and finish challenges
+fn sync_game_frame(
+    mut egui_context: ResMut<EguiContext>,
+    time: Res<Time>,
+    circuit_timer: Res<CircuitTimer>,
+    mut challenge: ResMut<SyncChallenge>,
+    mut active: ResMut<ActiveLevel>,
+    mut state: ResMut<State<AppState>>,
+    query_plots: Query<&CurrentTimePlot>,
+) {
+    if simulation_state(&state) == Some(AppState::Rhythm) {
+        return;
+    }
+    let title = match active.index {
+        Some(index) => format!("Level {}: {}", index + 1, active.level.name),
+        None => "Sync challenge".to_string(),
+    };
+    egui::Window::new("Sync challenge")
+        .title_bar(false)
+        .anchor(Align2::RIGHT_TOP, [-50.0, 50.0])
+        .fixed_size([200.0, 100.0])
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.heading(title);
+            if active.index.is_some() {
+                ui.label(&active.level.description);
+            }
+            let target = match &challenge.target {
+                Some(target) => target,
+                None => {
+                    ui.label("Tune R, L, C and Q to match a mystery current.");
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        if ui.button("Start challenge").clicked() {
+                            challenge.start(time.seconds_since_startup().to_bits());
+                        }
+                        if ui.button("Levels").clicked() {
+                            let _ = state.overwrite_replace(AppState::LevelSelect);
+                        }
+                        if ui.button("Rhythm").clicked() {
+                            *active = ActiveLevel {
+                                index: None,
+                                level: rhythm_level(),
+                                built: None,
+                            };
+                            let _ = state.overwrite_replace(AppState::Rhythm);
+                        }
+                    });
+                    return;
+                }
+            };
+
+            match &challenge.result {
+                Some(result) => {
+                    ui.label(
+                        RichText::new(star_text(result.stars))
+                            .size(30.0)
+                            .color(Color32::GOLD),
+                    );
+                    ui.label(format!("RMS error: {:.3} (A)", result.rms_error));
+                    if let Some(par) = challenge.par {
+                        ui.label(format!("par: {:.3} (A)", par));
+                    }
+                    if ui.button("Try again").clicked() {
+                        challenge.retry();
+                    }
+                }
+                None => {
+                    ui.add(
+                        egui::ProgressBar::new((challenge.time_left / CHALLENGE_TIME_LIMIT) as f32)
+                            .text(format!("time left: {:.0} (s)", challenge.time_left)),
+                    );
+                    //only compare the part of the target the run has reached
+                    let reached = target
+                        .iter()
+                        .take_while(|&&(t, _)| t <= circuit_timer.time)
+                        .count();
+                    let rms = query_plots
+                        .iter()
+                        .map(|plot| rms_error(&plot.0, &target[..reached]))
+                        .fold(f64::INFINITY, f64::min);
+                    if rms.is_finite() {
+                        ui.label(format!("RMS error so far: {:.3} (A)", rms));
+                    }
+                }
+            }
+            ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                if active.index.is_some() {
+                    if ui.button("Back to levels").clicked() {
+                        let _ = state.overwrite_replace(AppState::LevelSelect);
+                    }
+                    return;
+                }
+                if ui.button("New target").clicked() {
+                    challenge.start(time.seconds_since_startup().to_bits());
+                }
+                if ui.button("Quit").clicked() {
+                    challenge.quit();
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A damped swing sampled every `step` seconds
+    fn swing(step: f64) -> Vec<(f64, f64)> {
+        (0..=(10.0 / step).round() as usize)
+            .map(|k| k as f64 * step)
+            .map(|t| (t, 5.0 * (-0.1 * t).exp() * t.sin()))
+            .collect()
+    }
+
+    #[test]
+    fn plots_are_read_between_their_points() {
+        let plot = [(0.0, 0.0), (1.0, 2.0), (3.0, -2.0)];
+        assert_eq!(current_at(&plot, 0.5), 1.0);
+        assert_eq!(current_at(&plot, 2.0), 0.0);
+        assert_eq!(current_at(&plot, 3.0), -2.0);
+        //outside the plot counts as no current
+        assert_eq!(current_at(&plot, -1.0), 0.0);
+        assert_eq!(current_at(&plot, 3.5), 0.0);
+        assert_eq!(current_at(&[], 1.0), 0.0);
+    }
+
+    #[test]
+    fn matching_runs_have_no_error() {
+        let target = swing(0.1);
+        assert_eq!(rms_error(&target, &target), 0.0);
+        assert_eq!(rms_error(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn runs_at_another_step_are_compared_at_the_same_times() {
+        let target = swing(0.1);
+        //the same current, plotted three times as often
+        assert!(rms_error(&swing(0.1 / 3.0), &target) < 1e-3);
+        //matching by index would compare the first tenth of the run with the whole target
+        assert!(rms_error(&swing(0.01), &target) < 1e-3);
+    }
+
+    #[test]
+    fn error_is_the_root_mean_square_difference() {
+        let target = [(0.0, 1.0), (1.0, 1.0), (2.0, 1.0), (3.0, 1.0)];
+        let offset: Vec<(f64, f64)> = target.iter().map(|&(t, i)| (t, i + 0.5)).collect();
+        assert!((rms_error(&offset, &target) - 0.5).abs() < 1e-12);
+        //half the run is missing, so half the target is compared with no current
+        assert!((rms_error(&offset[..2], &target) - (0.625f64).sqrt()).abs() < 1e-12);
+        assert_eq!(rms_error(&[], &target), 1.0);
+    }
+
+    #[test]
+    fn stars_follow_the_error_relative_to_the_target() {
+        let target = [(0.0, 2.0), (1.0, -2.0)];
+        assert_eq!(star_rating(0.0, &target), 3);
+        assert_eq!(star_rating(0.09, &target), 3);
+        assert_eq!(star_rating(0.2, &target), 2);
+        assert_eq!(star_rating(0.5, &target), 1);
+        assert_eq!(star_rating(0.7, &target), 0);
+        //nothing to match is always matched
+        assert_eq!(star_rating(1.0, &[(0.0, 0.0)]), 3);
+    }
+}
@@ -0,0 +1,422 @@
+//! the inspector, opened by clicking a part of a circuit to see the voltage across it, the
+//! current through it, its power and the energy it stores, with its value editable in place
+
+use bevy::prelude::*;
+use bevy_egui::egui::{
+    plot::{Line, Plot, Value, Values},
+    Color32, LayerId, Order, RichText, Stroke,
+};
+use bevy_egui::{egui, EguiContext};
+
+use crate::graphics::{
+    cursor_world, format_si, simulation_running, simulation_shown, value_slider, ActiveLevel,
+    CircuitTimer, DLRCCircuit, Element, Part, PlacedElement, Schematic,
+    DEFAULT_SIGNIFICANT_FIGURES,
+};
+
+/// This plugin lets the player click the parts of a circuit to inspect them.
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Inspected>()
+            .add_system(pick_element.with_run_criteria(simulation_running))
+            .add_system(record_inspected.with_run_criteria(simulation_running))
+            .add_system(inspector_frame.with_run_criteria(simulation_shown));
+    }
+}
+
+/// how many readings the sparklines show
+const HISTORY_LENGTH: usize = 300;
+/// how far above and below the wire a click still picks a part, in the schematic's units
+const PICK_HALF_HEIGHT: f32 = 150.0;
+/// how far past its ends a click still picks a part, in the schematic's units
+const PICK_MARGIN: f32 = 40.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A part of one of the circuits
+pub struct InspectedElement {
+    pub circuit: Entity,
+    /// the index of the part in the circuit's `Schematic`
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// What a part is doing at one moment
+pub struct ElementReading {
+    /// the simulation time of the reading
+    pub time: f64,
+    /// the voltage across the part, in the direction of the current
+    pub voltage: f64,
+    pub current: f64,
+    /// the power going into the part, negative while it gives energy back
+    pub power: f64,
+    /// the energy stored in the part's field, zero for resistors
+    pub energy: f64,
+}
+
+#[derive(Default)]
+/// The part being inspected and its recent readings
+pub struct Inspected {
+    pub target: Option<InspectedElement>,
+    pub history: Vec<ElementReading>,
+}
+
+impl Inspected {
+    /// Starts inspecting a part, forgetting the readings of the last one
+    fn inspect(&mut self, target: Option<InspectedElement>) {
+        if self.target != target {
+            self.target = target;
+            self.history.clear();
+        }
+    }
+}
+
+/// Whether a part has readings worth inspecting
+fn inspectable(element: Element) -> bool {
+    matches!(
+        element,
+        Element::Resistor | Element::Bulb | Element::Inductor | Element::Capacitor
+    )
+}
+
+/// The resistance, inductance or capacitance of a part
+///
+/// # Arguments
+/// * 'circuit' - the circuit the part is in
+/// * 'placed' - the part, whose own value is used if it has one
+///
+/// # Returns
+/// The value, or zero for parts without one
+pub fn element_value(circuit: &DLRCCircuit, placed: &PlacedElement) -> f64 {
+    let rlc = &circuit.0.circuit;
+    placed.value.unwrap_or(match placed.element {
+        Element::Resistor | Element::Bulb => rlc.resistance,
+        Element::Inductor => rlc.inductance,
+        Element::Capacitor => rlc.capacitance,
+        Element::Source | Element::Switch => 0.0,
+    })
+}
+
+/// Works out what a part is doing right now
+///
+/// The current and charge are the same all the way around a series loop, so every part's
+/// reading follows from them and its own value.
+///
+/// # Arguments
+/// * 'circuit' - the circuit the part is in
+/// * 'placed' - the part
+/// * 'time' - the simulation time, stored with the reading
+///
+/// # Returns
+/// The reading
+pub fn element_reading(circuit: &DLRCCircuit, placed: &PlacedElement, time: f64) -> ElementReading {
+    let rlc = &circuit.0.circuit;
+    let value = element_value(circuit, placed);
+    let current = rlc.current();
+    let (voltage, energy) = match placed.element {
+        Element::Resistor | Element::Bulb => (current * value, 0.0),
+        Element::Inductor => (value * rlc.current_rate(), 0.5 * value * current * current),
+        Element::Capacitor => (rlc.charge() / value, rlc.charge().powi(2) / (2.0 * value)),
+        Element::Source | Element::Switch => (0.0, 0.0),
+    };
+    ElementReading {
+        time,
+        voltage,
+        current,
+        power: voltage * current,
+        energy,
+    }
+}
+
+/// Changes the value of a part
+///
+/// Parts with their own value change just themselves, and the circuit's totals are worked out
+/// again from every part, the way the editor's netlist adds them up. Parts without one change the
+/// circuit's value directly.
+///
+/// # Arguments
+/// * 'circuit' - the circuit the part is in
+/// * 'schematic' - the circuit's schematic, holding the part
+/// * 'index' - the index of the part in the schematic
+/// * 'value' - the new resistance, inductance or capacitance
+pub fn set_element_value(
+    circuit: &mut DLRCCircuit,
+    schematic: &mut Schematic,
+    index: usize,
+    value: f64,
+) {
+    let rlc = &mut circuit.0.circuit;
+    let placed = &mut schematic.elements[index];
+    if placed.value.is_none() {
+        match placed.element {
+            Element::Resistor | Element::Bulb => rlc.resistance = value,
+            Element::Inductor => rlc.inductance = value,
+            Element::Capacitor => rlc.capacitance = value,
+            Element::Source | Element::Switch => {}
+        }
+        return;
+    }
+    placed.value = Some(value);
+
+    let values = |kinds: &[Element]| -> Vec<f64> {
+        schematic
+            .elements
+            .iter()
+            .filter(|placed| kinds.contains(&placed.element))
+            .filter_map(|placed| placed.value)
+            .collect()
+    };
+    rlc.resistance = values(&[Element::Resistor, Element::Bulb]).iter().sum();
+    rlc.inductance = values(&[Element::Inductor]).iter().sum();
+    rlc.capacitance = values(&[Element::Capacitor])
+        .iter()
+        .map(|capacitance| capacitance.recip())
+        .sum::<f64>()
+        .recip();
+}
+
+/// Finds the part of a circuit under a point
+///
+/// # Arguments
+/// * 'schematic' - the circuit's schematic
+/// * 'transform' - where the circuit is
+/// * 'point' - the point in the world
+///
+/// # Returns
+/// The index of the part in the schematic, or none if the point isn't on an inspectable part
+fn element_under(schematic: &Schematic, transform: &GlobalTransform, point: Vec2) -> Option<usize> {
+    let local = (point.extend(0.0) - transform.translation) / transform.scale;
+    if (local.y - schematic.top()).abs() > PICK_HALF_HEIGHT {
+        return None;
+    }
+    schematic.elements.iter().position(|placed| {
+        inspectable(placed.element)
+            && local.x > placed.start - PICK_MARGIN
+            && local.x < placed.end + PICK_MARGIN
+    })
+}
+
+/// Opens the inspector on the part that's clicked
+fn pick_element(
+    windows: Res<Windows>,
+    buttons: Res<Input<MouseButton>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut inspected: ResMut<Inspected>,
+    query_circs: Query<(Entity, &Schematic, &GlobalTransform), With<DLRCCircuit>>,
+) {
+    if !buttons.just_pressed(MouseButton::Left) || egui_context.ctx_mut().wants_pointer_input() {
+        return;
+    }
+    let cursor = match cursor_world(&windows) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    let target = query_circs
+        .iter()
+        .find_map(|(entity, schematic, transform)| {
+            element_under(schematic, transform, cursor).map(|index| InspectedElement {
+                circuit: entity,
+                index,
+            })
+        });
+    if target.is_some() {
+        inspected.inspect(target);
+    }
+}
+
+/// Keeps the readings of the inspected part, whenever the simulation moves on
+fn record_inspected(
+    time: Res<CircuitTimer>,
+    mut inspected: ResMut<Inspected>,
+    query_circs: Query<(&DLRCCircuit, &Schematic)>,
+) {
+    let target = match inspected.target {
+        Some(target) => target,
+        None => return,
+    };
+    let (circuit, schematic) = match query_circs.get(target.circuit) {
+        Ok(circuit) => circuit,
+        Err(_) => return,
+    };
+    let placed = match schematic.elements.get(target.index) {
+        Some(placed) => placed,
+        None => return,
+    };
+    let last_time = inspected.history.last().map(|reading| reading.time);
+    match last_time {
+        Some(last) if last == time.time => return,
+        //the circuit was reset
+        Some(last) if last > time.time => inspected.history.clear(),
+        _ => {}
+    }
+    inspected
+        .history
+        .push(element_reading(circuit, placed, time.time));
+    if inspected.history.len() > HISTORY_LENGTH {
+        let extra = inspected.history.len() - HISTORY_LENGTH;
+        inspected.history.drain(..extra);
+    }
+}
+
+/// A small plot of one quantity over the recent readings
+fn sparkline(
+    ui: &mut egui::Ui,
+    id: &str,
+    history: &[ElementReading],
+    quantity: fn(&ElementReading) -> f64,
+    color: Color32,
+) {
+    let line = Line::new(Values::from_values_iter(
+        history
+            .iter()
+            .map(|reading| Value::new(reading.time, quantity(reading))),
+    ))
+    .stroke(Stroke { width: 2.0, color });
+    Plot::new(id)
+        .height(50.0)
+        .show_background(false)
+        .show_axes([false, false])
+        .allow_scroll(false)
+        .allow_zoom(false)
+        .allow_drag(false)
+        .allow_boxed_zoom(false)
+        .show_x(false)
+        .show_y(false)
+        .show(ui, |plot_ui| plot_ui.line(line));
+}
+
+/// Shows the inspected part's readings and lets its value be changed, outlining it in the scene
+fn inspector_frame(
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    active: Res<ActiveLevel>,
+    mut inspected: ResMut<Inspected>,
+    mut query_circs: Query<(Entity, &mut DLRCCircuit, &mut Schematic, &GlobalTransform)>,
+) {
+    let target = match inspected.target {
+        Some(target) => target,
+        None => return,
+    };
+    let circuit_count = query_circs.iter().count();
+    let circuit_number = query_circs
+        .iter()
+        .position(|(entity, ..)| entity == target.circuit);
+    let (_, mut circuit, mut schematic, transform) = match query_circs.get_mut(target.circuit) {
+        Ok(circuit) => circuit,
+        Err(_) => {
+            //the circuit was despawned
+            inspected.inspect(None);
+            return;
+        }
+    };
+    let placed = match schematic.elements.get(target.index) {
+        Some(placed) => *placed,
+        None => {
+            inspected.inspect(None);
+            return;
+        }
+    };
+
+    //outline the part, the same way the labels are placed over it
+    if let Some(window) = windows.get_primary() {
+        let to_screen = |x: f32, y: f32| {
+            let world = transform.mul_vec3(Vec3::new(x, y, 0.0));
+            egui::pos2(
+                window.width() * 0.5 + world.x,
+                window.height() * 0.5 - world.y,
+            )
+        };
+        let top = schematic.top();
+        let rect = egui::Rect::from_two_pos(
+            to_screen(placed.start - PICK_MARGIN, top + PICK_HALF_HEIGHT),
+            to_screen(placed.end + PICK_MARGIN, top - PICK_HALF_HEIGHT),
+        );
+        egui_context
+            .ctx_mut()
+            .layer_painter(LayerId::new(
+                Order::Background,
+                egui::Id::new("inspector outline"),
+            ))
+            .rect_stroke(rect, 4.0, Stroke::new(2.0, Color32::GOLD));
+    }
+
+    let title = match circuit_number {
+        Some(number) if circuit_count > 1 => {
+            format!("{} (circuit {})", placed.element.name(), number + 1)
+        }
+        _ => placed.element.name().to_string(),
+    };
+    let now = inspected
+        .history
+        .last()
+        .copied()
+        .unwrap_or_else(|| element_reading(&circuit, &placed, 0.0));
+    let mut open = true;
+    egui::Window::new(title)
+        .id(egui::Id::new("inspector"))
+        .default_pos([800.0, 100.0])
+        .resizable(false)
+        .open(&mut open)
+        .show(egui_context.ctx_mut(), |ui| {
+            //parts with their own value were built in the editor, and can always be changed
+            let (part, range) = match placed.element {
+                Element::Inductor => (Part::Inductor, active.level.ranges.inductance),
+                Element::Capacitor => (Part::Capacitor, active.level.ranges.capacitance),
+                _ => (Part::Resistor, active.level.ranges.resistance),
+            };
+            let editable = placed.value.is_some() || active.level.allows(part);
+            let mut value = element_value(&circuit, &placed);
+            let range = match placed.value {
+                Some(_) => (0.01, 100.0),
+                None => range,
+            };
+            let changed = ui
+                .add_enabled_ui(editable, |ui| {
+                    value_slider(
+                        ui,
+                        ("inspector", target.circuit, target.index),
+                        &mut value,
+                        range,
+                        placed.element.name(),
+                        placed.element.unit(),
+                    )
+                })
+                .inner;
+            if changed {
+                set_element_value(&mut circuit, &mut schematic, target.index, value);
+            }
+            ui.separator();
+
+            let si = |value, unit| format_si(value, unit, DEFAULT_SIGNIFICANT_FIGURES);
+            egui::Grid::new("inspector readings").show(ui, |ui| {
+                ui.label("voltage");
+                ui.label(si(now.voltage, "V"));
+                ui.end_row();
+                ui.label("current");
+                ui.label(si(now.current, "A"));
+                ui.end_row();
+                ui.label("power");
+                ui.label(si(now.power, "W"));
+                ui.end_row();
+                ui.label("stored energy");
+                ui.label(match placed.element {
+                    Element::Resistor | Element::Bulb => "none, it's all lost as heat".to_string(),
+                    _ => si(now.energy, "J"),
+                });
+                ui.end_row();
+            });
+            ui.separator();
+
+            let history = &inspected.history;
+            ui.label(RichText::new("voltage").color(Color32::LIGHT_RED));
+            sparkline(ui, "voltage", history, |r| r.voltage, Color32::LIGHT_RED);
+            ui.label(RichText::new("current").color(Color32::LIGHT_BLUE));
+            sparkline(ui, "current", history, |r| r.current, Color32::LIGHT_BLUE);
+            ui.label(RichText::new("power").color(Color32::GOLD));
+            sparkline(ui, "power", history, |r| r.power, Color32::GOLD);
+        });
+    if !open {
+        inspected.inspect(None);
+    }
+}
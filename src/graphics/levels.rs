@@ -0,0 +1,460 @@
+//! levels the player works through, each asking them to match a target current using a limited
+//! set of components
+
+use bevy::prelude::*;
+use bevy_egui::egui::{Align2, Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::{
+    clear_circuits, simulate_trace, spawn_built_circuit, AppState, BuiltCircuit, RunSummary,
+    SyncChallenge,
+};
+use crate::storage::SaveFile;
+use crate::{DisconnectLightCircuitCalculator, RLCCalculator};
+
+/// This plugin adds the level select screen, loads the built in levels and saved progress, and
+/// respawns the circuit whenever a level or the sandbox is entered.
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LevelBook::built_in())
+            .insert_resource(LevelProgress::load())
+            .insert_resource(ActiveLevel::sandbox())
+            .add_system_set(SystemSet::on_enter(AppState::Sandbox).with_system(spawn_built_circuit))
+            .add_system_set(SystemSet::on_enter(AppState::LevelSelect).with_system(clear_circuits))
+            .add_system_set(
+                SystemSet::on_update(AppState::LevelSelect).with_system(level_select_frame),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Level)
+                    .with_system(spawn_built_circuit)
+                    .with_system(start_level_challenge),
+            )
+            .add_system_set(SystemSet::on_update(AppState::Level).with_system(finish_level))
+            .add_system_set(SystemSet::on_exit(AppState::Level).with_system(quit_challenge));
+    }
+}
+
+/* LEVEL DEFINITIONS */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// A part of the circuit a level can let the player adjust
+pub enum Part {
+    Resistor,
+    Inductor,
+    Capacitor,
+    /// the starting charge on the capacitor
+    Charge,
+    /// the starting current through the inductor
+    Current,
+    /// a second circuit sharing a mutual inductance with the first
+    Coupling,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// The constants defining an RLC series circuit
+pub struct CircuitConstants {
+    pub startcharge: f64,
+    /// the current flowing at the start, zero unless given
+    #[serde(default)]
+    pub startcurrent: f64,
+    pub resistance: f64,
+    pub inductance: f64,
+    pub capacitance: f64,
+    /// the voltage of the sources around the loop, zero unless given
+    #[serde(default)]
+    pub emf: f64,
+}
+
+impl CircuitConstants {
+    /// Creates a calculator for a circuit with these constants
+    pub fn calculator(&self) -> RLCCalculator {
+        let mut circuit = RLCCalculator::with_initial_current(
+            self.startcharge,
+            self.startcurrent,
+            self.resistance,
+            self.inductance,
+            self.capacitance,
+        );
+        circuit.emf = self.emf;
+        circuit.reset();
+        circuit
+    }
+
+    /// Estimates the largest current the circuit reaches, the same as `RLCCalculator::peak_current`
+    pub fn peak_current(&self) -> f64 {
+        self.calculator().peak_current()
+    }
+
+    /// Creates a calculator for a circuit with these constants connected to a lightbulb
+    pub fn light_calculator(&self) -> DisconnectLightCircuitCalculator {
+        DisconnectLightCircuitCalculator::from_rlc(self.calculator())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+/// The inclusive (min, max) range of each slider
+pub struct SliderRanges {
+    pub resistance: (f64, f64),
+    pub inductance: (f64, f64),
+    pub capacitance: (f64, f64),
+    pub startcharge: (f64, f64),
+    pub startcurrent: (f64, f64),
+}
+
+impl Default for SliderRanges {
+    fn default() -> Self {
+        Self {
+            resistance: (0.0, 1.0),
+            inductance: (0.1, 10.0),
+            capacitance: (0.1, 10.0),
+            startcharge: (0.0, 50.0),
+            startcurrent: (-10.0, 10.0),
+        }
+    }
+}
+
+impl SliderRanges {
+    /// Ranges reaching a hundred times either side of a circuit's values, so the log sliders put
+    /// them in the middle
+    pub fn around(constants: &CircuitConstants) -> Self {
+        let around = |value: f64| {
+            if value > 0.0 {
+                (value / RANGE_SPREAD, value * RANGE_SPREAD)
+            } else {
+                (0.0, 1.0)
+            }
+        };
+        Self {
+            resistance: around(constants.resistance),
+            inductance: around(constants.inductance),
+            capacitance: around(constants.capacitance),
+            startcharge: around(constants.startcharge),
+            //either way round, so the current can start flowing in both directions, out past
+            //the peak current
+            startcurrent: {
+                let largest = match constants.peak_current() {
+                    peak if peak > 0.0 => peak * RANGE_SPREAD,
+                    _ => 1.0,
+                };
+                (-largest, largest)
+            },
+        }
+    }
+}
+
+/// how many times bigger and smaller than a value the ranges around it reach
+const RANGE_SPREAD: f64 = 100.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A single level, as written in `assets/levels.ron`
+pub struct Level {
+    pub name: String,
+    pub description: String,
+    /// the circuit whose current the player has to match, none for free play
+    pub target: Option<CircuitConstants>,
+    /// the constants the player's circuit starts with
+    pub start: CircuitConstants,
+    /// the parts the player is allowed to adjust
+    pub allowed: Vec<Part>,
+    #[serde(default)]
+    pub ranges: SliderRanges,
+    /// the RMS error, in amps, at or under which the level earns three stars
+    pub par: f64,
+}
+
+impl Level {
+    /// Whether the player may adjust the given part in this level
+    pub fn allows(&self, part: Part) -> bool {
+        self.allowed.contains(&part)
+    }
+}
+
+/// Every level, in the order they unlock
+pub struct LevelBook(pub Vec<Level>);
+
+impl LevelBook {
+    /// Reads the levels shipped with the game
+    pub fn built_in() -> Self {
+        Self(
+            ron::from_str(include_str!("../../assets/levels.ron"))
+                .expect("assets/levels.ron should be a valid list of levels"),
+        )
+    }
+}
+
+/// The level being played, or the sandbox
+pub struct ActiveLevel {
+    /// the position of the level in the `LevelBook`, none for the sandbox
+    pub index: Option<usize>,
+    pub level: Level,
+    /// the loop built in the editor, none for the usual bulb, inductor and capacitor
+    pub built: Option<BuiltCircuit>,
+}
+
+impl ActiveLevel {
+    /// The sandbox, with every part adjustable and no target
+    pub fn sandbox() -> Self {
+        let start = CircuitConstants {
+            startcharge: 10.0,
+            startcurrent: 0.0,
+            resistance: 0.2,
+            inductance: 4.0,
+            capacitance: 6.0,
+            emf: 0.0,
+        };
+        Self {
+            index: None,
+            level: Level {
+                name: "Sandbox".to_string(),
+                description: "Free play with every component.".to_string(),
+                target: None,
+                start,
+                allowed: vec![
+                    Part::Resistor,
+                    Part::Inductor,
+                    Part::Capacitor,
+                    Part::Charge,
+                    Part::Current,
+                    Part::Coupling,
+                ],
+                ranges: SliderRanges::around(&start),
+                par: 0.0,
+            },
+            built: None,
+        }
+    }
+
+    /// A circuit built in the editor, whose values are set there rather than with sliders
+    ///
+    /// # Arguments
+    /// * 'built' - the circuit the editor's loop reduces to
+    pub fn built(built: &BuiltCircuit) -> Self {
+        Self {
+            index: None,
+            level: Level {
+                name: "Built circuit".to_string(),
+                description: "The circuit from the editor.".to_string(),
+                target: None,
+                start: built.constants,
+                allowed: vec![Part::Coupling],
+                ranges: SliderRanges::default(),
+                par: 0.0,
+            },
+            built: Some(built.clone()),
+        }
+    }
+
+    /// The circuit the level is played on, with the level's starting values
+    pub fn circuit(&self) -> BuiltCircuit {
+        let mut built = self
+            .built
+            .clone()
+            .unwrap_or_else(|| BuiltCircuit::light_circuit(self.level.start));
+        built.constants = self.level.start;
+        built
+    }
+}
+
+/* PROGRESS */
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// The best star rating earned on each level, saved between runs
+pub struct LevelProgress {
+    pub stars: Vec<u8>,
+}
+
+impl SaveFile for LevelProgress {
+    const FILE: &'static str = "progress.ron";
+    const NAME: &'static str = "level progress";
+}
+
+impl LevelProgress {
+    /// The best star rating earned on a level
+    pub fn stars_for(&self, index: usize) -> u8 {
+        self.stars.get(index).copied().unwrap_or(0)
+    }
+
+    /// Whether a level can be played, which needs at least a star on the level before it
+    pub fn is_unlocked(&self, index: usize) -> bool {
+        index == 0 || self.stars_for(index - 1) > 0
+    }
+
+    /// Records a star rating for a level
+    ///
+    /// # Returns
+    /// Whether the rating beat the previous best
+    pub fn record(&mut self, index: usize, stars: u8) -> bool {
+        if stars <= self.stars_for(index) {
+            return false;
+        }
+        if self.stars.len() <= index {
+            self.stars.resize(index + 1, 0);
+        }
+        self.stars[index] = stars;
+        true
+    }
+}
+
+/// Formats a star rating like ★★☆
+pub fn star_text(stars: u8) -> String {
+    "\u{2605}".repeat(stars as usize) + &"\u{2606}".repeat(3usize.saturating_sub(stars as usize))
+}
+
+/// Gives the sync challenge the active level's target
+fn start_level_challenge(active: Res<ActiveLevel>, mut challenge: ResMut<SyncChallenge>) {
+    match active.level.target {
+        Some(target) => {
+            let trace = simulate_trace(&mut target.calculator());
+            challenge.start_with_target(trace, Some(active.level.par));
+        }
+        None => challenge.quit(),
+    }
+}
+
+/// Ends the level's challenge when leaving it
+fn quit_challenge(mut challenge: ResMut<SyncChallenge>) {
+    challenge.quit();
+}
+
+/// Saves the star rating and shows the results once a level's run has been scored
+fn finish_level(
+    active: Res<ActiveLevel>,
+    challenge: Res<SyncChallenge>,
+    mut progress: ResMut<LevelProgress>,
+    mut summary: ResMut<RunSummary>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if let (Some(index), Some(result)) = (active.index, &challenge.result) {
+        if progress.record(index, result.stars) {
+            progress.save();
+        }
+        *summary = RunSummary {
+            title: format!("Level {}: {}", index + 1, active.level.name),
+            stars: result.stars,
+            details: vec![
+                format!("RMS error: {:.3} (A)", result.rms_error),
+                format!("par: {:.3} (A)", active.level.par),
+            ],
+            retry: AppState::Level,
+        };
+        let _ = state.overwrite_set(AppState::Results);
+    }
+}
+
+/// creates the level select screen
+fn level_select_frame(
+    mut egui_context: ResMut<EguiContext>,
+    book: Res<LevelBook>,
+    progress: Res<LevelProgress>,
+    mut active: ResMut<ActiveLevel>,
+    mut state: ResMut<State<AppState>>,
+) {
+    egui::Window::new("Levels")
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .fixed_size([300.0, 300.0])
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            for (index, level) in book.0.iter().enumerate() {
+                ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                    let unlocked = progress.is_unlocked(index);
+                    let button = ui
+                        .add_enabled(
+                            unlocked,
+                            egui::Button::new(format!("{}. {}", index + 1, level.name)),
+                        )
+                        .on_hover_text(&level.description);
+                    ui.label(
+                        RichText::new(star_text(progress.stars_for(index))).color(Color32::GOLD),
+                    );
+                    if button.clicked() {
+                        *active = ActiveLevel {
+                            index: Some(index),
+                            level: level.clone(),
+                            built: None,
+                        };
+                        let _ = state.overwrite_replace(AppState::Level);
+                    }
+                });
+            }
+            ui.separator();
+            if ui.button("Back").clicked() {
+                let _ = state.overwrite_replace(AppState::MainMenu);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_levels_are_valid() {
+        let levels: Vec<Level> = ron::from_str(include_str!("../../assets/levels.ron"))
+            .unwrap_or_else(|error| panic!("assets/levels.ron doesn't parse: {}", error));
+        assert!(!levels.is_empty());
+        for level in levels.iter() {
+            if level.target.is_some() {
+                assert!(level.par > 0.0, "'{}' has a target but no par", level.name);
+            }
+            for constants in level.target.iter().chain([&level.start]) {
+                assert!(
+                    constants.inductance > 0.0 && constants.capacitance > 0.0,
+                    "'{}' has a circuit without an inductance or capacitance",
+                    level.name
+                );
+            }
+            //the sliders the player can move have to reach the starting values
+            let ranges = &level.ranges;
+            let start = &level.start;
+            for (part, (min, max), value) in [
+                (Part::Resistor, ranges.resistance, start.resistance),
+                (Part::Inductor, ranges.inductance, start.inductance),
+                (Part::Capacitor, ranges.capacitance, start.capacitance),
+                (Part::Charge, ranges.startcharge, start.startcharge),
+                (Part::Current, ranges.startcurrent, start.startcurrent),
+            ] {
+                if level.allows(part) {
+                    assert!(
+                        min <= value && value <= max,
+                        "'{}' starts its {:?} outside its slider",
+                        level.name,
+                        part
+                    );
+                }
+            }
+        }
+        assert_eq!(LevelBook::built_in().0.len(), levels.len());
+    }
+
+    #[test]
+    fn levels_unlock_in_order() {
+        let mut progress = LevelProgress::default();
+        assert!(progress.is_unlocked(0));
+        assert!(!progress.is_unlocked(1));
+
+        assert!(progress.record(0, 1));
+        assert!(progress.is_unlocked(1));
+        assert!(!progress.is_unlocked(2));
+    }
+
+    #[test]
+    fn only_better_ratings_are_recorded() {
+        let mut progress = LevelProgress::default();
+        assert!(!progress.record(0, 0));
+        assert!(progress.record(0, 2));
+        assert!(!progress.record(0, 2));
+        assert!(!progress.record(0, 1));
+        assert_eq!(progress.stars_for(0), 2);
+        assert!(progress.record(0, 3));
+        assert_eq!(progress.stars_for(0), 3);
+
+        //a later level can be recorded first, leaving the ones between it unplayed
+        assert!(progress.record(3, 2));
+        assert_eq!(progress.stars, vec![3, 0, 0, 2]);
+        assert!(!progress.is_unlocked(3));
+        assert!(progress.is_unlocked(4));
+        assert_eq!(progress.stars_for(10), 0);
+    }
+}
@@ -1,11 +1,39 @@
 //! deals with the visuals and a part of the game
 
 mod audio;
+mod editor;
 mod effects;
+mod export;
+mod flow;
+mod game;
+mod inspector;
+mod levels;
+mod presets;
+mod readout;
+mod rhythm;
+mod schematic;
+mod scope;
+mod simulation;
 mod sliders;
+mod sonify;
+mod states;
 mod visuals;
 
 pub use audio::*;
+pub use editor::*;
 pub use effects::*;
+pub use export::*;
+pub use flow::*;
+pub use game::*;
+pub use inspector::*;
+pub use levels::*;
+pub use presets::*;
+pub use readout::*;
+pub use rhythm::*;
+pub use schematic::*;
+pub use scope::*;
+pub use simulation::*;
 pub use sliders::*;
+pub use sonify::*;
+pub use states::*;
 pub use visuals::*;
@@ -0,0 +1,247 @@
+//! presets of real circuits for the sandbox, like a radio tuner or a camera flash, along with the
+//! slider ranges and the time scale that fit them
+
+use bevy::prelude::*;
+use bevy_egui::egui::{Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+use std::f64::consts::TAU;
+
+use crate::graphics::{
+    format_si, largest_current, plot_start, si_value_edit, ActiveLevel, AppState, CircuitConstants,
+    CircuitTimer, CircuitTimerMode, Coupling, CurrentTimePlot, DLRCCircuit, SliderRanges,
+    SyncChallenge, DEFAULT_SIGNIFICANT_FIGURES, MIN_CIRCUIT_TIME,
+};
+
+/// This plugin adds the presets and ranges panel to the sandbox.
+pub struct PresetPlugin;
+
+impl Plugin for PresetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AutoFit>().add_system_set(
+            SystemSet::on_update(AppState::Sandbox)
+                .with_system(presets_frame)
+                .with_system(refit_timer.after(presets_frame)),
+        );
+    }
+}
+
+#[derive(Default)]
+/// Whether the timer is fitted again whenever the circuit's values change before it's played
+pub struct AutoFit(pub bool);
+
+/// A circuit with realistic values, that the sandbox can be set to
+pub struct Preset {
+    pub name: &'static str,
+    /// what the circuit is and does, as shown to the player
+    pub description: &'static str,
+    pub constants: CircuitConstants,
+}
+
+/// every preset, in the order they're listed
+pub const PRESETS: [Preset; 3] = [
+    Preset {
+        name: "Radio tuner",
+        description: "The tank circuit of an AM radio, ringing at about 1 MHz: 240 \u{b5}H and \
+                      100 pF, started from 1 V.",
+        constants: CircuitConstants {
+            startcharge: 100e-12,
+            startcurrent: 0.0,
+            resistance: 5.0,
+            inductance: 240e-6,
+            capacitance: 100e-12,
+            emf: 0.0,
+        },
+    },
+    Preset {
+        name: "Camera flash",
+        description: "A 150 \u{b5}F flash capacitor charged to 330 V, dumped through the flash \
+                      tube in well under a millisecond.",
+        constants: CircuitConstants {
+            startcharge: 150e-6 * 330.0,
+            startcurrent: 0.0,
+            resistance: 2.0,
+            inductance: 50e-6,
+            capacitance: 150e-6,
+            emf: 0.0,
+        },
+    },
+    Preset {
+        name: "Car ignition coil",
+        description: "The primary of an ignition coil, 8 mH with a 0.25 \u{b5}F condenser charged \
+                      to 12 V, ringing at a few kHz.",
+        constants: CircuitConstants {
+            startcharge: 0.25e-6 * 12.0,
+            startcurrent: 0.0,
+            resistance: 1.5,
+            inductance: 8e-3,
+            capacitance: 0.25e-6,
+            emf: 0.0,
+        },
+    },
+];
+
+/// Fits the timer to the first circuit's natural period and largest current
+fn fit_timer(timer: &mut CircuitTimer, circuits: &[&DLRCCircuit]) {
+    if let Some(circuit) = circuits.first() {
+        let period = TAU / circuit.0.circuit.natural_angular_frequency();
+        timer.fit_to(period, largest_current(circuits.iter().copied()));
+    }
+}
+
+/// Fits the timer again whenever the circuit's values change before it's played, while it's
+/// fitted automatically
+fn refit_timer(
+    auto_fit: Res<AutoFit>,
+    active: Res<ActiveLevel>,
+    challenge: Res<SyncChallenge>,
+    mut timer: ResMut<CircuitTimer>,
+    query_circs: Query<&DLRCCircuit>,
+    mut last_values: Local<Vec<[f64; 5]>>,
+) {
+    let values: Vec<[f64; 5]> = query_circs
+        .iter()
+        .map(|circuit| {
+            let rlc = &circuit.0.circuit;
+            [
+                rlc.resistance,
+                rlc.inductance,
+                rlc.capacitance,
+                rlc.startcharge,
+                rlc.startcurrent,
+            ]
+        })
+        .collect();
+    if values == *last_values && !auto_fit.is_changed() {
+        return;
+    }
+    *last_values = values;
+    //the challenge's target and the editor's circuits are made for the usual time scale
+    if auto_fit.0
+        && timer.time <= MIN_CIRCUIT_TIME
+        && challenge.target.is_none()
+        && active.built.is_none()
+    {
+        let circuits: Vec<&DLRCCircuit> = query_circs.iter().collect();
+        fit_timer(&mut timer, &circuits);
+    }
+}
+
+/// Adds a row to change the smallest and largest value of a slider
+///
+/// # Arguments
+/// * 'ui' - the grid to add the row to
+/// * 'name' - the slider's name
+/// * 'unit' - the unit's symbol
+/// * 'range' - the smallest and largest value of the slider
+/// * 'lowest' - the lowest the smallest value can go
+fn range_row(ui: &mut egui::Ui, name: &str, unit: &str, range: &mut (f64, f64), lowest: f64) {
+    ui.label(name);
+    let (min, max) = range;
+    si_value_edit(ui, (name, "min"), min, lowest..=*max, unit);
+    si_value_edit(ui, (name, "max"), max, *min..=f64::MAX, unit);
+    ui.end_row();
+}
+
+/// creates the panel to pick a preset, change the slider ranges and fit the time scale
+fn presets_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut active: ResMut<ActiveLevel>,
+    mut timer: ResMut<CircuitTimer>,
+    mut auto_fit: ResMut<AutoFit>,
+    mut challenge: ResMut<SyncChallenge>,
+    mut query_circs: Query<(Entity, &mut DLRCCircuit, &mut CurrentTimePlot)>,
+    query_couplings: Query<&Coupling>,
+) {
+    //a circuit from the editor keeps the values of its own parts
+    if active.built.is_some() {
+        return;
+    }
+    egui::Window::new("Presets")
+        .title_bar(false)
+        .default_pos([800.0, 440.0])
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::CollapsingHeader::new("Presets and ranges")
+                .default_open(false)
+                .show(ui, |ui| {
+                    let mut picked = None;
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        for preset in PRESETS.iter() {
+                            if ui
+                                .button(preset.name)
+                                .on_hover_text(preset.description)
+                                .clicked()
+                            {
+                                picked = Some(preset);
+                            }
+                        }
+                    });
+                    if let Some(preset) = picked {
+                        active.level.start = preset.constants;
+                        active.level.ranges = SliderRanges::around(&preset.constants);
+                        //coupled circuits start uncharged, as when they're added
+                        let coupled: Vec<Entity> = query_couplings
+                            .iter()
+                            .map(|coupling| coupling.circuits.1)
+                            .collect();
+                        for (entity, mut circuit, mut plot) in query_circs.iter_mut() {
+                            let mut constants = preset.constants;
+                            if coupled.contains(&entity) {
+                                constants.startcharge = 0.0;
+                                constants.startcurrent = 0.0;
+                            }
+                            //the player's forced step is kept, though the readout may warn
+                            let forced_substep = circuit.0.circuit.forced_substep;
+                            *circuit = DLRCCircuit(constants.light_calculator());
+                            circuit.0.circuit.forced_substep = forced_substep;
+                            plot.0 = plot_start(&circuit.0.circuit);
+                        }
+                        timer.time = MIN_CIRCUIT_TIME;
+                        timer.mode = CircuitTimerMode::Pause;
+                        let circuits: Vec<&DLRCCircuit> =
+                            query_circs.iter().map(|(_, circuit, _)| circuit).collect();
+                        fit_timer(&mut timer, &circuits);
+                        auto_fit.0 = true;
+                        //the challenge's target was made for the usual time scale
+                        challenge.quit();
+                    }
+                    ui.separator();
+
+                    ui.label(RichText::new("slider ranges").color(Color32::WHITE));
+                    let ranges = &mut active.level.ranges;
+                    egui::Grid::new("slider ranges").show(ui, |ui| {
+                        range_row(ui, "R", "\u{3a9}", &mut ranges.resistance, 0.0);
+                        range_row(ui, "L", "H", &mut ranges.inductance, 0.0);
+                        range_row(ui, "C", "F", &mut ranges.capacitance, 0.0);
+                        range_row(ui, "starting Q", "C", &mut ranges.startcharge, 0.0);
+                        //the current can start flowing either way
+                        range_row(ui, "starting I", "A", &mut ranges.startcurrent, f64::MIN);
+                    });
+                    ui.separator();
+
+                    ui.label(RichText::new("time scale").color(Color32::WHITE));
+                    ui.label(format!(
+                        "{} per frame, ending at {}",
+                        format_si(timer.delta_t, "s", DEFAULT_SIGNIFICANT_FIGURES),
+                        format_si(timer.end, "s", DEFAULT_SIGNIFICANT_FIGURES)
+                    ));
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        if ui.button("Fit to circuit").clicked() {
+                            let circuits: Vec<&DLRCCircuit> =
+                                query_circs.iter().map(|(_, circuit, _)| circuit).collect();
+                            fit_timer(&mut timer, &circuits);
+                            auto_fit.0 = true;
+                            challenge.quit();
+                        }
+                        if ui
+                            .add_enabled(timer.is_rescaled(), egui::Button::new("Usual scale"))
+                            .clicked()
+                        {
+                            timer.reset_scale();
+                            auto_fit.0 = false;
+                        }
+                    });
+                    ui.checkbox(&mut auto_fit.0, "fit when the circuit changes")
+                        .on_hover_text("until the circuit is played");
+                });
+        });
+}
@@ -0,0 +1,349 @@
+//! the numeric readout, and writing and reading values with SI prefixes like mA, µF and mH
+
+use bevy::prelude::*;
+use bevy_egui::egui::{Align2, Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+use std::hash::Hash;
+use std::ops::RangeInclusive;
+
+use crate::graphics::{simulation_shown, CircuitTimer, DLRCCircuit};
+
+/// This plugin adds the heads-up readout of every circuit's live values.
+pub struct ReadoutPlugin;
+
+impl Plugin for ReadoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReadoutSettings>()
+            .add_system(readout_frame.with_run_criteria(simulation_shown));
+    }
+}
+
+/// how many significant figures values are written with, unless the player picks otherwise
+pub const DEFAULT_SIGNIFICANT_FIGURES: usize = 3;
+/// the most significant figures the readout can show
+const MAX_SIGNIFICANT_FIGURES: usize = 8;
+
+/// the prefixes, from pico to giga, with the power of ten each stands for
+const PREFIXES: [(&str, i32); 8] = [
+    ("p", -12),
+    ("n", -9),
+    ("\u{b5}", -6),
+    ("m", -3),
+    ("", 0),
+    ("k", 3),
+    ("M", 6),
+    ("G", 9),
+];
+
+/// Writes a value with the SI prefix that keeps it between 1 and 1000
+///
+/// # Arguments
+/// * 'value' - the value, in the base unit
+/// * 'unit' - the unit's symbol, written after the prefix
+/// * 'significant_figures' - how many figures the value is rounded to
+///
+/// # Returns
+/// The value, like "4.70 mH"
+pub fn format_si(value: f64, unit: &str, significant_figures: usize) -> String {
+    let figures = significant_figures.max(1) as i32;
+    if value == 0.0 || !value.is_finite() {
+        return format!("{:.*} {}", (figures - 1) as usize, value, unit);
+    }
+    //rounded first, so 999.96 with three figures is written as 1.00 k rather than 1000
+    let magnitude = value.abs().log10().floor() as i32;
+    let step = 10f64.powi(magnitude - figures + 1);
+    let rounded = (value / step).round() * step;
+    let magnitude = rounded.abs().log10().floor() as i32;
+
+    let (prefix, power) = PREFIXES
+        .iter()
+        .rev()
+        .find(|(_, power)| magnitude >= *power)
+        .unwrap_or(&PREFIXES[0]);
+    let scaled = rounded / 10f64.powi(*power);
+    let decimals = (figures - 1 - (magnitude - power)).max(0) as usize;
+    format!("{:.*} {}{}", decimals, scaled, prefix, unit)
+}
+
+/// Reads a value written with an optional SI prefix and unit
+///
+/// # Arguments
+/// * 'text' - what was typed, like "4.7 mH", "470u" or "2e-3"
+/// * 'unit' - the unit's symbol, which may be left off
+///
+/// # Returns
+/// The value in the base unit, or none if it couldn't be read
+pub fn parse_si(text: &str, unit: &str) -> Option<f64> {
+    let mut text = text.trim();
+    let mut units = vec![unit];
+    if unit == "\u{3a9}" {
+        units.extend(["ohms", "ohm", "Ohms", "Ohm"]);
+    }
+    if let Some(stripped) = units.iter().find_map(|unit| text.strip_suffix(unit)) {
+        text = stripped.trim_end();
+    }
+    //u and the greek mu are accepted for micro, as the micro sign is hard to type
+    let (number, power) = match PREFIXES
+        .iter()
+        .chain([("u", -6), ("\u{3bc}", -6)].iter())
+        .find(|(prefix, _)| !prefix.is_empty() && text.ends_with(prefix))
+    {
+        Some((prefix, power)) => (&text[..text.len() - prefix.len()], *power),
+        None => (text, 0),
+    };
+    let value: f64 = number.trim().parse().ok()?;
+    value.is_finite().then(|| value * 10f64.powi(power))
+}
+
+/// Adds a box showing a value with its SI prefix, where a new value can be typed
+///
+/// The typed text is kept while the box has focus, and read when it loses it.
+///
+/// # Arguments
+/// * 'ui' - the ui to add the box to
+/// * 'id_source' - what the box is remembered by, unique within the ui
+/// * 'value' - the value shown and changed
+/// * 'range' - the values a typed value is clamped to
+/// * 'unit' - the unit's symbol
+///
+/// # Returns
+/// Whether a new value was typed
+pub fn si_value_edit(
+    ui: &mut egui::Ui,
+    id_source: impl Hash,
+    value: &mut f64,
+    range: RangeInclusive<f64>,
+    unit: &str,
+) -> bool {
+    let id = egui::Id::new(id_source);
+    let mut text = ui
+        .data()
+        .get_temp::<String>(id)
+        .unwrap_or_else(|| format_si(*value, unit, DEFAULT_SIGNIFICANT_FIGURES));
+    let response = ui.add(egui::TextEdit::singleline(&mut text).desired_width(70.0));
+    if response.has_focus() {
+        ui.data().insert_temp(id, text);
+        return false;
+    }
+    ui.data().remove::<String>(id);
+    if !response.lost_focus() {
+        return false;
+    }
+    match parse_si(&text, unit) {
+        Some(typed) => {
+            *value = typed.clamp(*range.start(), *range.end());
+            true
+        }
+        None => false,
+    }
+}
+
+/// How the readout is written
+pub struct ReadoutSettings {
+    pub significant_figures: usize,
+}
+
+impl Default for ReadoutSettings {
+    fn default() -> Self {
+        Self {
+            significant_figures: DEFAULT_SIGNIFICANT_FIGURES,
+        }
+    }
+}
+
+/// creates the readout of the time and every circuit's current, charge, capacitor voltage, bulb
+/// power and integration step, where the step can be forced
+fn readout_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut settings: ResMut<ReadoutSettings>,
+    time: Res<CircuitTimer>,
+    mut query_circs: Query<&mut DLRCCircuit>,
+) {
+    egui::Window::new("Readout")
+        .anchor(Align2::CENTER_TOP, [0.0, 10.0])
+        .title_bar(false)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            let figures = settings.significant_figures;
+            let circuit_count = query_circs.iter().count();
+            let reading = |text: String| RichText::new(text).monospace().color(Color32::WHITE);
+            egui::Grid::new("readout").show(ui, |ui| {
+                ui.label("t");
+                ui.label(reading(format_si(time.time, "s", figures)));
+                ui.end_row();
+                for (index, circuit) in query_circs.iter().enumerate() {
+                    if circuit_count > 1 {
+                        ui.label(format!("circuit {}", index + 1));
+                        ui.end_row();
+                    }
+                    let rlc = &circuit.0.circuit;
+                    for (name, value, unit) in [
+                        ("I", rlc.current(), "A"),
+                        ("Q", rlc.charge(), "C"),
+                        ("V_C", rlc.charge() / rlc.capacitance, "V"),
+                        ("P", circuit.0.lightbulb_power(), "W"),
+                    ] {
+                        ui.label(name);
+                        ui.label(reading(format_si(value, unit, figures)));
+                        ui.end_row();
+                    }
+                    //the step the integrator actually takes within each frame's delta_t
+                    let substep = rlc.effective_substep(time.delta_t);
+                    let clamped = rlc.is_substep_clamped(time.delta_t);
+                    ui.label("dt");
+                    if rlc.is_stable(substep) && !clamped {
+                        ui.label(reading(format_si(substep, "s", figures)));
+                    } else {
+                        let mut hover = format!(
+                            "the amplitude drifts {:.1}% from the true one every period",
+                            rlc.step_drift(substep) * 100.0
+                        );
+                        if clamped {
+                            hover = format!(
+                                "the circuit needs too many steps a frame, so they were \
+                                 lengthened and {}",
+                                hover
+                            );
+                        }
+                        ui.label(
+                            RichText::new(format!("{} unstable", format_si(substep, "s", figures)))
+                                .monospace()
+                                .color(Color32::LIGHT_RED),
+                        )
+                        .on_hover_text(hover);
+                    }
+                    ui.end_row();
+                }
+            });
+            egui::CollapsingHeader::new("Timestep")
+                .default_open(false)
+                .show(ui, |ui| {
+                    for (index, mut circuit) in query_circs.iter_mut().enumerate() {
+                        let rlc = &mut circuit.0.circuit;
+                        let automatic = rlc.automatic_substep();
+                        ui.horizontal(|ui| {
+                            if circuit_count > 1 {
+                                ui.label(format!("circuit {}", index + 1));
+                            }
+                            let mut forced = rlc.forced_substep.is_some();
+                            if ui.checkbox(&mut forced, "force step").changed() {
+                                rlc.forced_substep = forced.then_some(automatic.min(time.delta_t));
+                            }
+                            match &mut rlc.forced_substep {
+                                Some(substep) => {
+                                    si_value_edit(
+                                        ui,
+                                        ("forced step", index),
+                                        substep,
+                                        0.0..=time.delta_t,
+                                        "s",
+                                    );
+                                }
+                                None => {
+                                    ui.label(format!(
+                                        "picked from the circuit: {}",
+                                        format_si(automatic, "s", figures)
+                                    ));
+                                }
+                            }
+                        });
+                    }
+                });
+            ui.add(
+                egui::Slider::new(
+                    &mut settings.significant_figures,
+                    1..=MAX_SIGNIFICANT_FIGURES,
+                )
+                .text("sig. figs"),
+            );
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether two values agree to a fraction of their size
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance * a.abs().max(b.abs())
+    }
+
+    #[test]
+    fn values_get_the_prefix_that_fits() {
+        assert_eq!(format_si(4.7e-3, "H", 3), "4.70 mH");
+        assert_eq!(format_si(100e-12, "F", 3), "100 pF");
+        assert_eq!(format_si(240e-6, "H", 3), "240 \u{b5}H");
+        assert_eq!(format_si(-2.5e-6, "A", 3), "-2.50 \u{b5}A");
+        assert_eq!(format_si(12345.0, "\u{3a9}", 3), "12.3 k\u{3a9}");
+        assert_eq!(format_si(1.5e9, "Hz", 2), "1.5 GHz");
+        assert_eq!(format_si(0.0, "A", 3), "0.00 A");
+    }
+
+    #[test]
+    fn rounding_rolls_over_to_the_next_prefix() {
+        assert_eq!(format_si(999.96, "\u{3a9}", 3), "1.00 k\u{3a9}");
+        assert_eq!(format_si(0.99996, "V", 3), "1.00 V");
+        assert_eq!(format_si(999.4, "\u{3a9}", 3), "999 \u{3a9}");
+    }
+
+    #[test]
+    fn values_below_pico_stay_in_pico() {
+        assert_eq!(format_si(1e-15, "F", 3), "0.00100 pF");
+    }
+
+    #[test]
+    fn prefixes_and_units_are_read() {
+        assert!(close(parse_si("4.7 mH", "H").unwrap(), 4.7e-3, 1e-12));
+        assert!(close(parse_si("4.7m", "H").unwrap(), 4.7e-3, 1e-12));
+        assert!(close(parse_si(" 2e-3 ", "H").unwrap(), 2e-3, 1e-12));
+        assert!(close(parse_si("100 pF", "F").unwrap(), 100e-12, 1e-12));
+        //u, the micro sign and the greek mu all mean micro
+        for micro in ["470u", "470 \u{b5}F", "470\u{3bc}F"] {
+            assert!(
+                close(parse_si(micro, "F").unwrap(), 470e-6, 1e-12),
+                "{}",
+                micro
+            );
+        }
+        for ohms in [
+            "2.2 k\u{3a9}",
+            "2.2k ohm",
+            "2.2 kOhms",
+            "2.2kohms",
+            "2.2 kOhm",
+        ] {
+            assert!(
+                close(parse_si(ohms, "\u{3a9}").unwrap(), 2200.0, 1e-12),
+                "{}",
+                ohms
+            );
+        }
+    }
+
+    #[test]
+    fn nonsense_is_refused() {
+        assert_eq!(parse_si("", "H"), None);
+        assert_eq!(parse_si("mH", "H"), None);
+        assert_eq!(parse_si("four", "H"), None);
+        assert_eq!(parse_si("inf", "H"), None);
+        assert_eq!(parse_si("NaN F", "F"), None);
+    }
+
+    #[test]
+    fn written_values_read_back() {
+        for unit in ["F", "H", "\u{3a9}", "A"] {
+            for power in -14..11 {
+                for mantissa in [1.0, 2.2, 4.7, 9.99] {
+                    let value = mantissa * 10f64.powi(power);
+                    for (figures, tolerance) in [(3, 5e-3), (8, 1e-7)] {
+                        let text = format_si(value, unit, figures);
+                        let read = parse_si(&text, unit).unwrap();
+                        assert!(close(read, value, tolerance), "{} read as {}", text, read);
+                        let negative = parse_si(&format_si(-value, unit, figures), unit).unwrap();
+                        assert!(close(negative, -value, tolerance));
+                    }
+                }
+            }
+        }
+    }
+}
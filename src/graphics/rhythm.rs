@@ -0,0 +1,576 @@
+//! the rhythm game, where the player tunes L and C until the current's peaks land on the beat
+
+use bevy::{audio::AudioSink, prelude::*};
+use bevy_egui::egui::{Align2, Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::{
+    spawn_built_circuit, spawn_pulse_circle, ActiveLevel, AppState, AudioAssets, AudioRole,
+    AudioSettings, Channel, CircuitConstants, CircuitTimer, CircuitTimerMode, CurrentPeak,
+    DLRCCircuit, Level, MusicController, Part, ReloadAudio, RunSummary, SliderRanges,
+    MAX_CIRCUIT_TIME, MIN_CIRCUIT_TIME,
+};
+
+/// This plugin adds the rhythm mode, which judges every beat as a hit or a miss.
+pub struct RhythmPlugin;
+
+impl Plugin for RhythmPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RhythmRun>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Rhythm)
+                    .with_system(spawn_built_circuit)
+                    .with_system(start_rhythm_run),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Rhythm)
+                    .with_system(judge_beats)
+                    .with_system(play_metronome)
+                    .with_system(start_rhythm_music.after(judge_beats))
+                    .with_system(finish_rhythm_run),
+            )
+            .add_system_set(
+                SystemSet::on_in_stack_update(AppState::Rhythm).with_system(rhythm_frame),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Rhythm).with_system(end_rhythm_run));
+    }
+}
+
+/* BEAT MAPS */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// The tempo of a music file, which the beat maps are timed from
+///
+/// Both are measured by opening the file in an audio editor: the tempo is read off a beat grid
+/// lined up with the drums, or by counting the beats in the loop and dividing by its length, and
+/// the first beat is where the first drum hit starts.
+pub struct MusicTempo {
+    /// beats per real minute
+    pub bpm: f64,
+    /// how far into the file its first beat is, in real seconds
+    pub first_beat: f64,
+}
+
+impl Default for MusicTempo {
+    /// the tempo of the default loop, `taishi-reverie-loop.ogg`
+    fn default() -> Self {
+        Self {
+            bpm: 96.0,
+            first_beat: 0.0,
+        }
+    }
+}
+
+/// how many simulated seconds pass every real second in rhythm mode, about the sandbox's pace at
+/// 60 frames a second
+pub const RHYTHM_TIME_SCALE: f64 = 6.0;
+
+/// A way of playing along with the music, beats measured on the music's own tempo
+pub struct BeatMap {
+    pub name: &'static str,
+    /// how many of the music's beats pass between consecutive beats of the map
+    pub beats_per_step: f64,
+    /// which beats of each bar are played, repeating
+    pub pattern: &'static [bool],
+}
+
+impl BeatMap {
+    /// the time between consecutive beats, in simulated seconds
+    ///
+    /// # Arguments
+    /// * 'tempo' - the tempo of the music being played along with
+    pub fn interval(&self, tempo: MusicTempo) -> f64 {
+        60.0 / tempo.bpm * self.beats_per_step * RHYTHM_TIME_SCALE
+    }
+
+    /// The simulation time to start the music at, so its beats land half a beat after a multiple
+    /// of the interval
+    ///
+    /// A charged circuit first peaks a quarter period in and then every half period, so this
+    /// leaves a value of LC that hits every beat.
+    ///
+    /// # Arguments
+    /// * 'tempo' - the tempo of the music being played along with
+    pub fn music_start(&self, tempo: MusicTempo) -> f64 {
+        let interval = self.interval(tempo);
+        (0.5 * interval - tempo.first_beat * RHYTHM_TIME_SCALE).rem_euclid(interval)
+    }
+
+    /// Lists the time of every played beat before the simulation ends
+    ///
+    /// # Arguments
+    /// * 'tempo' - the tempo of the music being played along with
+    pub fn beat_times(&self, tempo: MusicTempo) -> Vec<f64> {
+        let interval = self.interval(tempo);
+        let first = self.music_start(tempo) + tempo.first_beat * RHYTHM_TIME_SCALE;
+        (0..)
+            .map(|beat| (beat, first + beat as f64 * interval))
+            .take_while(|&(_, time)| time < MAX_CIRCUIT_TIME)
+            .filter(|&(beat, _)| self.pattern[beat % self.pattern.len()])
+            .map(|(_, time)| time)
+            .collect()
+    }
+}
+
+/// the beat maps to pick from, slowest first
+pub const BEAT_MAPS: [BeatMap; 3] = [
+    BeatMap {
+        name: "Reverie (slow)",
+        beats_per_step: 4.0,
+        pattern: &[true],
+    },
+    BeatMap {
+        name: "Reverie",
+        beats_per_step: 2.0,
+        pattern: &[true],
+    },
+    BeatMap {
+        name: "Reverie (syncopated)",
+        beats_per_step: 1.0,
+        pattern: &[true, true, false, true],
+    },
+];
+
+/// how far off a beat, as a fraction of the beat interval, a peak can land and still hit
+const HIT_WINDOW: f64 = 0.15;
+
+/// how far off a beat, as a fraction of the beat interval, a peak can land and be perfect
+const PERFECT_WINDOW: f64 = 0.05;
+
+/* RHYTHM RESOURCE */
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How well a peak landed on a beat
+pub enum Judgement {
+    /// the peak was this many simulated seconds late, or early if negative
+    Perfect(f64),
+    Good(f64),
+    Miss,
+}
+
+#[derive(Default)]
+/// The progress of the current rhythm run
+pub struct RhythmRun {
+    /// the position of the chosen map in `BEAT_MAPS`
+    pub map: usize,
+    /// the tempo of the music, taken from the audio settings as the run starts
+    pub tempo: MusicTempo,
+    /// whether rhythm mode is being played
+    pub playing: bool,
+    /// the simulation times of every peak so far
+    pub peaks: Vec<f64>,
+    /// the judgement of each beat that has passed, in order
+    pub judgements: Vec<Judgement>,
+    /// the simulation time the metronome last ticked up to
+    last_tick_time: f64,
+    /// the simulation time beats were last judged at
+    last_judge_time: f64,
+    /// whether the music has been restarted in time with the beat map
+    music_started: bool,
+}
+
+impl RhythmRun {
+    /// Clears the run, keeping the chosen map
+    pub fn restart(&mut self) {
+        self.peaks.clear();
+        self.judgements.clear();
+        self.last_tick_time = MIN_CIRCUIT_TIME;
+        self.last_judge_time = MIN_CIRCUIT_TIME;
+        self.music_started = false;
+    }
+
+    /// The beat map being played
+    pub fn beat_map(&self) -> Option<&'static BeatMap> {
+        self.playing.then(|| &BEAT_MAPS[self.map])
+    }
+
+    /// Lists the time of every played beat of the map being played, none if rhythm mode isn't
+    pub fn beat_times(&self) -> Vec<f64> {
+        self.beat_map()
+            .map(|beat_map| beat_map.beat_times(self.tempo))
+            .unwrap_or_default()
+    }
+
+    /// Counts the beats that were hit, perfectly or not
+    pub fn hits(&self) -> usize {
+        self.judgements
+            .iter()
+            .filter(|judgement| **judgement != Judgement::Miss)
+            .count()
+    }
+}
+
+/// Judges a beat against the peaks closest to it
+///
+/// # Arguments
+/// * 'beat' - the simulation time of the beat
+/// * 'interval' - the time between beats
+/// * 'peaks' - the simulation times of the peaks
+///
+/// # Returns
+/// The judgement of the beat
+pub fn judge_beat(beat: f64, interval: f64, peaks: &[f64]) -> Judgement {
+    let closest = peaks
+        .iter()
+        .map(|peak| peak - beat)
+        .min_by(|a, b| a.abs().total_cmp(&b.abs()));
+    match closest {
+        Some(offset) if offset.abs() <= PERFECT_WINDOW * interval => Judgement::Perfect(offset),
+        Some(offset) if offset.abs() <= HIT_WINDOW * interval => Judgement::Good(offset),
+        _ => Judgement::Miss,
+    }
+}
+
+/// The level rhythm mode is played on, where only L and C can change
+pub fn rhythm_level() -> Level {
+    Level {
+        name: "Rhythm".to_string(),
+        description: "Tune L and C so the current peaks on every beat.".to_string(),
+        target: None,
+        start: CircuitConstants {
+            startcharge: 10.0,
+            startcurrent: 0.0,
+            resistance: 0.02,
+            inductance: 4.0,
+            capacitance: 6.0,
+            emf: 0.0,
+        },
+        allowed: vec![Part::Inductor, Part::Capacitor],
+        ranges: SliderRanges::default(),
+        par: 0.0,
+    }
+}
+
+/// Enters rhythm mode on the last picked beat map, with the simulation following the real clock
+/// so the beats keep time with the music
+fn start_rhythm_run(
+    mut run: ResMut<RhythmRun>,
+    mut circuit_timer: ResMut<CircuitTimer>,
+    audio_settings: Res<AudioSettings>,
+) {
+    run.playing = true;
+    run.tempo = audio_settings.music_tempo;
+    run.restart();
+    circuit_timer.real_time_scale = Some(RHYTHM_TIME_SCALE);
+}
+
+/// Leaves rhythm mode, letting the music play on even if the run was paused
+fn end_rhythm_run(
+    mut run: ResMut<RhythmRun>,
+    mut circuit_timer: ResMut<CircuitTimer>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    music_controller: Res<MusicController>,
+) {
+    run.playing = false;
+    circuit_timer.real_time_scale = None;
+    music_controller.set_music_paused(&audio_sinks, false);
+}
+
+/// Restarts the music once the run reaches the beat map's `music_start`, so the music's beats
+/// land on the map's, and from then on pauses the music whenever the timer is paused so they
+/// stay together
+fn start_rhythm_music(
+    circuit_timer: Res<CircuitTimer>,
+    mut run: ResMut<RhythmRun>,
+    mut reload_writer: EventWriter<ReloadAudio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    music_controller: Res<MusicController>,
+) {
+    let beat_map = match run.beat_map() {
+        Some(beat_map) => beat_map,
+        None => return,
+    };
+    let playing = circuit_timer.mode == CircuitTimerMode::Play;
+    if run.music_started {
+        music_controller.set_music_paused(&audio_sinks, !playing);
+    } else if playing && circuit_timer.time >= beat_map.music_start(run.tempo) {
+        reload_writer.send(ReloadAudio);
+        run.music_started = true;
+    }
+}
+
+/// Rates a run by the fraction of beats hit
+///
+/// # Returns
+/// From 0 to 3 stars
+pub fn accuracy_rating(hits: usize, beats: usize) -> u8 {
+    match hits as f64 / beats.max(1) as f64 {
+        a if a >= 0.9 => 3,
+        a if a >= 0.7 => 2,
+        a if a >= 0.4 => 1,
+        _ => 0,
+    }
+}
+
+/// Shows the results once the simulation has ended and every beat is judged
+fn finish_rhythm_run(
+    circuit_timer: Res<CircuitTimer>,
+    run: Res<RhythmRun>,
+    mut summary: ResMut<RunSummary>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let beat_map = match run.beat_map() {
+        Some(beat_map) => beat_map,
+        None => return,
+    };
+    let beats = run.beat_times().len();
+    if circuit_timer.time < MAX_CIRCUIT_TIME || run.judgements.len() < beats {
+        return;
+    }
+
+    let offsets: Vec<f64> = run
+        .judgements
+        .iter()
+        .filter_map(|judgement| match judgement {
+            Judgement::Perfect(offset) | Judgement::Good(offset) => Some(offset.abs()),
+            Judgement::Miss => None,
+        })
+        .collect();
+    let mut details = vec![
+        format!("song: {}", beat_map.name),
+        format!("hits: {} / {}", run.hits(), beats),
+    ];
+    if !offsets.is_empty() {
+        details.push(format!(
+            "average offset: {:.2} (s)",
+            offsets.iter().sum::<f64>() / offsets.len() as f64
+        ));
+    }
+    *summary = RunSummary {
+        title: "Rhythm".to_string(),
+        stars: accuracy_rating(run.hits(), beats),
+        details,
+        retry: AppState::Rhythm,
+    };
+    let _ = state.overwrite_set(AppState::Results);
+}
+
+/// Collects peaks and judges every beat whose hit window has closed
+fn judge_beats(
+    mut commands: Commands,
+    circuit_timer: Res<CircuitTimer>,
+    mut run: ResMut<RhythmRun>,
+    mut peak_reader: EventReader<CurrentPeak>,
+) {
+    let beat_map = match run.beat_map() {
+        Some(beat_map) => beat_map,
+        None => return,
+    };
+    //the timer only goes backwards when it's reset
+    if circuit_timer.time < run.last_judge_time {
+        run.restart();
+    }
+    run.last_judge_time = circuit_timer.time;
+
+    let interval = beat_map.interval(run.tempo);
+    let beats = run.beat_times();
+    for peak in peak_reader.iter() {
+        run.peaks.push(peak.time);
+        //a second ring rewards peaks landing right on a beat
+        if let (Judgement::Perfect(_), Some(light)) =
+            (judge_beat(peak.time, interval, &beats), peak.light)
+        {
+            spawn_pulse_circle(&mut commands, light, 25.0);
+        }
+    }
+
+    while let Some(&beat) = beats.get(run.judgements.len()) {
+        if beat + HIT_WINDOW * interval > circuit_timer.time {
+            break;
+        }
+        let judgement = judge_beat(beat, interval, &run.peaks);
+        run.judgements.push(judgement);
+    }
+}
+
+/// Ticks on every beat, so the player can hear the peaks line up
+fn play_metronome(
+    audio_assets: Res<AudioAssets>,
+    audio_settings: Res<AudioSettings>,
+    audio: Res<Audio>,
+    circuit_timer: Res<CircuitTimer>,
+    mut run: ResMut<RhythmRun>,
+) {
+    let last_time = run.last_tick_time.min(circuit_timer.time);
+    if run
+        .beat_times()
+        .iter()
+        .any(|&beat| last_time < beat && beat <= circuit_timer.time)
+    {
+        if let Some(tick) = audio_assets.handle(AudioRole::Pulse) {
+            audio.play_with_settings(
+                tick,
+                PlaybackSettings::ONCE
+                    .with_speed(2.0)
+                    .with_volume(0.3 * audio_settings.mixer.gain(Channel::Effects)),
+            );
+        }
+    }
+    run.last_tick_time = circuit_timer.time;
+}
+
+/// creates a window to pick a beat map and follow the score
+fn rhythm_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut run: ResMut<RhythmRun>,
+    mut state: ResMut<State<AppState>>,
+    mut active: ResMut<ActiveLevel>,
+    query_circs: Query<&DLRCCircuit>,
+) {
+    let beat_map = match run.beat_map() {
+        Some(beat_map) => beat_map,
+        None => return,
+    };
+    egui::Window::new("Rhythm")
+        .title_bar(false)
+        .anchor(Align2::RIGHT_TOP, [-50.0, 50.0])
+        .fixed_size([200.0, 100.0])
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.heading("Rhythm");
+            ui.label(&active.level.description);
+            let mut map = run.map;
+            egui::ComboBox::from_label("song")
+                .selected_text(beat_map.name)
+                .show_ui(ui, |ui| {
+                    for (index, beat_map) in BEAT_MAPS.iter().enumerate() {
+                        ui.selectable_value(&mut map, index, beat_map.name);
+                    }
+                });
+            if map != run.map {
+                run.map = map;
+                run.restart();
+            }
+
+            let beat_map = &BEAT_MAPS[map];
+            ui.label(format!(
+                "beat every {:.2} (s), {:.0} bpm",
+                beat_map.interval(run.tempo),
+                run.tempo.bpm / beat_map.beats_per_step
+            ));
+            if let Some(dlcc) = query_circs.iter().next() {
+                let circuit = &dlcc.0.circuit;
+                ui.label(format!(
+                    "peaks every \u{03C0}\u{221A}(LC) = {:.2} (s)",
+                    std::f64::consts::PI * (circuit.inductance * circuit.capacitance).sqrt()
+                ));
+            }
+
+            let judged = run.judgements.len();
+            ui.label(format!(
+                "hits: {} / {} ({:.0}%)",
+                run.hits(),
+                judged,
+                100.0 * run.hits() as f64 / judged.max(1) as f64
+            ));
+            for judgement in run.judgements.iter().rev().take(5) {
+                let (text, color) = match judgement {
+                    Judgement::Perfect(offset) => {
+                        (format!("perfect {:+.2} (s)", offset), Color32::GOLD)
+                    }
+                    Judgement::Good(offset) => {
+                        (format!("good {:+.2} (s)", offset), Color32::LIGHT_GREEN)
+                    }
+                    Judgement::Miss => ("miss".to_string(), Color32::LIGHT_RED),
+                };
+                ui.label(RichText::new(text).color(color));
+            }
+            if ui.button("Back to sandbox").clicked() {
+                *active = ActiveLevel::sandbox();
+                let _ = state.overwrite_replace(AppState::Sandbox);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a map with a beat every 2.5 simulated seconds at the default tempo
+    const STEADY: BeatMap = BeatMap {
+        name: "steady",
+        beats_per_step: 1.0,
+        pattern: &[true],
+    };
+
+    #[test]
+    fn peaks_are_judged_by_the_closest_one() {
+        let interval = 10.0;
+        assert_eq!(judge_beat(5.0, interval, &[]), Judgement::Miss);
+        assert_eq!(
+            judge_beat(5.0, interval, &[0.0, 5.25, 9.0]),
+            Judgement::Perfect(0.25)
+        );
+        assert!(matches!(
+            judge_beat(5.0, interval, &[3.8, 8.0]),
+            Judgement::Good(offset) if (offset + 1.2).abs() < 1e-9
+        ));
+        //on the edges of the windows
+        assert_eq!(judge_beat(5.0, interval, &[5.5]), Judgement::Perfect(0.5));
+        assert_eq!(judge_beat(5.0, interval, &[6.5]), Judgement::Good(1.5));
+        assert_eq!(judge_beat(5.0, interval, &[6.6]), Judgement::Miss);
+    }
+
+    #[test]
+    fn beats_fall_half_an_interval_after_each_multiple() {
+        let tempo = MusicTempo::default();
+        let interval = STEADY.interval(tempo);
+        assert!((interval - 60.0 / 96.0 * RHYTHM_TIME_SCALE).abs() < 1e-12);
+        assert!((STEADY.music_start(tempo) - 0.5 * interval).abs() < 1e-12);
+
+        let beats = STEADY.beat_times(tempo);
+        assert_eq!(
+            beats.len(),
+            (MAX_CIRCUIT_TIME / interval - 0.5).ceil() as usize
+        );
+        for (k, beat) in beats.iter().enumerate() {
+            assert!((beat - (k as f64 + 0.5) * interval).abs() < 1e-9);
+        }
+        assert!(beats.last().unwrap() < &MAX_CIRCUIT_TIME);
+    }
+
+    #[test]
+    fn first_beat_moves_the_music_but_not_the_beats() {
+        let late = MusicTempo {
+            bpm: 96.0,
+            first_beat: 0.3,
+        };
+        let interval = STEADY.interval(late);
+        let start = STEADY.music_start(late);
+        assert!((0.0..interval).contains(&start));
+        //the music's first beat lands on the first beat of the map
+        let beats = STEADY.beat_times(late);
+        assert!((beats[0] - (start + 0.3 * RHYTHM_TIME_SCALE)).abs() < 1e-9);
+        assert!((beats[0] - 0.5 * interval).rem_euclid(interval) < 1e-9);
+    }
+
+    #[test]
+    fn tempo_and_pattern_pick_the_beats() {
+        let slow = MusicTempo {
+            bpm: 48.0,
+            first_beat: 0.0,
+        };
+        assert!(
+            (STEADY.interval(slow) - 2.0 * STEADY.interval(MusicTempo::default())).abs() < 1e-12
+        );
+
+        let syncopated = &BEAT_MAPS[2];
+        let tempo = MusicTempo::default();
+        let interval = syncopated.interval(tempo);
+        let beats = syncopated.beat_times(tempo);
+        //every fourth beat is left out
+        assert!((beats[2] - beats[1] - 2.0 * interval).abs() < 1e-9);
+        assert!((beats[3] - beats[2] - interval).abs() < 1e-9);
+    }
+
+    #[test]
+    fn accuracy_sets_the_stars() {
+        assert_eq!(accuracy_rating(10, 10), 3);
+        assert_eq!(accuracy_rating(9, 10), 3);
+        assert_eq!(accuracy_rating(8, 10), 2);
+        assert_eq!(accuracy_rating(7, 10), 2);
+        assert_eq!(accuracy_rating(4, 10), 1);
+        assert_eq!(accuracy_rating(3, 10), 0);
+        //a run with no beats doesn't divide by zero
+        assert_eq!(accuracy_rating(0, 0), 0);
+    }
+}
@@ -0,0 +1,496 @@
+//! drawing circuits from their parts: the wires, resistor zigzags, inductor coils, capacitor
+//! plates and bulbs are laid out along the loop and drawn with lyon, with live values above them
+
+use bevy::prelude::*;
+use bevy_egui::egui::{Align2, Color32, FontId, LayerId, Order};
+use bevy_egui::{egui, EguiContext};
+use bevy_prototype_lyon::{
+    entity::ShapeBundle,
+    prelude::{DrawMode, GeometryBuilder, StrokeMode},
+    shapes,
+};
+use std::f32::consts::TAU;
+
+use crate::graphics::{format_si, simulation_shown, DLRCCircuit, DEFAULT_SIGNIFICANT_FIGURES};
+
+/// This plugin lays the circuits out side by side and labels their parts with live values.
+pub struct SchematicPlugin;
+
+impl Plugin for SchematicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(arrange_circuits)
+            .add_system(schematic_labels.with_run_criteria(simulation_shown));
+    }
+}
+
+/// the room each part gets along the top wire
+const SLOT_WIDTH: f32 = 520.0;
+/// the distance between the top and bottom wires
+const SCHEMATIC_HEIGHT: f32 = 520.0;
+/// how thick the wires and parts are drawn
+pub const WIRE_WIDTH: f32 = 14.0;
+/// how far above the top wire the labels sit
+const LABEL_HEIGHT: f32 = 210.0;
+/// the gap left between circuits laid out side by side, in pixels
+const CIRCUIT_GAP: f32 = 32.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Something a circuit is built from
+pub enum Element {
+    Resistor,
+    Inductor,
+    Capacitor,
+    /// a resistor that glows with the power through it
+    Bulb,
+    /// a battery, whose positive side is the end the current leaves from
+    Source,
+    Switch,
+}
+
+impl Element {
+    pub const ALL: [Element; 6] = [
+        Element::Resistor,
+        Element::Inductor,
+        Element::Capacitor,
+        Element::Bulb,
+        Element::Source,
+        Element::Switch,
+    ];
+
+    /// The name of the element, as shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            Element::Resistor => "Resistor",
+            Element::Inductor => "Inductor",
+            Element::Capacitor => "Capacitor",
+            Element::Bulb => "Bulb",
+            Element::Source => "Source",
+            Element::Switch => "Switch",
+        }
+    }
+
+    /// The unit of the element's value, empty if it has none
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Element::Resistor | Element::Bulb => "\u{3a9}",
+            Element::Inductor => "H",
+            Element::Capacitor => "F",
+            Element::Source => "V",
+            Element::Switch => "",
+        }
+    }
+
+    /// How much of the top wire the part takes up
+    pub fn length(&self) -> f32 {
+        match self {
+            Element::Resistor | Element::Bulb => 260.0,
+            Element::Inductor => 340.0,
+            Element::Capacitor | Element::Source => 60.0,
+            Element::Switch => 160.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A part placed along the top wire of a schematic
+pub struct PlacedElement {
+    pub element: Element,
+    /// the x where the part starts
+    pub start: f32,
+    /// the x where the part ends
+    pub end: f32,
+    /// the element's own resistance, inductance, capacitance or voltage, none to label it with
+    /// the circuit's
+    pub value: Option<f64>,
+}
+
+impl PlacedElement {
+    /// The x of the middle of the part
+    pub fn center(&self) -> f32 {
+        (self.start + self.end) * 0.5
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+/// The layout of a circuit's drawing, centered on the circuit entity
+///
+/// The wire runs around a rectangle and the parts sit along its top, in the order the current
+/// passes through them going clockwise.
+pub struct Schematic {
+    pub elements: Vec<PlacedElement>,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Schematic {
+    /// Lays out elements connected in series
+    ///
+    /// # Arguments
+    /// * 'elements' - the elements in the order they're connected, left to right along the top
+    ///   wire, each with its own value if it has one
+    ///
+    /// # Returns
+    /// The schematic, with every part in the middle of an equal slot
+    pub fn series(elements: &[(Element, Option<f64>)]) -> Self {
+        let width = SLOT_WIDTH * elements.len().max(1) as f32;
+        let placed = elements
+            .iter()
+            .enumerate()
+            .map(|(index, &(element, value))| {
+                let center = -width * 0.5 + SLOT_WIDTH * (index as f32 + 0.5);
+                PlacedElement {
+                    element,
+                    start: center - element.length() * 0.5,
+                    end: center + element.length() * 0.5,
+                    value,
+                }
+            })
+            .collect();
+        Self {
+            elements: placed,
+            width,
+            height: SCHEMATIC_HEIGHT,
+        }
+    }
+
+    /// The schematic of a `DLRCCircuit`, whose resistor is the bulb
+    pub fn light_circuit() -> Self {
+        Self::series(&[
+            (Element::Bulb, None),
+            (Element::Inductor, None),
+            (Element::Capacitor, None),
+        ])
+    }
+
+    pub fn left(&self) -> f32 {
+        -self.width * 0.5
+    }
+
+    pub fn right(&self) -> f32 {
+        self.width * 0.5
+    }
+
+    pub fn top(&self) -> f32 {
+        self.height * 0.5
+    }
+
+    pub fn bottom(&self) -> f32 {
+        -self.height * 0.5
+    }
+
+    /// Finds the first element of a kind
+    pub fn element(&self, element: Element) -> Option<&PlacedElement> {
+        self.elements
+            .iter()
+            .find(|placed| placed.element == element)
+    }
+
+    /// The length of the whole loop of wire
+    pub fn wire_length(&self) -> f32 {
+        2.0 * (self.width + self.height)
+    }
+
+    /// Finds a point along the wire
+    ///
+    /// # Arguments
+    /// * 'distance' - how far along the wire the point is, going clockwise from the top left
+    ///   corner
+    ///
+    /// # Returns
+    /// The point, relative to the circuit entity
+    pub fn wire_point(&self, distance: f32) -> Vec2 {
+        let (width, height) = (self.width, self.height);
+        let distance = distance.rem_euclid(self.wire_length());
+        if distance < width {
+            Vec2::new(self.left() + distance, self.top())
+        } else if distance < width + height {
+            Vec2::new(self.right(), self.top() - (distance - width))
+        } else if distance < 2.0 * width + height {
+            Vec2::new(self.right() - (distance - width - height), self.bottom())
+        } else {
+            Vec2::new(
+                self.left(),
+                self.bottom() + (distance - 2.0 * width - height),
+            )
+        }
+    }
+
+    /// Checks if a point on the wire is inside one of the parts
+    pub fn in_element(&self, point: Vec2) -> bool {
+        point.y == self.top()
+            && self
+                .elements
+                .iter()
+                .any(|placed| point.x > placed.start && point.x < placed.end)
+    }
+
+    /// Builds the shapes of the wire and every part
+    pub fn shape(&self) -> ShapeBundle {
+        let top = self.top();
+        let mut builder = GeometryBuilder::new();
+
+        //the wire between the parts, then around the rest of the loop
+        let mut x = self.left();
+        for placed in self.elements.iter() {
+            builder = builder.add(&shapes::Line(
+                Vec2::new(x, top),
+                Vec2::new(placed.start, top),
+            ));
+            x = placed.end;
+        }
+        builder = builder.add(&shapes::Polygon {
+            points: vec![
+                Vec2::new(x, top),
+                Vec2::new(self.right(), top),
+                Vec2::new(self.right(), self.bottom()),
+                Vec2::new(self.left(), self.bottom()),
+                Vec2::new(self.left(), top),
+            ],
+            closed: false,
+        });
+
+        for placed in self.elements.iter() {
+            builder = element_geometry(builder, placed, top);
+        }
+
+        builder.build(
+            DrawMode::Stroke(StrokeMode::new(Color::WHITE, WIRE_WIDTH)),
+            Transform::identity(),
+        )
+    }
+}
+
+/// half the height of a capacitor plate
+pub const PLATE_HALF_HEIGHT: f32 = 70.0;
+/// the gap between the capacitor plates
+const PLATE_GAP: f32 = 36.0;
+
+/// Finds the x of both plates of a capacitor or source
+pub fn capacitor_plates(capacitor: &PlacedElement) -> (f32, f32) {
+    let center = capacitor.center();
+    (center - PLATE_GAP * 0.5, center + PLATE_GAP * 0.5)
+}
+
+/// Adds the drawing of an element, with switches drawn closed
+///
+/// # Arguments
+/// * 'builder' - the builder to add the shapes to
+/// * 'placed' - the element and where it starts and ends
+/// * 'top' - the y of the wire the element sits on
+///
+/// # Returns
+/// The builder with the element added
+pub fn element_geometry(
+    builder: GeometryBuilder,
+    placed: &PlacedElement,
+    top: f32,
+) -> GeometryBuilder {
+    match placed.element {
+        Element::Resistor => builder.add(&zigzag(placed, top, 65.0)),
+        Element::Bulb => builder.add(&zigzag(placed, top, 40.0)),
+        Element::Inductor => builder.add(&coil(placed, top)),
+        Element::Capacitor => plates(builder, placed, top, PLATE_HALF_HEIGHT),
+        //the long plate is the positive side
+        Element::Source => {
+            let (_, positive) = capacitor_plates(placed);
+            plates(builder, placed, top, PLATE_HALF_HEIGHT * 0.5).add(&shapes::Line(
+                Vec2::new(positive, top - PLATE_HALF_HEIGHT),
+                Vec2::new(positive, top + PLATE_HALF_HEIGHT),
+            ))
+        }
+        Element::Switch => switch_geometry(builder, placed, top, true),
+    }
+}
+
+/// Adds the leads and plates of a capacitor or source
+///
+/// # Arguments
+/// * 'builder' - the builder to add the shapes to
+/// * 'placed' - the capacitor or source
+/// * 'top' - the y of the wire it sits on
+/// * 'half_height' - half the height of both plates
+fn plates(
+    builder: GeometryBuilder,
+    placed: &PlacedElement,
+    top: f32,
+    half_height: f32,
+) -> GeometryBuilder {
+    let (left_plate, right_plate) = capacitor_plates(placed);
+    builder
+        .add(&shapes::Line(
+            Vec2::new(placed.start, top),
+            Vec2::new(left_plate, top),
+        ))
+        .add(&shapes::Line(
+            Vec2::new(right_plate, top),
+            Vec2::new(placed.end, top),
+        ))
+        .add(&shapes::Line(
+            Vec2::new(left_plate, top - half_height),
+            Vec2::new(left_plate, top + half_height),
+        ))
+        .add(&shapes::Line(
+            Vec2::new(right_plate, top - half_height),
+            Vec2::new(right_plate, top + half_height),
+        ))
+}
+
+/// Adds the contacts and lever of a switch
+///
+/// # Arguments
+/// * 'builder' - the builder to add the shapes to
+/// * 'placed' - the switch
+/// * 'top' - the y of the wire it sits on
+/// * 'closed' - whether the lever touches the far contact
+///
+/// # Returns
+/// The builder with the switch added
+pub fn switch_geometry(
+    builder: GeometryBuilder,
+    placed: &PlacedElement,
+    top: f32,
+    closed: bool,
+) -> GeometryBuilder {
+    let contact = 12.0;
+    let pivot = Vec2::new(placed.start + contact, top);
+    let far_contact = Vec2::new(placed.end - contact, top);
+    //an open lever is lifted by 30 degrees
+    let reach = far_contact.x - pivot.x;
+    let lever = if closed {
+        far_contact
+    } else {
+        pivot + Vec2::new(reach * 0.87, reach * 0.5)
+    };
+    builder
+        .add(&shapes::Circle {
+            radius: contact,
+            center: pivot,
+        })
+        .add(&shapes::Circle {
+            radius: contact,
+            center: far_contact,
+        })
+        .add(&shapes::Line(pivot, lever))
+}
+
+/// The zigzag of a resistor or a bulb's filament
+fn zigzag(placed: &PlacedElement, top: f32, amplitude: f32) -> shapes::Polygon {
+    let teeth = 6;
+    let length = placed.end - placed.start;
+    let mut points = vec![Vec2::new(placed.start, top)];
+    for tooth in 0..teeth {
+        let sign = if tooth % 2 == 0 { 1.0 } else { -1.0 };
+        points.push(Vec2::new(
+            placed.start + length * (tooth as f32 + 0.5) / teeth as f32,
+            top + amplitude * sign,
+        ));
+    }
+    points.push(Vec2::new(placed.end, top));
+    shapes::Polygon {
+        points,
+        closed: false,
+    }
+}
+
+/// The loops of an inductor's coil, traced as a stretched prolate cycloid
+fn coil(placed: &PlacedElement, top: f32) -> shapes::Polygon {
+    let loops = 4.0;
+    let steps = 120;
+    let length = placed.end - placed.start;
+    //the coil moves forward by advance and swings back by twice that, so it crosses itself
+    let advance = length / (loops * TAU);
+    let points = (0..=steps)
+        .map(|step| {
+            let angle = step as f32 / steps as f32 * loops * TAU;
+            Vec2::new(
+                placed.start + advance * angle - 1.8 * advance * angle.sin(),
+                top + 35.0 * (1.0 - angle.cos()),
+            )
+        })
+        .collect();
+    shapes::Polygon {
+        points,
+        closed: false,
+    }
+}
+
+/// Places the circuits in a row from left to right, keeping the leftmost where it was spawned
+fn arrange_circuits(mut query_circs: Query<(&Schematic, &mut Transform), With<DLRCCircuit>>) {
+    let mut circuits: Vec<_> = query_circs.iter_mut().collect();
+    circuits.sort_by(|a, b| a.1.translation.x.total_cmp(&b.1.translation.x));
+    let mut right_edge = None;
+    for (schematic, transform) in circuits.iter_mut() {
+        let half_width = schematic.width * 0.5 * transform.scale.x;
+        if let Some(edge) = right_edge {
+            let x = edge + half_width;
+            if transform.translation.x != x {
+                transform.translation.x = x;
+            }
+        }
+        right_edge = Some(transform.translation.x + half_width + CIRCUIT_GAP);
+    }
+}
+
+/// Writes the live values of every part above it
+fn schematic_labels(
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    query_circs: Query<(&DLRCCircuit, &Schematic, &GlobalTransform)>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let painter = egui_context.ctx_mut().layer_painter(LayerId::new(
+        Order::Background,
+        egui::Id::new("schematic labels"),
+    ));
+    for (circuit, schematic, global_transform) in query_circs.iter() {
+        let rlc = &circuit.0.circuit;
+        for placed in schematic.elements.iter() {
+            let si = |value, unit| format_si(value, unit, DEFAULT_SIGNIFICANT_FIGURES);
+            //the current and charge are the same all the way around a series loop
+            let text = match placed.element {
+                Element::Resistor => {
+                    format!(
+                        "R = {}",
+                        si(placed.value.unwrap_or(rlc.resistance), "\u{3a9}")
+                    )
+                }
+                Element::Bulb => format!(
+                    "R = {}\nP = {}",
+                    si(placed.value.unwrap_or(rlc.resistance), "\u{3a9}"),
+                    si(circuit.0.lightbulb_power(), "W")
+                ),
+                Element::Inductor => format!(
+                    "L = {}\nI = {}",
+                    si(placed.value.unwrap_or(rlc.inductance), "H"),
+                    si(rlc.current(), "A")
+                ),
+                Element::Capacitor => format!(
+                    "C = {}\nQ = {}",
+                    si(placed.value.unwrap_or(rlc.capacitance), "F"),
+                    si(rlc.charge(), "C")
+                ),
+                Element::Source => format!("V = {}", si(placed.value.unwrap_or(0.0), "V")),
+                Element::Switch => "closed".to_string(),
+            };
+            //the 2d camera puts the origin in the middle of the window, one unit to a pixel
+            let world = global_transform.mul_vec3(Vec3::new(
+                placed.center(),
+                schematic.top() + LABEL_HEIGHT,
+                0.0,
+            ));
+            painter.text(
+                egui::pos2(
+                    window.width() * 0.5 + world.x,
+                    window.height() * 0.5 - world.y,
+                ),
+                Align2::CENTER_BOTTOM,
+                text,
+                FontId::proportional(14.0),
+                Color32::LIGHT_GRAY,
+            );
+        }
+    }
+}
@@ -0,0 +1,702 @@
+//! an oscilloscope for the circuits: up to four channels of any circuit quantity, with volts per
+//! division, time per division and edge triggering, the way a bench scope shows them
+
+use bevy::prelude::*;
+use bevy_egui::egui::{
+    plot::{GridMark, HLine, Line, LineStyle, Plot, VLine, Value, Values},
+    Color32, RichText,
+};
+use bevy_egui::{egui, EguiContext};
+
+use crate::graphics::{format_si, simulation_running, simulation_shown, CircuitTimer, DLRCCircuit};
+use crate::DisconnectLightCircuitCalculator;
+
+/// This plugin adds the oscilloscope window and keeps its channels sampled.
+pub struct ScopePlugin;
+
+impl Plugin for ScopePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Oscilloscope>()
+            .add_system(sample_scope.with_run_criteria(simulation_running))
+            .add_system(scope_frame.with_run_criteria(simulation_shown));
+    }
+}
+
+/// the most channels the scope has
+pub const MAX_CHANNELS: usize = 4;
+/// the fewest channels the scope has
+const MIN_CHANNELS: usize = 2;
+/// how many divisions the screen is across
+const DIVISIONS_ACROSS: f64 = 10.0;
+/// how many divisions the screen is from the middle to the top
+const DIVISIONS_UP: f64 = 4.0;
+/// how many divisions of the screen come before the trigger
+const PRETRIGGER_DIVISIONS: f64 = 1.0;
+/// how many screens of samples are kept to look for triggers in
+const KEPT_SCREENS: f64 = 3.0;
+/// the colors of the channels, the usual yellow, cyan, magenta and green
+const CHANNEL_COLORS: [Color32; MAX_CHANNELS] = [
+    Color32::from_rgb(240, 220, 60),
+    Color32::from_rgb(60, 220, 240),
+    Color32::from_rgb(240, 80, 200),
+    Color32::from_rgb(80, 230, 110),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A quantity in a circuit a channel can measure, keeping its sign
+pub enum Probe {
+    Current,
+    /// the charge on the capacitor
+    Charge,
+    /// the voltage across all the resistance
+    ResistorVoltage,
+    InductorVoltage,
+    CapacitorVoltage,
+    /// the power through the lightbulb
+    Power,
+    /// the energy stored in the capacitor and inductor
+    Energy,
+}
+
+impl Probe {
+    pub const ALL: [Probe; 7] = [
+        Probe::Current,
+        Probe::Charge,
+        Probe::ResistorVoltage,
+        Probe::InductorVoltage,
+        Probe::CapacitorVoltage,
+        Probe::Power,
+        Probe::Energy,
+    ];
+
+    /// The name of the quantity, as shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            Probe::Current => "current",
+            Probe::Charge => "charge",
+            Probe::ResistorVoltage => "V across R",
+            Probe::InductorVoltage => "V across L",
+            Probe::CapacitorVoltage => "V across C",
+            Probe::Power => "power",
+            Probe::Energy => "energy",
+        }
+    }
+
+    /// The unit the quantity is measured in
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Probe::Current => "A",
+            Probe::Charge => "C",
+            Probe::ResistorVoltage | Probe::InductorVoltage | Probe::CapacitorVoltage => "V",
+            Probe::Power => "W",
+            Probe::Energy => "J",
+        }
+    }
+
+    /// Measures the quantity in a circuit
+    pub fn measure(&self, circuit: &DisconnectLightCircuitCalculator) -> f64 {
+        let rlc = &circuit.circuit;
+        match self {
+            Probe::Current => rlc.current(),
+            Probe::Charge => rlc.charge(),
+            Probe::ResistorVoltage => rlc.current() * rlc.resistance,
+            Probe::InductorVoltage => rlc.current_rate() * rlc.inductance,
+            Probe::CapacitorVoltage => rlc.charge() / rlc.capacitance,
+            Probe::Power => circuit.lightbulb_power(),
+            Probe::Energy => rlc.energy(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// One trace of the scope
+pub struct ScopeChannel {
+    pub probe: Probe,
+    /// which circuit is probed, in the order the circuit window lists them
+    pub circuit: usize,
+    /// how much of the quantity one division up the screen is
+    pub per_division: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Which way a channel has to cross the trigger level to trigger
+pub enum Slope {
+    Rising,
+    Falling,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// When the screen is redrawn
+pub enum TriggerMode {
+    /// on every trigger, running freely when nothing has triggered for a while
+    Auto,
+    /// only on a trigger, holding the last sweep while waiting
+    Normal,
+    /// once, on the first trigger after arming
+    Single,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The edge the scope lines its sweeps up on
+pub struct Trigger {
+    /// the index of the channel watched
+    pub channel: usize,
+    pub level: f64,
+    pub slope: Slope,
+    pub mode: TriggerMode,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Every channel's value at one moment
+pub struct ScopeSample {
+    pub time: f64,
+    pub values: [f64; MAX_CHANNELS],
+}
+
+#[derive(Debug, Clone)]
+/// The samples shown on the screen
+pub struct Sweep {
+    /// the time at the left edge of the screen
+    pub start: f64,
+    /// whether the sweep is lined up on a trigger, rather than running freely
+    pub triggered: bool,
+    pub samples: Vec<ScopeSample>,
+}
+
+/// The scope's controls and what it has captured
+pub struct Oscilloscope {
+    pub channels: Vec<ScopeChannel>,
+    /// how much simulation time one division across the screen is
+    pub time_per_division: f64,
+    pub trigger: Trigger,
+    /// the samples kept to look for triggers in
+    samples: Vec<ScopeSample>,
+    /// what's on the screen
+    sweep: Option<Sweep>,
+    /// in single mode, the time the scope was armed at, none once it has captured a sweep
+    armed_at: Option<f64>,
+}
+
+impl Default for Oscilloscope {
+    /// the current and the capacitor voltage of the first circuit, triggered on the current rising
+    /// through zero
+    fn default() -> Self {
+        Self {
+            channels: vec![
+                ScopeChannel {
+                    probe: Probe::Current,
+                    circuit: 0,
+                    per_division: 0.5,
+                },
+                ScopeChannel {
+                    probe: Probe::CapacitorVoltage,
+                    circuit: 0,
+                    per_division: 0.5,
+                },
+            ],
+            time_per_division: 5.0,
+            trigger: Trigger {
+                channel: 0,
+                level: 0.0,
+                slope: Slope::Rising,
+                mode: TriggerMode::Auto,
+            },
+            samples: Vec::new(),
+            sweep: None,
+            armed_at: Some(0.0),
+        }
+    }
+}
+
+impl Oscilloscope {
+    /// How much simulation time the screen spans
+    pub fn screen_time(&self) -> f64 {
+        self.time_per_division * DIVISIONS_ACROSS
+    }
+
+    /// Forgets everything captured, as after the circuit is reset or a channel is changed
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.sweep = None;
+        self.armed_at = Some(0.0);
+    }
+
+    /// Arms the scope to capture the next trigger in single mode
+    pub fn arm(&mut self) {
+        self.armed_at = Some(self.samples.last().map_or(0.0, |sample| sample.time));
+        self.sweep = None;
+    }
+
+    /// Adds a sample, rewinding if the circuit was reset, and updates the screen
+    pub fn record(&mut self, sample: ScopeSample) {
+        match self.samples.last() {
+            Some(last) if last.time == sample.time => return,
+            Some(last) if last.time > sample.time => self.clear(),
+            _ => {}
+        }
+        self.samples.push(sample);
+        let oldest = sample.time - self.screen_time() * KEPT_SCREENS;
+        let stale = self.samples.iter().take_while(|s| s.time < oldest).count();
+        self.samples.drain(..stale);
+        self.capture();
+    }
+
+    /// Finds every time the trigger channel crosses the trigger level the right way
+    ///
+    /// # Returns
+    /// The times, found between samples by a straight line, from oldest to newest
+    pub fn trigger_times(&self) -> Vec<f64> {
+        let channel = self.trigger.channel;
+        let level = self.trigger.level;
+        self.samples
+            .windows(2)
+            .filter_map(|pair| {
+                let (before, after) = (pair[0].values[channel], pair[1].values[channel]);
+                let crossed = match self.trigger.slope {
+                    Slope::Rising => before < level && after >= level,
+                    Slope::Falling => before > level && after <= level,
+                };
+                crossed.then(|| {
+                    let fraction = (level - before) / (after - before);
+                    pair[0].time + (pair[1].time - pair[0].time) * fraction
+                })
+            })
+            .collect()
+    }
+
+    /// Picks what goes on the screen from the kept samples, following the trigger mode
+    pub fn capture(&mut self) {
+        let newest = match self.samples.last() {
+            Some(sample) => sample.time,
+            None => return,
+        };
+        let screen = self.screen_time();
+        let pretrigger = self.time_per_division * PRETRIGGER_DIVISIONS;
+        //a trigger can only be shown once the screen after it has been sampled
+        let complete: Vec<f64> = self
+            .trigger_times()
+            .into_iter()
+            .filter(|&time| time - pretrigger + screen <= newest)
+            .collect();
+        let trigger = match self.trigger.mode {
+            TriggerMode::Single => match self.armed_at {
+                Some(armed_at) => {
+                    let first = complete.iter().copied().find(|&time| time >= armed_at);
+                    if first.is_some() {
+                        self.armed_at = None;
+                    }
+                    first
+                }
+                None => return,
+            },
+            TriggerMode::Normal => complete.last().copied(),
+            //auto gives up on the trigger once a couple of screens pass without one
+            TriggerMode::Auto => complete
+                .last()
+                .copied()
+                .filter(|&time| newest - time < 2.0 * screen),
+        };
+        let (start, triggered) = match (trigger, self.trigger.mode) {
+            (Some(time), _) => (time - pretrigger, true),
+            (None, TriggerMode::Auto) => (newest - screen, false),
+            (None, _) => return,
+        };
+        self.sweep = Some(Sweep {
+            start,
+            triggered,
+            samples: self
+                .samples
+                .iter()
+                .filter(|sample| sample.time >= start && sample.time <= start + screen)
+                .copied()
+                .collect(),
+        });
+    }
+
+    /// What the scope is doing, as the status light on a scope would show it
+    pub fn status(&self) -> &'static str {
+        match (self.trigger.mode, &self.sweep) {
+            (TriggerMode::Single, _) if self.armed_at.is_some() => "Armed",
+            (TriggerMode::Single, _) => "Stopped",
+            (_, Some(sweep)) if sweep.triggered => "Trig'd",
+            (TriggerMode::Auto, _) => "Auto",
+            (TriggerMode::Normal, _) => "Ready",
+        }
+    }
+}
+
+/// Samples every channel whenever the simulation moves on
+fn sample_scope(
+    time: Res<CircuitTimer>,
+    mut scope: ResMut<Oscilloscope>,
+    query_circs: Query<&DLRCCircuit>,
+) {
+    let circuits: Vec<&DLRCCircuit> = query_circs.iter().collect();
+    let mut values = [0.0; MAX_CHANNELS];
+    for (value, channel) in values.iter_mut().zip(scope.channels.iter()) {
+        if let Some(circuit) = circuits.get(channel.circuit) {
+            *value = channel.probe.measure(&circuit.0);
+        }
+    }
+    scope.record(ScopeSample {
+        time: time.time,
+        values,
+    });
+}
+
+/// The steps a scale knob clicks through, going 1, 2, 5, 10 and so on, from pico to kilo so
+/// rescaled circuits fit too
+fn scale_steps() -> impl Iterator<Item = f64> {
+    (-12..=3).flat_map(|exponent| {
+        [1.0, 2.0, 5.0]
+            .into_iter()
+            .map(move |mantissa| mantissa * 10f64.powi(exponent))
+    })
+}
+
+/// Adds a box to pick a scale from the 1, 2, 5 steps
+fn scale_combo(ui: &mut egui::Ui, id: impl std::hash::Hash, value: &mut f64, unit: &str) -> bool {
+    let mut changed = false;
+    egui::ComboBox::from_id_source(id)
+        .selected_text(format!("{}/div", format_si(*value, unit, 1)))
+        .show_ui(ui, |ui| {
+            for step in scale_steps() {
+                changed |= ui
+                    .selectable_value(value, step, format!("{}/div", format_si(step, unit, 1)))
+                    .changed();
+            }
+        });
+    changed
+}
+
+/// Marks every division of the screen
+fn division_marks(bounds: (f64, f64)) -> Vec<GridMark> {
+    (bounds.0.ceil() as i32..=bounds.1.floor() as i32)
+        .map(|division| GridMark {
+            value: division as f64,
+            step_size: 1.0,
+        })
+        .collect()
+}
+
+/// creates the oscilloscope window, with its screen and controls
+fn scope_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut scope: ResMut<Oscilloscope>,
+    query_circs: Query<&DLRCCircuit>,
+) {
+    let circuit_count = query_circs.iter().count();
+    egui::Window::new("Oscilloscope")
+        .title_bar(false)
+        .default_pos([450.0, 60.0])
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::CollapsingHeader::new("Oscilloscope")
+                .default_open(false)
+                .show(ui, |ui| {
+                    let scope = &mut *scope;
+                    let screen = scope.screen_time();
+                    Plot::new("scope screen")
+                        .width(420.0)
+                        .height(336.0)
+                        .set_margin_fraction(egui::Vec2::ZERO)
+                        .include_x(0.0)
+                        .include_x(DIVISIONS_ACROSS)
+                        .include_y(-DIVISIONS_UP)
+                        .include_y(DIVISIONS_UP)
+                        .x_grid_spacer(|input| division_marks(input.bounds))
+                        .y_grid_spacer(|input| division_marks(input.bounds))
+                        .show_axes([false, false])
+                        .allow_scroll(false)
+                        .allow_zoom(false)
+                        .allow_drag(false)
+                        .allow_boxed_zoom(false)
+                        .show_x(false)
+                        .show_y(false)
+                        .show(ui, |plot_ui| {
+                            if let Some(sweep) = &scope.sweep {
+                                for (index, channel) in scope.channels.iter().enumerate() {
+                                    //like a real scope, traces off the screen stick to its edge
+                                    let points = sweep.samples.iter().map(|sample| {
+                                        Value::new(
+                                            (sample.time - sweep.start) / screen * DIVISIONS_ACROSS,
+                                            (sample.values[index] / channel.per_division)
+                                                .clamp(-DIVISIONS_UP, DIVISIONS_UP),
+                                        )
+                                    });
+                                    plot_ui.line(
+                                        Line::new(Values::from_values_iter(points))
+                                            .color(CHANNEL_COLORS[index])
+                                            .width(2.0),
+                                    );
+                                }
+                            }
+                            let trigger = scope.trigger;
+                            if let Some(channel) = scope.channels.get(trigger.channel) {
+                                plot_ui.hline(
+                                    HLine::new(trigger.level / channel.per_division)
+                                        .color(CHANNEL_COLORS[trigger.channel])
+                                        .style(LineStyle::dashed_loose()),
+                                );
+                            }
+                            plot_ui.vline(
+                                VLine::new(PRETRIGGER_DIVISIONS)
+                                    .color(Color32::from_rgba_unmultiplied(255, 255, 255, 60))
+                                    .style(LineStyle::dotted_loose()),
+                            );
+                        });
+
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        ui.label(RichText::new(scope.status()).strong());
+                        ui.label("time");
+                        if scale_combo(ui, "time/div", &mut scope.time_per_division, "s") {
+                            scope.capture();
+                        }
+                    });
+                    ui.separator();
+
+                    let mut cleared = false;
+                    egui::Grid::new("scope channels").show(ui, |ui| {
+                        for (index, channel) in scope.channels.iter_mut().enumerate() {
+                            ui.label(
+                                RichText::new(format!("CH{}", index + 1))
+                                    .color(CHANNEL_COLORS[index]),
+                            );
+                            let before = *channel;
+                            egui::ComboBox::from_id_source(("probe", index))
+                                .selected_text(channel.probe.name())
+                                .show_ui(ui, |ui| {
+                                    for probe in Probe::ALL {
+                                        ui.selectable_value(
+                                            &mut channel.probe,
+                                            probe,
+                                            probe.name(),
+                                        );
+                                    }
+                                });
+                            if circuit_count > 1 {
+                                egui::ComboBox::from_id_source(("circuit", index))
+                                    .selected_text(format!("circuit {}", channel.circuit + 1))
+                                    .show_ui(ui, |ui| {
+                                        for circuit in 0..circuit_count {
+                                            ui.selectable_value(
+                                                &mut channel.circuit,
+                                                circuit,
+                                                format!("circuit {}", circuit + 1),
+                                            );
+                                        }
+                                    });
+                            }
+                            scale_combo(
+                                ui,
+                                ("per division", index),
+                                &mut channel.per_division,
+                                channel.probe.unit(),
+                            );
+                            ui.end_row();
+                            //the old samples measured something else
+                            cleared |=
+                                channel.probe != before.probe || channel.circuit != before.circuit;
+                        }
+                    });
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        if scope.channels.len() < MAX_CHANNELS && ui.button("Add channel").clicked()
+                        {
+                            let last = *scope.channels.last().expect("the scope has channels");
+                            scope.channels.push(last);
+                            cleared = true;
+                        }
+                        if scope.channels.len() > MIN_CHANNELS
+                            && ui.button("Remove channel").clicked()
+                        {
+                            scope.channels.pop();
+                            scope.trigger.channel =
+                                scope.trigger.channel.min(scope.channels.len() - 1);
+                            cleared = true;
+                        }
+                    });
+                    ui.separator();
+
+                    let before = scope.trigger;
+                    let trigger = &mut scope.trigger;
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        ui.label("trigger on");
+                        egui::ComboBox::from_id_source("trigger channel")
+                            .selected_text(format!("CH{}", trigger.channel + 1))
+                            .show_ui(ui, |ui| {
+                                for index in 0..scope.channels.len() {
+                                    ui.selectable_value(
+                                        &mut trigger.channel,
+                                        index,
+                                        format!("CH{}", index + 1),
+                                    );
+                                }
+                            });
+                        let unit = scope.channels[trigger.channel].probe.unit();
+                        ui.add(
+                            egui::DragValue::new(&mut trigger.level)
+                                .speed(0.01)
+                                .suffix(format!(" {}", unit)),
+                        );
+                    });
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        ui.selectable_value(&mut trigger.slope, Slope::Rising, "Rising");
+                        ui.selectable_value(&mut trigger.slope, Slope::Falling, "Falling");
+                        ui.separator();
+                        ui.selectable_value(&mut trigger.mode, TriggerMode::Auto, "Auto");
+                        ui.selectable_value(&mut trigger.mode, TriggerMode::Normal, "Normal");
+                        ui.selectable_value(&mut trigger.mode, TriggerMode::Single, "Single");
+                    });
+                    let arm = trigger.mode == TriggerMode::Single && ui.button("Arm").clicked();
+
+                    if cleared {
+                        scope.clear();
+                    } else if arm
+                        || (scope.trigger != before && scope.trigger.mode == TriggerMode::Single)
+                    {
+                        scope.arm();
+                    } else if scope.trigger != before {
+                        scope.capture();
+                    }
+                });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    /// how often the test signals are sampled
+    const STEP: f64 = 0.1;
+
+    /// A scope with a screen ten seconds across, triggering on the first channel
+    fn scope(mode: TriggerMode, slope: Slope, level: f64) -> Oscilloscope {
+        Oscilloscope {
+            time_per_division: 1.0,
+            trigger: Trigger {
+                channel: 0,
+                level,
+                slope,
+                mode,
+            },
+            ..Oscilloscope::default()
+        }
+    }
+
+    /// Samples a signal from one time up to another
+    fn feed(scope: &mut Oscilloscope, from: f64, to: f64, signal: impl Fn(f64) -> f64) {
+        let steps = ((to - from) / STEP).round() as usize;
+        for k in 0..=steps {
+            let time = from + k as f64 * STEP;
+            let mut values = [0.0; MAX_CHANNELS];
+            values[0] = signal(time);
+            scope.record(ScopeSample { time, values });
+        }
+    }
+
+    /// a sine wave rising through zero every 8 seconds
+    fn sine(time: f64) -> f64 {
+        (TAU * time / 8.0).sin()
+    }
+
+    /// Whether the times are close to the expected ones
+    fn near(times: &[f64], expected: &[f64]) -> bool {
+        times.len() == expected.len()
+            && times
+                .iter()
+                .zip(expected)
+                .all(|(a, b)| (a - b).abs() < 0.01)
+    }
+
+    #[test]
+    fn crossings_follow_the_slope_and_level() {
+        let mut rising = scope(TriggerMode::Normal, Slope::Rising, 0.0);
+        feed(&mut rising, 0.0, 18.0, sine);
+        assert!(near(&rising.trigger_times(), &[8.0, 16.0]));
+
+        let mut falling = scope(TriggerMode::Normal, Slope::Falling, 0.0);
+        feed(&mut falling, 0.0, 18.0, sine);
+        assert!(near(&falling.trigger_times(), &[4.0, 12.0]));
+
+        //the sine rises through a half a twelfth of the way into each period
+        let mut raised = scope(TriggerMode::Normal, Slope::Rising, 0.5);
+        feed(&mut raised, 0.0, 18.0, sine);
+        let expected = [2.0 / 3.0, 8.0 + 2.0 / 3.0, 16.0 + 2.0 / 3.0];
+        assert!(near(&raised.trigger_times(), &expected));
+    }
+
+    #[test]
+    fn sweeps_start_a_division_before_the_trigger() {
+        let mut scope = scope(TriggerMode::Normal, Slope::Rising, 0.0);
+        feed(&mut scope, 0.0, 30.0, sine);
+        //the trigger at 24 can't be shown until the screen after it is sampled
+        let sweep = scope.sweep.as_ref().unwrap();
+        assert!(sweep.triggered);
+        assert!((sweep.start - 15.0).abs() < 0.01);
+        let first = sweep.samples.first().unwrap().time;
+        let last = sweep.samples.last().unwrap().time;
+        assert!(first >= sweep.start && first < 16.0);
+        assert!(last <= sweep.start + scope.screen_time());
+        assert_eq!(scope.status(), "Trig'd");
+    }
+
+    #[test]
+    fn normal_mode_waits_for_a_trigger_and_holds_the_last() {
+        let mut scope = scope(TriggerMode::Normal, Slope::Rising, 0.0);
+        feed(&mut scope, 0.0, 10.0, sine);
+        assert!(scope.sweep.is_none());
+        assert_eq!(scope.status(), "Ready");
+
+        //the signal stops crossing after the trigger at 16, so its sweep stays up
+        feed(&mut scope, 10.1, 20.0, sine);
+        feed(&mut scope, 20.1, 100.0, |_| -1.0);
+        let sweep = scope.sweep.as_ref().unwrap();
+        assert!(sweep.triggered);
+        assert!((sweep.start - 15.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn single_mode_captures_once_until_armed() {
+        let mut scope = scope(TriggerMode::Single, Slope::Rising, 0.0);
+        assert_eq!(scope.status(), "Armed");
+        feed(&mut scope, 0.0, 30.0, sine);
+        let start = scope.sweep.as_ref().unwrap().start;
+        assert!((start - 7.0).abs() < 0.01);
+        assert_eq!(scope.status(), "Stopped");
+
+        //later triggers don't replace the captured sweep
+        feed(&mut scope, 30.1, 50.0, sine);
+        assert_eq!(scope.sweep.as_ref().unwrap().start, start);
+
+        //arming again captures the next complete trigger after the arming
+        scope.arm();
+        assert!(scope.sweep.is_none());
+        assert_eq!(scope.status(), "Armed");
+        feed(&mut scope, 50.1, 70.0, sine);
+        let start = scope.sweep.as_ref().unwrap().start;
+        assert!((start - 55.0).abs() < 0.01);
+        assert_eq!(scope.status(), "Stopped");
+    }
+
+    #[test]
+    fn auto_mode_runs_freely_without_triggers() {
+        let mut scope = scope(TriggerMode::Auto, Slope::Rising, 0.0);
+        feed(&mut scope, 0.0, 12.0, |_| -1.0);
+        let sweep = scope.sweep.as_ref().unwrap();
+        assert!(!sweep.triggered);
+        assert!((sweep.start - 2.0).abs() < 1e-9);
+        assert_eq!(scope.status(), "Auto");
+
+        scope.clear();
+        feed(&mut scope, 0.0, 20.0, sine);
+        assert!(scope.sweep.as_ref().unwrap().triggered);
+        //a trigger is still shown while less than two screens have passed since it
+        feed(&mut scope, 20.1, 30.0, |_| -1.0);
+        assert!(scope.sweep.as_ref().unwrap().triggered);
+        feed(&mut scope, 30.1, 40.0, |_| -1.0);
+        let sweep = scope.sweep.as_ref().unwrap();
+        assert!(!sweep.triggered);
+        assert!((sweep.start - 30.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,353 @@
+//! the simulation core: the timer, the circuit entities and stepping them, and finding the peaks
+//! of their current, none of which needs a window, renderer or sound card
+
+use bevy::prelude::*;
+
+use crate::graphics::{simulation_running, Light};
+use crate::{DisconnectLightCircuitCalculator, RLCCalculator};
+
+/// This plugin adds the shared manipulable timer, steps every circuit while the simulation runs,
+/// and sends an event at every peak of their current.
+///
+/// It only needs `MinimalPlugins` and an `AppState`, so it can run without a window.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CircuitTimer>()
+            .add_event::<CurrentPeak>()
+            .add_system(update_time.with_run_criteria(simulation_running))
+            .add_system(
+                detect_current_peaks
+                    .with_run_criteria(simulation_running)
+                    .after(update_time),
+            );
+    }
+}
+
+/// at what time the simulation ends
+pub const MAX_CIRCUIT_TIME: f64 = 100.0;
+
+/// the minimum time of the simulation, the start
+/// there isn't much reason I can see for this to not always be zero
+pub const MIN_CIRCUIT_TIME: f64 = 0.0;
+
+/* CIRCUIT ENTITY */
+#[derive(Bundle)]
+/// A bundle of the components the simulation needs to step a circuit, without drawing it
+pub struct SimulatedCircuitBundle {
+    pub circuit: DLRCCircuit,
+    pub plot: CurrentTimePlot,
+    pub sts: SpawnedThisSignum,
+    pub lcrs: LastCurrentRateSignum,
+}
+
+impl SimulatedCircuitBundle {
+    /// Creates the components for a circuit that hasn't been stepped yet
+    ///
+    /// # Arguments
+    /// * 'circuit' - the circuit calculator the entity will simulate
+    pub fn new(circuit: DLRCCircuit) -> Self {
+        Self {
+            plot: CurrentTimePlot(plot_start(&circuit.0.circuit)),
+            sts: SpawnedThisSignum(0.0, false),
+            lcrs: LastCurrentRateSignum(1.0),
+            circuit,
+        }
+    }
+}
+
+/// The first point of a circuit's plot, at the start with the current it starts with
+///
+/// # Arguments
+/// * 'circuit' - the circuit, which should be reset first
+///
+/// # Returns
+/// The current, time pairs to start plotting from
+pub fn plot_start(circuit: &RLCCalculator) -> Vec<(f64, f64)> {
+    vec![(MIN_CIRCUIT_TIME, circuit.current())]
+}
+
+#[derive(Component)]
+/// A component representing the circuit calculator, rather than the visual part.
+pub struct DLRCCircuit(pub DisconnectLightCircuitCalculator);
+
+#[derive(Component)]
+/// A component to store the current computed current, time pairs related to a circuit which has been
+pub struct CurrentTimePlot(pub Vec<(f64, f64)>);
+
+#[derive(Component)]
+/// The sign of the current that spawned the last circle
+pub struct SpawnedThisSignum(pub f64, pub bool);
+
+impl SpawnedThisSignum {
+    /// Follows the current, forgetting the last peak once the current changes sign
+    ///
+    /// # Arguments
+    /// * 'current' - the current after the latest step
+    pub fn follow(&mut self, current: f64) {
+        if current.signum() != self.0.signum() {
+            self.1 = false;
+        }
+        self.0 = current;
+    }
+}
+
+#[derive(Component)]
+/// The rate of change of current at the time the last circle was spawned
+pub struct LastCurrentRateSignum(pub f64);
+
+/// Despawns every circuit, along with their lights, arrows and couplings
+pub fn clear_circuits(
+    mut commands: Commands,
+    query_circs: Query<Entity, With<DLRCCircuit>>,
+    query_couplings: Query<Entity, With<Coupling>>,
+) {
+    despawn_circuits(&mut commands, &query_circs, &query_couplings);
+}
+
+/// Despawns every circuit, along with their lights, arrows and couplings, for systems that go on
+/// to spawn new ones
+///
+/// # Arguments
+/// * 'commands' - the commands used to despawn the entities
+/// * 'query_circs' - every circuit entity
+/// * 'query_couplings' - every coupling entity
+pub fn despawn_circuits(
+    commands: &mut Commands,
+    query_circs: &Query<Entity, With<DLRCCircuit>>,
+    query_couplings: &Query<Entity, With<Coupling>>,
+) {
+    for entity in query_circs.iter().chain(query_couplings.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/* COUPLING ENTITY */
+#[derive(Component)]
+/// A component linking two circuit entities through a mutual inductance, so they are stepped as
+/// one system
+pub struct Coupling {
+    pub circuits: (Entity, Entity),
+    /// k, the coupling coefficient between 0 and 1
+    pub coefficient: f64,
+}
+
+/* PEAKS */
+/// An event sent whenever a circuit's current reaches a local extremum
+pub struct CurrentPeak {
+    pub circuit: Entity,
+    /// the light on the circuit, none if it isn't drawn
+    pub light: Option<Entity>,
+    /// the simulation time of the peak
+    pub time: f64,
+    /// the current at the peak, negative for troughs
+    pub current: f64,
+}
+
+/* Circuit Timer Resource */
+#[derive(PartialEq)]
+/// The two modes the simulation can be in, paused or playing
+pub enum CircuitTimerMode {
+    Play,
+    Pause,
+}
+
+/// A timer keeping track of the current time in the simulation
+pub struct CircuitTimer {
+    pub time: f64,
+    pub mode: CircuitTimerMode,
+    /// the amount of simulation time passing every frame
+    pub delta_t: f64,
+    /// the time the simulation ends at
+    pub end: f64,
+    /// the largest current the plot shows, above and below zero
+    pub current_range: f64,
+    /// how many simulated seconds pass every real second, if the simulation follows the real
+    /// clock rather than stepping `delta_t` every frame
+    pub real_time_scale: Option<f64>,
+}
+
+impl Default for CircuitTimer {
+    fn default() -> Self {
+        Self {
+            time: MIN_CIRCUIT_TIME,
+            mode: CircuitTimerMode::Pause,
+            delta_t: DELTA_T,
+            end: MAX_CIRCUIT_TIME,
+            current_range: PLOT_CURRENT_RANGE,
+            real_time_scale: None,
+        }
+    }
+}
+
+impl CircuitTimer {
+    /// Scales the time steps, the end and the plot to a circuit, so it swings the same number of
+    /// times as the sandbox circuit whatever its values are
+    ///
+    /// # Arguments
+    /// * 'period' - the circuit's natural period, in seconds
+    /// * 'peak_current' - the largest current the circuit reaches
+    pub fn fit_to(&mut self, period: f64, peak_current: f64) {
+        if !(period.is_finite() && period > 0.0) {
+            return;
+        }
+        self.delta_t = period / FRAMES_PER_PERIOD;
+        self.end = MIN_CIRCUIT_TIME + period * PERIODS_SHOWN;
+        if peak_current.is_finite() && peak_current > 0.0 {
+            self.current_range = peak_current * 1.25;
+        }
+    }
+
+    /// The amount of simulation time passing this frame
+    ///
+    /// # Arguments
+    /// * 'real_delta' - the real time since the last frame, in seconds
+    pub fn step(&self, real_delta: f64) -> f64 {
+        match self.real_time_scale {
+            Some(scale) => scale * real_delta,
+            None => self.delta_t,
+        }
+    }
+
+    /// Goes back to the usual time steps, end and plot, which levels and rhythm runs are made for
+    pub fn reset_scale(&mut self) {
+        self.delta_t = DELTA_T;
+        self.end = MAX_CIRCUIT_TIME;
+        self.current_range = PLOT_CURRENT_RANGE;
+    }
+
+    /// Whether the time steps, end or plot have been scaled away from the usual ones
+    pub fn is_rescaled(&self) -> bool {
+        self.delta_t != DELTA_T
+            || self.end != MAX_CIRCUIT_TIME
+            || self.current_range != PLOT_CURRENT_RANGE
+    }
+}
+
+///the amount of simulation time passing every frame, unless the timer is rescaled
+///this intentionally doesn't make the simulation run in real time
+pub const DELTA_T: f64 = 0.1;
+/// the largest current the plot shows, unless the timer is rescaled
+pub const PLOT_CURRENT_RANGE: f64 = 10.0;
+/// how many frames a rescaled circuit takes to swing once, about the same as the sandbox circuit
+const FRAMES_PER_PERIOD: f64 = 300.0;
+/// how many swings of a rescaled circuit fit before the end, about the same as the sandbox circuit
+const PERIODS_SHOWN: f64 = 3.25;
+
+/// Updates the timer and other time senstitive parts of the simulation
+pub fn update_time(
+    mut time: ResMut<CircuitTimer>,
+    real_time: Res<Time>,
+    mut query_circs: Query<(
+        Entity,
+        &mut DLRCCircuit,
+        &mut CurrentTimePlot,
+        &mut SpawnedThisSignum,
+        &mut LastCurrentRateSignum,
+    )>,
+    query_couplings: Query<&Coupling>,
+) {
+    if time.mode == CircuitTimerMode::Play {
+        let step = time.step(real_time.delta_seconds_f64());
+        time.time += step;
+        let mut circs: Vec<_> = query_circs.iter_mut().collect();
+        for (_, circ, _, _, lcrs) in circs.iter_mut() {
+            lcrs.0 = circ.0.circuit.current_rate().signum();
+        }
+
+        //coupled circuits have to be stepped together
+        let mut ticked = vec![false; circs.len()];
+        for coupling in query_couplings.iter() {
+            let (a, b) = coupling.circuits;
+            let a = circs.iter().position(|circ| circ.0 == a);
+            let b = circs.iter().position(|circ| circ.0 == b);
+            if let (Some(a), Some(b)) = (a, b) {
+                if a == b || ticked[a] || ticked[b] {
+                    continue;
+                }
+                let (low, high) = circs.split_at_mut(a.max(b));
+                let (first, second) = (&mut low[a.min(b)].1, &mut high[0].1);
+                first
+                    .0
+                    .circuit
+                    .tick_coupled(&mut second.0.circuit, coupling.coefficient, step);
+                ticked[a] = true;
+                ticked[b] = true;
+            }
+        }
+
+        for ((_, mut circ, mut plot, mut sts, _), ticked) in circs.into_iter().zip(ticked) {
+            if !ticked {
+                circ.0.circuit.tick(step);
+            }
+            let new_current = circ.0.circuit.current();
+            plot.0.push((time.time, new_current));
+            sts.follow(new_current);
+        }
+    }
+
+    if time.time > time.end {
+        time.time = time.end;
+        time.mode = CircuitTimerMode::Pause;
+    }
+    time.time = time.time.max(MIN_CIRCUIT_TIME);
+}
+
+/// Works out whether a circuit's current has just reached a local extremum, away from the ends of
+/// the simulation and big enough to see
+///
+/// # Arguments
+/// * 'timer' - the timer, for the time, how long a step is and how much current the plot shows
+/// * 'circuit' - the circuit, just stepped
+/// * 'sts' - the sign of the current and whether it has already peaked with that sign
+/// * 'lcrs' - the sign of the current's rate of change before the step
+///
+/// # Returns
+/// Whether the current peaked on this step
+pub fn is_current_peak(
+    timer: &CircuitTimer,
+    circuit: &RLCCalculator,
+    sts: &SpawnedThisSignum,
+    lcrs: &LastCurrentRateSignum,
+) -> bool {
+    //two frames from either end, and a fiftieth of the plot's current, at any time scale
+    let time_epsilon = 2.0 * timer.delta_t;
+    let current_epsilon = timer.current_range * 0.02;
+    circuit.current_rate().signum() != lcrs.0
+        && timer.time > MIN_CIRCUIT_TIME + time_epsilon
+        && timer.time < timer.end - time_epsilon
+        && !sts.1
+        && circuit.current().abs() > current_epsilon
+}
+
+/// Sends a `CurrentPeak` whenever a circuit's current reaches a local extremum, away from the ends
+/// of the simulation and big enough to see
+fn detect_current_peaks(
+    circuit_timer: Res<CircuitTimer>,
+    mut peak_writer: EventWriter<CurrentPeak>,
+    query_lights: Query<(Entity, &Parent), With<Light>>,
+    mut query_circs: Query<(
+        Entity,
+        &DLRCCircuit,
+        &mut SpawnedThisSignum,
+        &LastCurrentRateSignum,
+    )>,
+) {
+    for (entity, circuit, mut sts, lcrs) in query_circs.iter_mut() {
+        let rlc = &circuit.0.circuit;
+        if is_current_peak(&circuit_timer, rlc, &sts, lcrs) {
+            let light = query_lights
+                .iter()
+                .find(|(_, parent)| parent.0 == entity)
+                .map(|(light, _)| light);
+            peak_writer.send(CurrentPeak {
+                circuit: entity,
+                light,
+                time: circuit_timer.time,
+                current: rlc.current(),
+            });
+            sts.1 = true;
+        }
+    }
+}
@@ -1,13 +1,14 @@
 use bevy::prelude::*;
 use bevy_egui::egui::{
-    plot::{GridMark, Line, Plot, Points, Text, Value, Values},
+    plot::{GridMark, Line, LineStyle, Plot, Points, Text, VLine, Value, Values},
     Align2, Color32, RichText,
 };
 use bevy_egui::{egui, EguiContext, EguiPlugin};
 
 use crate::graphics::{
-    CircuitTimer, CircuitTimerMode, CurrentTimePlot, DLRCCircuit, MAX_CIRCUIT_TIME,
-    MIN_CIRCUIT_TIME,
+    format_si, plot_start, si_value_edit, simulation_shown, spawn_coupled_circuit, ActiveLevel,
+    CircuitTimer, CircuitTimerMode, Coupling, CurrentTimePlot, DLRCCircuit, Part, RhythmRun,
+    SyncChallenge, DEFAULT_SIGNIFICANT_FIGURES, MIN_CIRCUIT_TIME,
 };
 
 ///Plugin to add sliders and plot to the game
@@ -16,17 +17,22 @@ pub struct UIWindowsPlugin;
 impl Plugin for UIWindowsPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(EguiPlugin)
-            .add_system(left_slider_frame)
-            .add_system(circuit_plot);
+            .add_system(left_slider_frame.with_run_criteria(simulation_shown))
+            .add_system(circuit_plot.with_run_criteria(simulation_shown));
     }
 }
 
 /// create a window with the desired sliders
 fn left_slider_frame(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
     mut egui_context: ResMut<EguiContext>,
-    mut query_circs: Query<(&mut DLRCCircuit, &mut CurrentTimePlot)>,
+    mut query_circs: Query<(Entity, &mut DLRCCircuit, &mut CurrentTimePlot)>,
+    mut query_couplings: Query<(Entity, &mut Coupling)>,
     mut time: ResMut<CircuitTimer>,
+    active: Res<ActiveLevel>,
 ) {
+    let level = &active.level;
     egui::Window::new("Circuit")
         .anchor(Align2::LEFT_CENTER, [50.0, 200.0])
         .fixed_size([200.0, 200.0])
@@ -43,38 +49,88 @@ fn left_slider_frame(
         .show(egui_context.ctx_mut(), |ui| {
             ui.add(
                 //the f32 cast should be fine
-                egui::ProgressBar::new((time.time / (MAX_CIRCUIT_TIME - MIN_CIRCUIT_TIME)) as f32)
-                    .text(format!("time since start: {:.1} (s)", time.time)),
+                egui::ProgressBar::new(
+                    ((time.time - MIN_CIRCUIT_TIME) / (time.end - MIN_CIRCUIT_TIME)) as f32,
+                )
+                .text(format!(
+                    "time since start: {}",
+                    format_si(time.time, "s", DEFAULT_SIGNIFICANT_FIGURES)
+                )),
             );
-            for (mut dlcc, _) in query_circs.iter_mut() {
-                let r = &mut dlcc.0.circuit.resistance;
-                ui.add(
-                    egui::Slider::new(r, 0.00..=1.0)
-                        .text("R (\u{03A9})")
-                        .text_color(egui::Color32::WHITE)
-                        .fixed_decimals(2),
-                );
-                let l = &mut dlcc.0.circuit.inductance;
-                ui.add(
-                    egui::Slider::new(l, 0.1..=10.0)
-                        .text("L (H)")
-                        .text_color(egui::Color32::WHITE)
-                        .fixed_decimals(2),
-                );
-                let c = &mut dlcc.0.circuit.capacitance;
-                ui.add(
-                    egui::Slider::new(c, 0.1..=10.0)
-                        .text("C (F)")
-                        .text_color(egui::Color32::WHITE)
-                        .fixed_decimals(2),
-                );
-                let start_q = &mut dlcc.0.circuit.startcharge;
+            let circuit_count = query_circs.iter().count();
+            for (index, (_, mut dlcc, mut plot)) in query_circs.iter_mut().enumerate() {
+                if circuit_count > 1 {
+                    ui.label(RichText::new(format!("circuit {}", index + 1)).color(Color32::WHITE));
+                }
+                let rlc = &mut dlcc.0.circuit;
+                let mut changed = false;
+                for (value, part, range, label, unit) in [
+                    (
+                        &mut rlc.resistance,
+                        Part::Resistor,
+                        level.ranges.resistance,
+                        "R",
+                        "\u{3a9}",
+                    ),
+                    (
+                        &mut rlc.inductance,
+                        Part::Inductor,
+                        level.ranges.inductance,
+                        "L",
+                        "H",
+                    ),
+                    (
+                        &mut rlc.capacitance,
+                        Part::Capacitor,
+                        level.ranges.capacitance,
+                        "C",
+                        "F",
+                    ),
+                    (
+                        &mut rlc.startcharge,
+                        Part::Charge,
+                        level.ranges.startcharge,
+                        "starting Q",
+                        "C",
+                    ),
+                    (
+                        &mut rlc.startcurrent,
+                        Part::Current,
+                        level.ranges.startcurrent,
+                        "starting I",
+                        "A",
+                    ),
+                ] {
+                    changed |= ui
+                        .add_enabled_ui(level.allows(part), |ui| {
+                            value_slider(ui, (index, label), value, range, label, unit)
+                        })
+                        .inner;
+                }
+                //until the circuit is played, it shows the values it will start with
+                if changed && time.time <= MIN_CIRCUIT_TIME {
+                    rlc.reset();
+                    plot.0 = plot_start(rlc);
+                }
+            }
+            for (coupling_entity, mut coupling) in query_couplings.iter_mut() {
                 ui.add(
-                    egui::Slider::new(start_q, 0.0..=50.0)
-                        .text("starting Q (C)")
+                    egui::Slider::new(&mut coupling.coefficient, 0.0..=0.99)
+                        .text("k (coupling)")
                         .text_color(egui::Color32::WHITE)
                         .fixed_decimals(2),
                 );
+                if ui.button("Remove coupled circuit").clicked() {
+                    commands.entity(coupling.circuits.1).despawn_recursive();
+                    commands.entity(coupling_entity).despawn();
+                }
+            }
+            if query_couplings.is_empty() && level.allows(Part::Coupling) {
+                if let Some((first, circuit, _)) = query_circs.iter().next() {
+                    if ui.button("Add coupled circuit").clicked() {
+                        spawn_coupled_circuit(&mut commands, &asset_server, (first, circuit), 0.2);
+                    }
+                }
             }
             ui.with_layout(egui::Layout::left_to_right(), |ui| {
                 //start, stop, and rewind buttons
@@ -83,18 +139,63 @@ fn left_slider_frame(
                 if ui.button("Reset").clicked() {
                     time.time = MIN_CIRCUIT_TIME;
                     time.mode = CircuitTimerMode::Pause;
-                    for (mut dlcc, mut plot) in query_circs.iter_mut() {
+                    for (_, mut dlcc, mut plot) in query_circs.iter_mut() {
                         dlcc.0.circuit.reset();
-                        plot.0.clear();
-                        plot.0.push((MIN_CIRCUIT_TIME, 0.0));
+                        plot.0 = plot_start(&dlcc.0.circuit);
                     }
                 }
             });
         });
 }
 
+/// Adds a log-scaled slider for one of a circuit's values, with a box to type it in with its unit
+///
+/// # Arguments
+/// * 'ui' - the ui to add the slider to
+/// * 'id_source' - what the typing box is remembered by
+/// * 'value' - the value the slider changes
+/// * 'range' - the smallest and largest values the slider reaches
+/// * 'label' - the name written after the slider
+/// * 'unit' - the unit's symbol
+///
+/// # Returns
+/// Whether the value was changed, by the slider or by typing
+pub fn value_slider(
+    ui: &mut egui::Ui,
+    id_source: impl std::hash::Hash,
+    value: &mut f64,
+    (min, max): (f64, f64),
+    label: &str,
+    unit: &str,
+) -> bool {
+    //a range from zero would spend most of the slider on tiny values
+    let smallest = if min > 0.0 { min } else { max * 1e-3 };
+    ui.horizontal(|ui| {
+        let slid = ui
+            .add(
+                egui::Slider::new(value, min..=max)
+                    .logarithmic(true)
+                    .smallest_positive(smallest)
+                    .show_value(false),
+            )
+            .changed();
+        let typed = si_value_edit(ui, id_source, value, min..=max, unit);
+        ui.label(RichText::new(label).color(Color32::WHITE));
+        slid || typed
+    })
+    .inner
+}
+
 /// creates a window containing a plot of the current against time
-fn circuit_plot(mut egui_ctx: ResMut<EguiContext>, query_circs: Query<&CurrentTimePlot>) {
+fn circuit_plot(
+    mut egui_ctx: ResMut<EguiContext>,
+    query_circs: Query<&CurrentTimePlot>,
+    challenge: Res<SyncChallenge>,
+    rhythm: Res<RhythmRun>,
+    time: Res<CircuitTimer>,
+) {
+    let (end, range) = (time.end, time.current_range);
+    let beat_times = rhythm.beat_times();
     egui::Window::new("current")
         .title_bar(false)
         .anchor(Align2::LEFT_TOP, [0.0, 100.0])
@@ -109,7 +210,7 @@ fn circuit_plot(mut egui_ctx: ResMut<EguiContext>, query_circs: Query<&CurrentTi
                 }),
         )
         .show(egui_ctx.ctx_mut(), |ui| {
-            for points in query_circs.iter() {
+            for (index, points) in query_circs.iter().enumerate() {
                 let line = Line::new(Values::from_values_iter(
                     points.0.iter().map(|&(a, b)| Value::new(a, b)),
                 ))
@@ -121,15 +222,15 @@ fn circuit_plot(mut egui_ctx: ResMut<EguiContext>, query_circs: Query<&CurrentTi
                 //stupid hack to get graph to have fixed axis
                 //basically just add the boundry points to the graph
                 let boundry_points = vec![
-                    Value::new(MIN_CIRCUIT_TIME, -10.0),
-                    Value::new(MIN_CIRCUIT_TIME, 10.0),
-                    Value::new(MAX_CIRCUIT_TIME, -10.0),
-                    Value::new(MAX_CIRCUIT_TIME, 10.0),
+                    Value::new(MIN_CIRCUIT_TIME, -range),
+                    Value::new(MIN_CIRCUIT_TIME, range),
+                    Value::new(end, -range),
+                    Value::new(end, range),
                 ];
-                Plot::new("")
+                Plot::new(("current plot", index))
                     .show_background(false)
                     .view_aspect(1.0)
-                    .data_aspect(5.0)
+                    .data_aspect(((end - MIN_CIRCUIT_TIME) / (2.0 * range)) as f32)
                     .allow_scroll(false)
                     .allow_zoom(false)
                     .allow_drag(false)
@@ -150,6 +251,22 @@ fn circuit_plot(mut egui_ctx: ResMut<EguiContext>, query_circs: Query<&CurrentTi
                     .show_x(false)
                     .show_y(false)
                     .show(ui, |plot_ui| {
+                        for &beat in beat_times.iter() {
+                            plot_ui.vline(
+                                VLine::new(beat)
+                                    .color(Color32::from_rgba_unmultiplied(255, 255, 255, 40))
+                                    .style(LineStyle::dotted_loose()),
+                            );
+                        }
+                        if let Some(target) = &challenge.target {
+                            plot_ui.line(
+                                Line::new(Values::from_values_iter(
+                                    target.iter().map(|&(a, b)| Value::new(a, b)),
+                                ))
+                                .color(Color32::from_rgb(230, 160, 40))
+                                .style(LineStyle::dashed_loose()),
+                            );
+                        }
                         plot_ui.line(line);
                         plot_ui.points(
                             Points::new(Values::from_values(boundry_points))
@@ -157,14 +274,14 @@ fn circuit_plot(mut egui_ctx: ResMut<EguiContext>, query_circs: Query<&CurrentTi
                         );
                         plot_ui.text(
                             Text::new(
-                                Value::new(1.0, 10.0),
+                                Value::new(end * 0.01, range),
                                 RichText::new("current").size(20.0).color(Color32::WHITE),
                             )
                             .anchor(Align2::LEFT_TOP),
                         );
                         plot_ui.text(
                             Text::new(
-                                Value::new(90.0, -0.1),
+                                Value::new(end * 0.9, -range * 0.01),
                                 RichText::new("time").size(20.0).color(Color32::WHITE),
                             )
                             .anchor(Align2::LEFT_TOP),
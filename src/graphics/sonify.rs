@@ -0,0 +1,791 @@
+//! hearing the circuit: a synthesized tone whose loudness follows the current and whose pitch
+//! follows the circuit's damped frequency, and mappings from the circuit to the music
+
+use bevy::{audio::AudioSink, prelude::*, reflect::TypeUuid};
+use bevy_egui::egui::{Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::TAU;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::graphics::{
+    add_audio_source, simulation_shown, AppState, AudioAssets, AudioRole, AudioSettings, Channel,
+    CurrentPeak, DLRCCircuit,
+};
+use crate::storage::SaveFile;
+use crate::DisconnectLightCircuitCalculator;
+
+/// This plugin gives every circuit a tone, kept in step with its current, and drives the audio
+/// parameters from the circuit through the mappings in `AudioSettings`.
+pub struct SonificationPlugin;
+
+impl Plugin for SonificationPlugin {
+    fn build(&self, app: &mut App) {
+        add_audio_source::<CircuitTone>(app);
+        app.init_resource::<AudioParameters>()
+            .add_system(spawn_tone_voices)
+            .add_system(update_tones)
+            .add_system(apply_mappings)
+            .init_resource::<PulseVoices>()
+            .add_event::<PulseNote>()
+            .add_system(choose_pulse_notes)
+            .add_system(play_pulse_notes.after(choose_pulse_notes))
+            .add_system(mapping_frame.with_run_criteria(simulation_shown))
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(pause_tones))
+            .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(resume_tones));
+    }
+}
+
+/// how loud a tone is at the largest current
+const TONE_VOLUME: f32 = 0.15;
+/// the sample rate of the synthesized audio
+pub const SAMPLE_RATE: u32 = 44100;
+/// how much of the way to its target the tone moves each sample, so changes don't click
+const SMOOTHING: f32 = 0.002;
+
+/* TONE SOURCE */
+/// What a tone should sound like, written by the game and read by the audio thread
+#[derive(Default)]
+pub struct ToneParameters {
+    /// the bits of an f32 from 0 to 1
+    amplitude: AtomicU32,
+    /// the bits of an f32 in hertz
+    frequency: AtomicU32,
+}
+
+impl ToneParameters {
+    pub fn amplitude(&self) -> f32 {
+        f32::from_bits(self.amplitude.load(Ordering::Relaxed))
+    }
+
+    pub fn frequency(&self) -> f32 {
+        f32::from_bits(self.frequency.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, amplitude: f32, frequency: f32) {
+        self.amplitude.store(amplitude.to_bits(), Ordering::Relaxed);
+        self.frequency.store(frequency.to_bits(), Ordering::Relaxed);
+    }
+}
+
+#[derive(TypeUuid)]
+#[uuid = "a093a2d4-99c5-43e3-b36f-aa1f4a83be9f"]
+/// A sine tone that follows its `ToneParameters` for as long as anything else holds them
+pub struct CircuitTone {
+    pub parameters: Arc<ToneParameters>,
+}
+
+/// The samples of a `CircuitTone`
+pub struct ToneDecoder {
+    parameters: Arc<ToneParameters>,
+    phase: f32,
+    amplitude: f32,
+    frequency: f32,
+}
+
+impl Iterator for ToneDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        //the circuit this tone belonged to is gone
+        if Arc::strong_count(&self.parameters) == 1 {
+            return None;
+        }
+        self.amplitude += (self.parameters.amplitude() - self.amplitude) * SMOOTHING;
+        self.frequency += (self.parameters.frequency() - self.frequency) * SMOOTHING;
+        self.phase = (self.phase + self.frequency / SAMPLE_RATE as f32) % 1.0;
+        Some(self.amplitude * (self.phase * TAU).sin())
+    }
+}
+
+impl rodio::Source for ToneDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Decodable for CircuitTone {
+    type Decoder = ToneDecoder;
+    type DecoderItem = f32;
+
+    fn decoder(&self) -> ToneDecoder {
+        ToneDecoder {
+            parameters: self.parameters.clone(),
+            phase: 0.0,
+            amplitude: 0.0,
+            frequency: self.parameters.frequency(),
+        }
+    }
+}
+
+/* VOICES */
+#[derive(Component)]
+/// The tone playing for a circuit, which stops once the circuit is despawned
+pub struct ToneVoice {
+    pub parameters: Arc<ToneParameters>,
+    pub sink: Handle<AudioSink>,
+}
+
+/// Calculates the pitch of a circuit's tone
+///
+/// # Arguments
+/// * 'circuit' - the circuit being played
+/// * 'time_scale' - how many times faster than the simulation the tone runs
+///
+/// # Returns
+/// The damped frequency of the circuit in hertz, sped up by the time scale, or none if the
+/// circuit doesn't oscillate
+pub fn tone_frequency(circuit: &DLRCCircuit, time_scale: f64) -> Option<f32> {
+    circuit
+        .0
+        .circuit
+        .damped_angular_frequency()
+        .map(|wd| (wd / std::f64::consts::TAU * time_scale) as f32)
+}
+
+/// Estimates the largest current any of the circuits could reach
+///
+/// # Returns
+/// The largest peak current of any circuit, were it without resistance
+pub fn largest_current<'a>(circuits: impl Iterator<Item = &'a DLRCCircuit>) -> f64 {
+    circuits
+        .map(|circuit| circuit.0.circuit.peak_current())
+        .fold(0.0, f64::max)
+}
+
+/// Starts a tone for every circuit without one
+fn spawn_tone_voices(
+    mut commands: Commands,
+    tone_audio: Res<Audio<CircuitTone>>,
+    mut tone_sources: ResMut<Assets<CircuitTone>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    query_circs: Query<Entity, (With<DLRCCircuit>, Without<ToneVoice>)>,
+) {
+    for entity in query_circs.iter() {
+        let parameters = Arc::new(ToneParameters::default());
+        let source = tone_sources.add(CircuitTone {
+            parameters: parameters.clone(),
+        });
+        let sink = audio_sinks.get_handle(tone_audio.play(source));
+        commands
+            .entity(entity)
+            .insert(ToneVoice { parameters, sink });
+    }
+}
+
+/// Works out how loud a circuit's tone should be
+///
+/// Each tone is as loud as its current compared to the largest current any of the circuits could
+/// reach, so a coupled circuit that starts uncharged is heard picking up energy.
+///
+/// # Arguments
+/// * 'circuit' - the circuit being played
+/// * 'largest_current' - the current the biggest peaks reach
+/// * 'settings' - whether tones are on, and the effects volume
+///
+/// # Returns
+/// The tone's amplitude, which is 0 if the circuit doesn't oscillate
+pub fn tone_loudness(circuit: &DLRCCircuit, largest_current: f64, settings: &AudioSettings) -> f32 {
+    let rlc = &circuit.0.circuit;
+    if !settings.tone || largest_current <= 0.0 || rlc.damped_angular_frequency().is_none() {
+        return 0.0;
+    }
+    let size = (rlc.current().abs() / largest_current).min(1.0);
+    size as f32 * TONE_VOLUME * settings.mixer.gain(Channel::Effects)
+}
+
+/// Sets the loudness and pitch of every tone from its circuit
+fn update_tones(settings: Res<AudioSettings>, query_circs: Query<(&DLRCCircuit, &ToneVoice)>) {
+    let largest_current = largest_current(query_circs.iter().map(|(circuit, _)| circuit));
+    for (circuit, voice) in query_circs.iter() {
+        //keep the pitch of a circuit that stops oscillating so the tone fades out rather than
+        //sliding away
+        let frequency = tone_frequency(circuit, settings.tone_time_scale)
+            .unwrap_or_else(|| voice.parameters.frequency());
+        voice.parameters.set(
+            tone_loudness(circuit, largest_current, &settings),
+            frequency,
+        );
+    }
+}
+
+/// pauses every tone along with the simulation
+fn pause_tones(audio_sinks: Res<Assets<AudioSink>>, query_voices: Query<&ToneVoice>) {
+    for voice in query_voices.iter() {
+        if let Some(sink) = audio_sinks.get(&voice.sink) {
+            sink.pause();
+        }
+    }
+}
+
+/// picks every tone back up when the simulation is unpaused
+fn resume_tones(audio_sinks: Res<Assets<AudioSink>>, query_voices: Query<&ToneVoice>) {
+    for voice in query_voices.iter() {
+        if let Some(sink) = audio_sinks.get(&voice.sink) {
+            sink.play();
+        }
+    }
+}
+
+/* MAPPINGS */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Something measured from the circuits, averaged over all of them
+pub enum CircuitQuantity {
+    /// the power through the lightbulb, in watts
+    Power,
+    /// the size of the current, in amps
+    Current,
+    /// the size of the current's rate of change, in amps per second
+    CurrentRate,
+    /// the energy stored in the capacitor and inductor, in joules
+    Energy,
+}
+
+impl CircuitQuantity {
+    pub const ALL: [CircuitQuantity; 4] = [
+        CircuitQuantity::Power,
+        CircuitQuantity::Current,
+        CircuitQuantity::CurrentRate,
+        CircuitQuantity::Energy,
+    ];
+
+    /// The name of the quantity, as shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            CircuitQuantity::Power => "power (W)",
+            CircuitQuantity::Current => "|current| (A)",
+            CircuitQuantity::CurrentRate => "|di/dt| (A/s)",
+            CircuitQuantity::Energy => "energy (J)",
+        }
+    }
+
+    /// Measures the quantity in a circuit
+    pub fn measure(&self, circuit: &DisconnectLightCircuitCalculator) -> f64 {
+        match self {
+            CircuitQuantity::Power => circuit.lightbulb_power(),
+            CircuitQuantity::Current => circuit.circuit.current().abs(),
+            CircuitQuantity::CurrentRate => circuit.circuit.current_rate().abs(),
+            CircuitQuantity::Energy => circuit.circuit.energy(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Something about the audio a mapping can drive
+pub enum AudioParameter {
+    MusicVolume,
+    NoiseVolume,
+    /// the speed of the music, which also shifts its pitch
+    PlaybackSpeed,
+    /// the speed the pulse note is played at, which sets its pitch
+    PulsePitch,
+}
+
+impl AudioParameter {
+    pub const ALL: [AudioParameter; 4] = [
+        AudioParameter::MusicVolume,
+        AudioParameter::NoiseVolume,
+        AudioParameter::PlaybackSpeed,
+        AudioParameter::PulsePitch,
+    ];
+
+    /// The name of the parameter, as shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioParameter::MusicVolume => "music volume",
+            AudioParameter::NoiseVolume => "noise volume",
+            AudioParameter::PlaybackSpeed => "playback speed",
+            AudioParameter::PulsePitch => "pulse note pitch",
+        }
+    }
+
+    /// The value the parameter takes when nothing is mapped to it
+    pub fn unmapped(&self) -> f32 {
+        match self {
+            AudioParameter::NoiseVolume => 0.04,
+            AudioParameter::MusicVolume
+            | AudioParameter::PlaybackSpeed
+            | AudioParameter::PulsePitch => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// How a mapping's input is shaped before it's scaled to the output range
+pub enum Curve {
+    Linear,
+    /// slow at first, then fast
+    Squared,
+    /// fast at first, then slow
+    SquareRoot,
+    /// slow at both ends
+    Smooth,
+}
+
+impl Curve {
+    pub const ALL: [Curve; 4] = [
+        Curve::Linear,
+        Curve::Squared,
+        Curve::SquareRoot,
+        Curve::Smooth,
+    ];
+
+    /// The name of the curve, as shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            Curve::Linear => "linear",
+            Curve::Squared => "squared",
+            Curve::SquareRoot => "square root",
+            Curve::Smooth => "smooth",
+        }
+    }
+
+    /// Shapes a value from 0 to 1 into another value from 0 to 1
+    pub fn apply(&self, t: f64) -> f64 {
+        match self {
+            Curve::Linear => t,
+            Curve::Squared => t * t,
+            Curve::SquareRoot => t.sqrt(),
+            Curve::Smooth => t * t * (3.0 - 2.0 * t),
+        }
+    }
+
+    /// Maps a value from one range onto another along the curve
+    ///
+    /// # Arguments
+    /// * 'value' - the value to map
+    /// * 'input' - the values mapped to the start and end of the output, clamped outside of them
+    /// * 'output' - the values at the start and end of the input, which can be reversed
+    pub fn map(&self, value: f64, input: (f64, f64), output: (f32, f32)) -> f32 {
+        let (start, end) = input;
+        let t = if end == start {
+            (value >= end) as u8 as f64
+        } else {
+            ((value - start) / (end - start)).clamp(0.0, 1.0)
+        };
+        let (low, high) = output;
+        low + (high - low) * self.apply(t) as f32
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Binds a circuit quantity to an audio parameter
+pub struct Mapping {
+    pub quantity: CircuitQuantity,
+    pub parameter: AudioParameter,
+    pub curve: Curve,
+    /// the quantity's values mapped to the start and end of the output, clamped outside of them
+    pub input: (f64, f64),
+    /// the parameter's values at the start and end of the input, which can be reversed
+    pub output: (f32, f32),
+}
+
+impl Mapping {
+    /// Maps a measured quantity to a value of the parameter
+    pub fn map(&self, value: f64) -> f32 {
+        self.curve.map(value, self.input, self.output)
+    }
+}
+
+/// The mappings the app starts with, where the noise dies down as the lightbulb brightens
+pub fn default_mappings() -> Vec<Mapping> {
+    vec![Mapping {
+        quantity: CircuitQuantity::Power,
+        parameter: AudioParameter::NoiseVolume,
+        curve: Curve::Linear,
+        input: (0.0, 0.04),
+        output: (0.04, 0.0),
+    }]
+}
+
+#[derive(Debug, Clone)]
+/// The audio parameters worked out from the mappings this frame
+pub struct AudioParameters {
+    pub music_volume: f32,
+    pub noise_volume: f32,
+    pub playback_speed: f32,
+    pub pulse_pitch: f32,
+}
+
+impl Default for AudioParameters {
+    fn default() -> Self {
+        Self {
+            music_volume: AudioParameter::MusicVolume.unmapped(),
+            noise_volume: AudioParameter::NoiseVolume.unmapped(),
+            playback_speed: AudioParameter::PlaybackSpeed.unmapped(),
+            pulse_pitch: AudioParameter::PulsePitch.unmapped(),
+        }
+    }
+}
+
+impl AudioParameters {
+    /// The value of a parameter
+    pub fn get(&self, parameter: AudioParameter) -> f32 {
+        match parameter {
+            AudioParameter::MusicVolume => self.music_volume,
+            AudioParameter::NoiseVolume => self.noise_volume,
+            AudioParameter::PlaybackSpeed => self.playback_speed,
+            AudioParameter::PulsePitch => self.pulse_pitch,
+        }
+    }
+
+    fn get_mut(&mut self, parameter: AudioParameter) -> &mut f32 {
+        match parameter {
+            AudioParameter::MusicVolume => &mut self.music_volume,
+            AudioParameter::NoiseVolume => &mut self.noise_volume,
+            AudioParameter::PlaybackSpeed => &mut self.playback_speed,
+            AudioParameter::PulsePitch => &mut self.pulse_pitch,
+        }
+    }
+}
+
+/// Measures a quantity averaged over every circuit
+///
+/// # Returns
+/// The average, or 0 without any circuits
+pub fn average_quantity(quantity: CircuitQuantity, circuits: &[&DLRCCircuit]) -> f64 {
+    if circuits.is_empty() {
+        return 0.0;
+    }
+    circuits
+        .iter()
+        .map(|circuit| quantity.measure(&circuit.0))
+        .sum::<f64>()
+        / circuits.len() as f64
+}
+
+/// Works out every audio parameter from the mappings, averaging mappings that drive the same one
+///
+/// # Arguments
+/// * 'mappings' - the mappings from the circuits to the audio
+/// * 'circuits' - the circuits being measured
+pub fn evaluate_mappings(mappings: &[Mapping], circuits: &[&DLRCCircuit]) -> AudioParameters {
+    let mut parameters = AudioParameters::default();
+    for parameter in AudioParameter::ALL {
+        let values: Vec<f32> = mappings
+            .iter()
+            .filter(|mapping| mapping.parameter == parameter)
+            .map(|mapping| mapping.map(average_quantity(mapping.quantity, circuits)))
+            .collect();
+        if !values.is_empty() {
+            *parameters.get_mut(parameter) = values.iter().sum::<f32>() / values.len() as f32;
+        }
+    }
+    parameters
+}
+
+/// Updates the audio parameters from the mappings every frame
+fn apply_mappings(
+    settings: Res<AudioSettings>,
+    mut parameters: ResMut<AudioParameters>,
+    query_circs: Query<&DLRCCircuit>,
+) {
+    let circuits: Vec<&DLRCCircuit> = query_circs.iter().collect();
+    *parameters = evaluate_mappings(&settings.mappings, &circuits);
+}
+
+/// creates a window for editing the mappings from the circuit to the audio
+fn mapping_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut settings: ResMut<AudioSettings>,
+    parameters: Res<AudioParameters>,
+    query_circs: Query<&DLRCCircuit>,
+) {
+    let circuits: Vec<&DLRCCircuit> = query_circs.iter().collect();
+    egui::Window::new("Sound mapping")
+        .title_bar(false)
+        .default_pos([800.0, 500.0])
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::CollapsingHeader::new("Sound mapping")
+                .default_open(false)
+                .show(ui, |ui| {
+                    //the live values help with picking ranges
+                    for quantity in CircuitQuantity::ALL {
+                        ui.label(format!(
+                            "{}: {:.3}",
+                            quantity.name(),
+                            average_quantity(quantity, &circuits)
+                        ));
+                    }
+                    ui.separator();
+
+                    let mut removed = None;
+                    for (index, mapping) in settings.mappings.iter_mut().enumerate() {
+                        ui.push_id(index, |ui| mapping_row(ui, mapping, &parameters));
+                        if ui.button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                        ui.separator();
+                    }
+                    if let Some(index) = removed {
+                        settings.mappings.remove(index);
+                    }
+
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        if ui.button("Add mapping").clicked() {
+                            settings.mappings.push(Mapping {
+                                quantity: CircuitQuantity::Current,
+                                parameter: AudioParameter::MusicVolume,
+                                curve: Curve::Linear,
+                                input: (0.0, 1.0),
+                                output: (0.0, 1.0),
+                            });
+                        }
+                        if ui.button("Defaults").clicked() {
+                            settings.mappings = default_mappings();
+                        }
+                        if ui.button("Save").clicked() {
+                            settings.save();
+                        }
+                    });
+                });
+        });
+}
+
+/// Adds the controls for one mapping
+///
+/// # Arguments
+/// * 'ui' - the ui to add the controls to
+/// * 'mapping' - the mapping being edited
+/// * 'parameters' - the current audio parameters, to show what the mapping is doing
+fn mapping_row(ui: &mut egui::Ui, mapping: &mut Mapping, parameters: &AudioParameters) {
+    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+        quantity_combo(ui, &mut mapping.quantity);
+        ui.label("\u{2192}");
+        egui::ComboBox::from_id_source("parameter")
+            .selected_text(mapping.parameter.name())
+            .show_ui(ui, |ui| {
+                for parameter in AudioParameter::ALL {
+                    ui.selectable_value(&mut mapping.parameter, parameter, parameter.name());
+                }
+            });
+        curve_combo(ui, &mut mapping.curve);
+    });
+    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+        ui.label("from");
+        ui.add(egui::DragValue::new(&mut mapping.input.0).speed(0.01));
+        ui.label("to");
+        ui.add(egui::DragValue::new(&mut mapping.input.1).speed(0.01));
+        ui.label("gives");
+        ui.add(
+            egui::DragValue::new(&mut mapping.output.0)
+                .speed(0.01)
+                .clamp_range(0.0..=4.0),
+        );
+        ui.label("to");
+        ui.add(
+            egui::DragValue::new(&mut mapping.output.1)
+                .speed(0.01)
+                .clamp_range(0.0..=4.0),
+        );
+    });
+    ui.label(
+        RichText::new(format!("now {:.3}", parameters.get(mapping.parameter))).color(Color32::GRAY),
+    );
+}
+
+/// Adds a box to pick a circuit quantity from
+pub fn quantity_combo(ui: &mut egui::Ui, quantity: &mut CircuitQuantity) {
+    egui::ComboBox::from_id_source("quantity")
+        .selected_text(quantity.name())
+        .show_ui(ui, |ui| {
+            for option in CircuitQuantity::ALL {
+                ui.selectable_value(quantity, option, option.name());
+            }
+        });
+}
+
+/// Adds a box to pick a curve from
+pub fn curve_combo(ui: &mut egui::Ui, curve: &mut Curve) {
+    egui::ComboBox::from_id_source("curve")
+        .selected_text(curve.name())
+        .show_ui(ui, |ui| {
+            for option in Curve::ALL {
+                ui.selectable_value(curve, option, option.name());
+            }
+        });
+}
+
+/* PULSE NOTES */
+/// the names of the keys, starting from the pulse note's own pitch
+pub const KEY_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+/// how many octaves the pulse notes span, from the smallest peak to the largest
+const PULSE_OCTAVES: usize = 2;
+/// how long a pulse note rings before its voice is freed, in seconds
+const PULSE_NOTE_LENGTH: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// The scale the pulse notes are picked from
+pub enum Scale {
+    Major,
+    Minor,
+    Pentatonic,
+    Blues,
+    Chromatic,
+}
+
+impl Scale {
+    pub const ALL: [Scale; 5] = [
+        Scale::Major,
+        Scale::Minor,
+        Scale::Pentatonic,
+        Scale::Blues,
+        Scale::Chromatic,
+    ];
+
+    /// The name of the scale, as shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scale::Major => "major",
+            Scale::Minor => "minor",
+            Scale::Pentatonic => "pentatonic",
+            Scale::Blues => "blues",
+            Scale::Chromatic => "chromatic",
+        }
+    }
+
+    /// The semitones above the root of each degree in one octave of the scale
+    pub fn intervals(&self) -> &'static [i32] {
+        match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Pentatonic => &[0, 2, 4, 7, 9],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+        }
+    }
+
+    /// Picks the note for a peak, with bigger peaks climbing further up the scale
+    ///
+    /// # Arguments
+    /// * 'size' - the size of the peak, from 0 to 1
+    ///
+    /// # Returns
+    /// The semitones above the lowest root, which ranges over `PULSE_OCTAVES` octaves
+    pub fn semitones(&self, size: f64) -> i32 {
+        let intervals = self.intervals();
+        let degrees = intervals.len() * PULSE_OCTAVES;
+        let degree = (size.clamp(0.0, 1.0) * degrees as f64).round() as usize;
+        (degree / intervals.len()) as i32 * 12 + intervals[degree % intervals.len()]
+    }
+}
+
+/// A pulse note to play, picked from a peak of the current
+pub struct PulseNote {
+    /// how fast the sample is played, which sets its pitch
+    pub speed: f32,
+    pub volume: f32,
+    /// whether the note is doubled an octave below, giving troughs a darker sound
+    pub doubled: bool,
+}
+
+/// Picks a pulse note for a peak of the current
+///
+/// # Arguments
+/// * 'peak' - the current at the peak, negative for troughs
+/// * 'largest_current' - the current the biggest peaks reach
+/// * 'settings' - the scale and key to use
+/// * 'pitch' - the mapped pulse pitch, which every note is multiplied by
+pub fn pulse_note(
+    peak: f64,
+    largest_current: f64,
+    settings: &AudioSettings,
+    pitch: f32,
+) -> PulseNote {
+    let size = if largest_current > 0.0 {
+        (peak.abs() / largest_current).min(1.0)
+    } else {
+        0.0
+    };
+    //centered on the sample's own pitch
+    let semitones = settings.pulse_scale.semitones(size) + settings.pulse_key as i32
+        - 12 * PULSE_OCTAVES as i32 / 2;
+    PulseNote {
+        speed: 2f32.powf(semitones as f32 / 12.0) * pitch,
+        volume: 0.4 + 0.6 * size as f32,
+        doubled: peak < 0.0,
+    }
+}
+
+/// The pulse notes still ringing, oldest first
+#[derive(Default)]
+pub struct PulseVoices(VecDeque<(Handle<AudioSink>, f64)>);
+
+/// Turns every peak of the current into a pulse note
+fn choose_pulse_notes(
+    mut peak_reader: EventReader<CurrentPeak>,
+    mut note_writer: EventWriter<PulseNote>,
+    settings: Res<AudioSettings>,
+    parameters: Res<AudioParameters>,
+    query_circs: Query<&DLRCCircuit>,
+) {
+    let largest_current = largest_current(query_circs.iter());
+    for peak in peak_reader.iter() {
+        note_writer.send(pulse_note(
+            peak.current,
+            largest_current,
+            &settings,
+            parameters.pulse_pitch,
+        ));
+    }
+}
+
+/// Plays the pulse notes, cutting off the oldest ones once there are too many ringing
+fn play_pulse_notes(
+    mut note_reader: EventReader<PulseNote>,
+    audio_assets: Res<AudioAssets>,
+    settings: Res<AudioSettings>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    mut voices: ResMut<PulseVoices>,
+    time: Res<Time>,
+) {
+    let now = time.seconds_since_startup();
+    while matches!(voices.0.front(), Some((_, started)) if now - started > PULSE_NOTE_LENGTH) {
+        voices.0.pop_front();
+    }
+    let sound_effect = match audio_assets.handle(AudioRole::Pulse) {
+        Some(sound_effect) => sound_effect,
+        None => return,
+    };
+    let gain = settings.mixer.gain(Channel::Effects);
+    for note in note_reader.iter() {
+        let mut play = |speed: f32, volume: f32| {
+            while voices.0.len() >= settings.pulse_polyphony.max(1) {
+                if let Some((oldest, _)) = voices.0.pop_front() {
+                    if let Some(sink) = audio_sinks.get(&oldest) {
+                        sink.stop();
+                    }
+                }
+            }
+            let sink = audio.play_with_settings(
+                sound_effect.clone(),
+                PlaybackSettings::ONCE
+                    .with_speed(speed)
+                    .with_volume(volume * gain),
+            );
+            voices.0.push_back((audio_sinks.get_handle(sink), now));
+        };
+        play(note.speed, note.volume);
+        if note.doubled {
+            play(note.speed * 0.5, note.volume * 0.5);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! the screens the app moves between, and the menus shown on them
+
+use bevy::{app::AppExit, ecs::schedule::ShouldRun, prelude::*, window::WindowMode};
+use bevy_egui::egui::{Align2, Color32, RichText};
+use bevy_egui::{egui, EguiContext};
+
+use crate::graphics::{
+    audio_settings_ui, clear_circuits, rhythm_level, star_text, ActiveLevel, AudioSettings,
+    LevelBook, LevelProgress, ReloadAudio,
+};
+use crate::storage::SaveFile;
+
+/// This plugin adds the app states, starting on the main menu, along with the menus for
+/// pausing, settings and results.
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(AppState::MainMenu)
+            .init_resource::<RunSummary>()
+            .add_system(toggle_pause)
+            .add_system(menu_button.with_run_criteria(simulation_running))
+            .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(clear_circuits))
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(main_menu_frame))
+            .add_system_set(SystemSet::on_update(AppState::Paused).with_system(pause_menu_frame))
+            .add_system_set(SystemSet::on_update(AppState::Settings).with_system(settings_frame))
+            .add_system_set(SystemSet::on_update(AppState::Results).with_system(results_frame));
+    }
+}
+
+/* APP STATE */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Which screen the app is on
+pub enum AppState {
+    MainMenu,
+    /// free play, with every component and the random sync challenge
+    Sandbox,
+    /// picking a level
+    LevelSelect,
+    /// playing the level in `ActiveLevel`
+    Level,
+    /// landing the current's peaks on the beat
+    Rhythm,
+    /// the score of a finished level or rhythm run, from `RunSummary`
+    Results,
+    /// placing elements and wires on a grid, to be played in the sandbox
+    Build,
+    /// pushed on top of any other state
+    Settings,
+    /// pushed on top of a simulation state, freezing it along with the audio and effects
+    Paused,
+}
+
+impl AppState {
+    /// Whether the circuit is simulated in this state
+    pub fn is_simulation(&self) -> bool {
+        matches!(self, AppState::Sandbox | AppState::Level | AppState::Rhythm)
+    }
+}
+
+/// Finds the simulation state in the stack, even if something like the pause menu is on top of it
+///
+/// # Returns
+/// The simulation state, or none if the app isn't simulating
+pub fn simulation_state(state: &State<AppState>) -> Option<AppState> {
+    state
+        .inactives()
+        .iter()
+        .chain(std::iter::once(state.current()))
+        .rev()
+        .find(|state| state.is_simulation())
+        .copied()
+}
+
+/// Run criteria for systems that advance the simulation, which stop whenever it's paused
+pub fn simulation_running(state: Res<State<AppState>>) -> ShouldRun {
+    if state.current().is_simulation() {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Run criteria for systems that show the simulation, which stay up while it's paused
+pub fn simulation_shown(state: Res<State<AppState>>) -> ShouldRun {
+    if simulation_state(&state).is_some() {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Run criteria for systems that should only stop while paused
+pub fn not_paused(state: Res<State<AppState>>) -> ShouldRun {
+    if *state.current() == AppState::Paused {
+        ShouldRun::No
+    } else {
+        ShouldRun::Yes
+    }
+}
+
+/* RESULTS */
+/// What the results screen shows after a level or rhythm run
+pub struct RunSummary {
+    pub title: String,
+    /// from 0 to 3
+    pub stars: u8,
+    /// lines of text explaining the score
+    pub details: Vec<String>,
+    /// the state that plays the run again
+    pub retry: AppState,
+}
+
+impl Default for RunSummary {
+    fn default() -> Self {
+        Self {
+            title: String::new(),
+            stars: 0,
+            details: Vec::new(),
+            retry: AppState::Sandbox,
+        }
+    }
+}
+
+/// Pauses or unpauses the simulation with the escape key
+fn toggle_pause(keys: Res<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    if !keys.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    match state.current() {
+        AppState::Paused | AppState::Settings => {
+            let _ = state.overwrite_pop();
+        }
+        current if current.is_simulation() => {
+            let _ = state.overwrite_push(AppState::Paused);
+        }
+        _ => {}
+    }
+}
+
+/// creates a small button in the corner that opens the pause menu
+fn menu_button(mut egui_context: ResMut<EguiContext>, mut state: ResMut<State<AppState>>) {
+    egui::Area::new("menu button")
+        .anchor(Align2::LEFT_TOP, [10.0, 10.0])
+        .show(egui_context.ctx_mut(), |ui| {
+            if ui.button("Menu (Esc)").clicked() {
+                let _ = state.overwrite_push(AppState::Paused);
+            }
+        });
+}
+
+/// A window in the middle of the screen, used by all the menus
+fn menu_window(title: &str) -> egui::Window<'_> {
+    egui::Window::new(title)
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .fixed_size([250.0, 250.0])
+        .collapsible(false)
+}
+
+/// creates the main menu
+fn main_menu_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut active: ResMut<ActiveLevel>,
+    mut state: ResMut<State<AppState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    menu_window("Syncing Circuits").show(egui_context.ctx_mut(), |ui| {
+        ui.vertical_centered_justified(|ui| {
+            if ui.button("Sandbox").clicked() {
+                *active = ActiveLevel::sandbox();
+                let _ = state.overwrite_replace(AppState::Sandbox);
+            }
+            if ui.button("Build").clicked() {
+                let _ = state.overwrite_replace(AppState::Build);
+            }
+            if ui.button("Levels").clicked() {
+                let _ = state.overwrite_replace(AppState::LevelSelect);
+            }
+            if ui.button("Rhythm").clicked() {
+                *active = ActiveLevel {
+                    index: None,
+                    level: rhythm_level(),
+                    built: None,
+                };
+                let _ = state.overwrite_replace(AppState::Rhythm);
+            }
+            if ui.button("Settings").clicked() {
+                let _ = state.overwrite_push(AppState::Settings);
+            }
+            if ui.button("Quit").clicked() {
+                exit.send(AppExit);
+            }
+        });
+    });
+}
+
+/// creates the pause menu
+fn pause_menu_frame(mut egui_context: ResMut<EguiContext>, mut state: ResMut<State<AppState>>) {
+    menu_window("Paused").show(egui_context.ctx_mut(), |ui| {
+        ui.vertical_centered_justified(|ui| {
+            if ui.button("Resume").clicked() {
+                let _ = state.overwrite_pop();
+            }
+            if ui.button("Settings").clicked() {
+                let _ = state.overwrite_push(AppState::Settings);
+            }
+            if ui.button("Main menu").clicked() {
+                let _ = state.overwrite_replace(AppState::MainMenu);
+            }
+        });
+    });
+}
+
+/// creates the settings menu
+fn settings_frame(
+    mut egui_context: ResMut<EguiContext>,
+    mut windows: ResMut<Windows>,
+    mut progress: ResMut<LevelProgress>,
+    mut audio_settings: ResMut<AudioSettings>,
+    mut reload_writer: EventWriter<ReloadAudio>,
+    mut state: ResMut<State<AppState>>,
+) {
+    menu_window("Settings").show(egui_context.ctx_mut(), |ui| {
+        if let Some(window) = windows.get_primary_mut() {
+            let mut fullscreen = window.mode() != WindowMode::Windowed;
+            if ui.checkbox(&mut fullscreen, "Fullscreen").changed() {
+                window.set_mode(if fullscreen {
+                    WindowMode::BorderlessFullscreen
+                } else {
+                    WindowMode::Windowed
+                });
+            }
+        }
+        if ui.button("Reset level progress").clicked() {
+            *progress = LevelProgress::default();
+            progress.save();
+        }
+
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                audio_settings_ui(ui, &mut audio_settings, &mut reload_writer)
+            });
+
+        ui.separator();
+        if ui.button("Back").clicked() {
+            let _ = state.overwrite_pop();
+        }
+    });
+}
+
+/// creates the results screen
+fn results_frame(
+    mut egui_context: ResMut<EguiContext>,
+    summary: Res<RunSummary>,
+    book: Res<LevelBook>,
+    progress: Res<LevelProgress>,
+    mut active: ResMut<ActiveLevel>,
+    mut state: ResMut<State<AppState>>,
+) {
+    menu_window("Results").show(egui_context.ctx_mut(), |ui| {
+        ui.vertical_centered_justified(|ui| {
+            ui.heading(&summary.title);
+            ui.label(
+                RichText::new(star_text(summary.stars))
+                    .size(30.0)
+                    .color(Color32::GOLD),
+            );
+            for line in summary.details.iter() {
+                ui.label(line);
+            }
+            ui.separator();
+            if ui.button("Retry").clicked() {
+                let _ = state.overwrite_replace(summary.retry);
+            }
+            let next = active.index.map(|index| index + 1);
+            if let Some(next) = next.filter(|&next| next < book.0.len()) {
+                if ui
+                    .add_enabled(progress.is_unlocked(next), egui::Button::new("Next level"))
+                    .clicked()
+                {
+                    *active = ActiveLevel {
+                        index: Some(next),
+                        level: book.0[next].clone(),
+                        built: None,
+                    };
+                    let _ = state.overwrite_replace(AppState::Level);
+                }
+            }
+            if active.index.is_some() && ui.button("Levels").clicked() {
+                let _ = state.overwrite_replace(AppState::LevelSelect);
+            }
+            if ui.button("Main menu").clicked() {
+                let _ = state.overwrite_replace(AppState::MainMenu);
+            }
+        });
+    });
+}
@@ -6,87 +6,105 @@ use bevy_prototype_lyon::{
     shapes,
 };
 
-use crate::DisconnectLightCircuitCalculator;
-use std::cmp::PartialEq;
+use crate::graphics::{
+    despawn_circuits, simulation_running, ActiveLevel, CircuitConstants, CircuitTimer,
+    CircuitTimerMode, Coupling, CurrentPeak, DLRCCircuit, Element, Schematic,
+    SimulatedCircuitBundle, MIN_CIRCUIT_TIME,
+};
 
-/// This plugin spawns all disconnected lightbulb circuits, adds a shared manipulable timer to the resources, and updates the lightbulb brightness.
+/// This plugin draws the circuits, updates the lightbulb brightness, and pulses the lights at
+/// every peak the simulation finds.
 pub struct DLCPlugin;
 
 impl Plugin for DLCPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_plugin(ShapePlugin)
-            .add_startup_system(spawn_dlc)
-            .insert_resource(CircuitTimer {
-                time: MIN_CIRCUIT_TIME,
-                mode: CircuitTimerMode::Pause,
-            })
-            .add_system(update_lightbulb)
-            .add_system(expand_circles)
-            .add_system(update_current_arrow);
+            .add_system(update_lightbulb.with_run_criteria(simulation_running))
+            .add_system(expand_circles.with_run_criteria(simulation_running))
+            .add_system(update_current_arrow.with_run_criteria(simulation_running));
     }
 }
 
-/// at what time the simulation ends
-pub const MAX_CIRCUIT_TIME: f64 = 100.0;
-
-/// the minimum time of the simulation, the start
-/// there isn't much reason I can see for this to not always be zero
-pub const MIN_CIRCUIT_TIME: f64 = 0.0;
-
 /* CIRCUIT ENTITY */
 #[derive(Bundle)]
 /// A bundle of components defining a circuit
 pub struct CircuitBundle {
-    pub circuit: DLRCCircuit,
-    pub plot: CurrentTimePlot,
-    pub sts: SpawnedThisSignum,
-    pub lcrs: LastCurrentRateSignum,
     #[bundle]
-    pub sprite_bundle: SpriteBundle,
+    pub simulated: SimulatedCircuitBundle,
+    pub schematic: Schematic,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
 }
 
-#[derive(Component)]
-/// A component representing the circuit calculator, rather than the visual part.
-pub struct DLRCCircuit(pub DisconnectLightCircuitCalculator);
-
-#[derive(Component)]
-/// A component to store the current computed current, time pairs related to a circuit which has been
-pub struct CurrentTimePlot(pub Vec<(f64, f64)>);
-
-#[derive(Component)]
-/// The sign of the current that spawned the last circle
-pub struct SpawnedThisSignum(pub f64, pub bool);
-
-#[derive(Component)]
-/// The rate of change of current at the time the last circle was spawned
-pub struct LastCurrentRateSignum(pub f64);
-
 const ARROW_SPRITE_SCALE: f32 = 0.45;
 
-/// Spawns all circuit + light entities
-fn spawn_dlc(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let dlcc = DLRCCircuit(DisconnectLightCircuitCalculator::with_constants(
-        10.0, 0.2, 4.0, 6.0,
-    ));
+/// Replaces all circuit + light entities with the active level's built circuit, and rewinds the
+/// timer
+pub fn spawn_built_circuit(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    active: Res<ActiveLevel>,
+    mut circuit_timer: ResMut<CircuitTimer>,
+    query_circs: Query<Entity, With<DLRCCircuit>>,
+    query_couplings: Query<Entity, With<Coupling>>,
+) {
+    despawn_circuits(&mut commands, &query_circs, &query_couplings);
+    circuit_timer.time = MIN_CIRCUIT_TIME;
+    circuit_timer.mode = CircuitTimerMode::Pause;
+    circuit_timer.reset_scale();
+
+    let built = active.circuit();
+    spawn_circuit(
+        &mut commands,
+        &asset_server,
+        DLRCCircuit(built.constants.light_calculator()),
+        built.schematic(),
+        Vec3::new(-75.0, 0.0, 5.0),
+    );
+}
 
+/// Spawns a single circuit entity, along with its drawing, light and current arrow
+///
+/// # Arguments
+/// * 'commands' - the commands used to spawn the entities
+/// * 'asset_server' - the asset server used to load the arrow sprite
+/// * 'circuit' - the circuit calculator the entity will simulate
+/// * 'schematic' - how the circuit is drawn
+/// * 'translation' - where the circuit is placed, though it's moved along to make room if
+///   another circuit is in the way
+///
+/// # Returns
+/// The entity of the spawned circuit
+pub fn spawn_circuit(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    circuit: DLRCCircuit,
+    schematic: Schematic,
+    translation: Vec3,
+) -> Entity {
     let circle_builder = GeometryBuilder::new().add(&shapes::Circle {
         radius: 10.0,
         ..shapes::Circle::default()
     });
+    //the light sits on the bulb, or the first resistor if there's no bulb
+    let bulb = schematic
+        .element(Element::Bulb)
+        .or_else(|| schematic.element(Element::Resistor))
+        .map_or(0.0, |placed| placed.center());
+    let light_translation = Vec3::new(bulb, schematic.top(), 15.0);
+    //wide schematics are shrunk to take up the same room as the usual three elements
+    let scale = 0.3 * (3.0 / schematic.elements.len().max(3) as f32);
+    let drawing = schematic.shape();
 
     commands
         .spawn_bundle(CircuitBundle {
-            circuit: dlcc,
-            //initialized with MIN_CIRCUIT_TIME, 0.0, because that is what it starts as
-            plot: CurrentTimePlot(vec![(MIN_CIRCUIT_TIME, 0.0)]),
-            sts: SpawnedThisSignum(0.0, false),
-            lcrs: LastCurrentRateSignum(1.0),
-            sprite_bundle: SpriteBundle {
-                texture: asset_server.load("series-edited.png"),
-                transform: Transform::from_scale(Vec3::splat(0.3))
-                    .with_translation(Vec3::new(-75.0, 0.0, 5.0)),
-                ..default()
-            },
+            simulated: SimulatedCircuitBundle::new(circuit),
+            schematic,
+            transform: Transform::from_scale(Vec3::splat(scale)).with_translation(translation),
+            global_transform: GlobalTransform::default(),
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(drawing);
         })
         .with_children(|parent| {
             parent.spawn_bundle(LightBundle {
@@ -96,8 +114,7 @@ fn spawn_dlc(mut commands: Commands, asset_server: Res<AssetServer>) {
                         fill_mode: FillMode::color(Color::hsla(0.0, 0.0, 0.0, 0.7)),
                         outline_mode: StrokeMode::new(Color::hsla(0.0, 0.0, 1.0, 1.0), 1.0),
                     },
-                    Transform::from_scale(Vec3::splat(18.0))
-                        .with_translation(Vec3::new(-505.0, 335.0, 15.0)),
+                    Transform::from_scale(Vec3::splat(18.0)).with_translation(light_translation),
                 ),
             });
         })
@@ -112,7 +129,49 @@ fn spawn_dlc(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ..default()
                 },
             });
-        });
+        })
+        .id()
+}
+
+/// Spawns a second circuit next to the given one, coupled to it through a mutual inductance
+///
+/// # Arguments
+/// * 'commands' - the commands used to spawn the entities
+/// * 'asset_server' - the asset server used to load the arrow sprite
+/// * 'partner' - the circuit entity the new circuit will be coupled to, and its calculator, whose
+///   values the new circuit copies so the two are in resonance
+/// * 'coefficient' - k, the starting coupling coefficient
+pub fn spawn_coupled_circuit(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    (partner, partner_circuit): (Entity, &DLRCCircuit),
+    coefficient: f64,
+) {
+    let rlc = &partner_circuit.0.circuit;
+    //starts uncharged so the energy visibly moves over from the partner
+    let mut dlcc = DLRCCircuit(
+        CircuitConstants {
+            startcharge: 0.0,
+            startcurrent: 0.0,
+            resistance: rlc.resistance,
+            inductance: rlc.inductance,
+            capacitance: rlc.capacitance,
+            emf: rlc.emf,
+        }
+        .light_calculator(),
+    );
+    dlcc.0.circuit.forced_substep = rlc.forced_substep;
+    let circuit = spawn_circuit(
+        commands,
+        asset_server,
+        dlcc,
+        Schematic::light_circuit(),
+        Vec3::new(425.0, 0.0, 5.0),
+    );
+    commands.spawn().insert(Coupling {
+        circuits: (partner, circuit),
+        coefficient,
+    });
 }
 
 /* LIGHTBULB ENTITY */
@@ -128,64 +187,27 @@ pub struct LightBundle {
 /// A marker component to indicate this shape is a light.
 pub struct Light;
 
-/// Updates the colors of all light entities based on the time provided by CircuitTimer and the
-/// current circuit. Also, if the lightbulb power is at a peak, a circle is spawned.
+/// Updates the colors of all light entities based on the current circuit. Also, a circle is
+/// spawned on the light of every circuit whose current peaked.
 fn update_lightbulb(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    audio: Res<Audio>,
-    circuit_timer: ResMut<CircuitTimer>,
-    mut query_lights: Query<(Entity, &Parent, &mut DrawMode), With<Light>>,
-    mut query_circs: Query<(
-        &mut DLRCCircuit,
-        &mut SpawnedThisSignum,
-        &mut LastCurrentRateSignum,
-    )>,
+    mut peak_reader: EventReader<CurrentPeak>,
+    mut query_lights: Query<(&Parent, &mut DrawMode), With<Light>>,
+    query_circs: Query<&DLRCCircuit>,
 ) {
-    for (entity, parent, mut draw_mode) in query_lights.iter_mut() {
-        let mut parent_circuit = query_circs
-            .get_mut(parent.0)
+    for (parent, mut draw_mode) in query_lights.iter_mut() {
+        let parent_circuit = query_circs
+            .get(parent.0)
             .expect("couldn't find child to light");
-        let new_power = parent_circuit.0 .0.lightbulb_power();
+        let new_power = parent_circuit.0.lightbulb_power();
 
         *draw_mode = DrawMode::Outlined {
             fill_mode: FillMode::color(Color::hsla(0.0, 0.0, new_power as f32 * 6.0, 0.7)),
             outline_mode: StrokeMode::new(Color::hsla(0.0, 0.0, 1.0, 1.0), 1.0),
         };
-
-        let epsilon = 0.2;
-        if parent_circuit.0 .0.circuit.current_rate().signum() != parent_circuit.2 .0
-            && circuit_timer.time > MIN_CIRCUIT_TIME + epsilon
-            && circuit_timer.time < MAX_CIRCUIT_TIME - epsilon
-            && !parent_circuit.1 .1
-            && parent_circuit.0 .0.circuit.current().abs() > epsilon
-        {
-            //play circle pop sound
-            let sound_effect = asset_server.load("taishi-piano-note.ogg");
-            audio.play(sound_effect);
-
-            let starting_radius = 10.0;
-            let circle_builder = GeometryBuilder::new().add(&shapes::Circle {
-                radius: starting_radius,
-                ..shapes::Circle::default()
-            });
-            commands.entity(entity).with_children(|parent| {
-                parent.spawn_bundle(CircleBundle {
-                    radius: CircleRadius(starting_radius),
-                    shape_bundle: circle_builder.build(
-                        DrawMode::Outlined {
-                            fill_mode: FillMode::color(Color::hsla(0.0, 0.0, 0.0, 0.0)),
-                            outline_mode: StrokeMode::new(
-                                Color::hsla(0.0, 0.0, 1.0, calculate_circle_alpha(starting_radius)),
-                                1.0,
-                            ),
-                        },
-                        Transform::identity(),
-                    ),
-                });
-            });
-            parent_circuit.1 .1 = true;
-        }
+    }
+    for light in peak_reader.iter().filter_map(|peak| peak.light) {
+        spawn_pulse_circle(&mut commands, light, 10.0);
     }
 }
 
@@ -202,6 +224,34 @@ pub struct CircleBundle {
 /// A component to store the radius of an expanding circle
 pub struct CircleRadius(pub f32);
 
+/// Spawns an expanding circle around a light
+///
+/// # Arguments
+/// * 'commands' - the commands used to spawn the circle
+/// * 'light' - the light entity the circle expands from
+/// * 'starting_radius' - the radius the circle starts expanding from
+pub fn spawn_pulse_circle(commands: &mut Commands, light: Entity, starting_radius: f32) {
+    let circle_builder = GeometryBuilder::new().add(&shapes::Circle {
+        radius: starting_radius,
+        ..shapes::Circle::default()
+    });
+    commands.entity(light).with_children(|parent| {
+        parent.spawn_bundle(CircleBundle {
+            radius: CircleRadius(starting_radius),
+            shape_bundle: circle_builder.build(
+                DrawMode::Outlined {
+                    fill_mode: FillMode::color(Color::hsla(0.0, 0.0, 0.0, 0.0)),
+                    outline_mode: StrokeMode::new(
+                        Color::hsla(0.0, 0.0, 1.0, calculate_circle_alpha(starting_radius)),
+                        1.0,
+                    ),
+                },
+                Transform::identity(),
+            ),
+        });
+    });
+}
+
 /// Calculates what the alpha value of the circle should be to make it fade as the radius gets
 /// larger
 ///
@@ -276,52 +326,3 @@ fn update_current_arrow(
         }
     }
 }
-
-/* Circuit Timer Resource */
-#[derive(PartialEq)]
-/// The two modes the simulation can be in, paused or playing
-pub enum CircuitTimerMode {
-    Play,
-    Pause,
-}
-
-/// A timer keeping track of the current time in the simulation
-pub struct CircuitTimer {
-    pub time: f64,
-    pub mode: CircuitTimerMode,
-}
-
-///the amount of simulation time passing every frame
-///this intentionally doesn't make the simulation run in real time
-const DELTA_T: f64 = 0.1;
-
-/// Updates the timer and other time senstitive parts of the simulation
-pub fn update_time(
-    mut time: ResMut<CircuitTimer>,
-    mut query_circs: Query<(
-        &mut DLRCCircuit,
-        &mut CurrentTimePlot,
-        &mut SpawnedThisSignum,
-        &mut LastCurrentRateSignum,
-    )>,
-) {
-    if time.mode == CircuitTimerMode::Play {
-        time.time += DELTA_T;
-        for (mut circ, mut plot, mut sts, mut lcrs) in query_circs.iter_mut() {
-            lcrs.0 = circ.0.circuit.current_rate().signum();
-            circ.0.circuit.tick(DELTA_T);
-            let new_current = circ.0.circuit.current();
-            plot.0.push((time.time, new_current));
-            if new_current.signum() != sts.0.signum() {
-                sts.1 = false;
-            }
-            sts.0 = new_current;
-        }
-    }
-
-    if time.time > MAX_CIRCUIT_TIME {
-        time.time = MAX_CIRCUIT_TIME;
-        time.mode = CircuitTimerMode::Pause;
-    }
-    time.time = time.time.max(MIN_CIRCUIT_TIME);
-}
@@ -3,7 +3,9 @@
 
 mod circuits;
 
+pub use circuits::CircuitState;
 pub use circuits::DisconnectLightCircuitCalculator;
 pub use circuits::RLCCalculator;
 
 pub mod graphics;
+pub mod storage;
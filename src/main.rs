@@ -1,19 +1,50 @@
 use bevy::prelude::*;
 use syncing_circuits::graphics::{
-    update_time, DLCPlugin, EffectsPlugin, MusicPlugin, UIWindowsPlugin,
+    render_wav, AudioSettings, DLCPlugin, EditorPlugin, EffectsPlugin, FlowPlugin, InspectorPlugin,
+    LevelPlugin, MenuPlugin, MusicPlugin, PresetPlugin, ReadoutPlugin, RenderOptions, RhythmPlugin,
+    SchematicPlugin, ScopePlugin, SimulationPlugin, SonificationPlugin, SyncGamePlugin,
+    UIWindowsPlugin,
 };
+use syncing_circuits::storage::SaveFile;
 
 fn main() {
+    match RenderOptions::from_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => {
+            if let Err(e) = render_wav(&options, &AudioSettings::load()) {
+                eprintln!("couldn't render {}: {}", options.path.display(), e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(None) => {}
+        Err(usage) => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    }
+
     let mut app = App::new();
     app.insert_resource(Msaa { samples: 4 })
         .insert_resource(ClearColor(Color::BLACK))
         .add_plugins(DefaultPlugins)
+        .add_plugin(MenuPlugin)
+        .add_plugin(SimulationPlugin)
         .add_plugin(UIWindowsPlugin)
         .add_plugin(MusicPlugin)
+        .add_plugin(SonificationPlugin)
         .add_startup_system(start_camera)
         .add_plugin(EffectsPlugin)
         .add_plugin(DLCPlugin)
-        .add_system(update_time)
+        .add_plugin(SchematicPlugin)
+        .add_plugin(FlowPlugin)
+        .add_plugin(EditorPlugin)
+        .add_plugin(InspectorPlugin)
+        .add_plugin(ScopePlugin)
+        .add_plugin(ReadoutPlugin)
+        .add_plugin(PresetPlugin)
+        .add_plugin(SyncGamePlugin)
+        .add_plugin(LevelPlugin)
+        .add_plugin(RhythmPlugin)
         .run();
 }
 
@@ -0,0 +1,74 @@
+//! saving and loading small files, like level progress, between runs
+
+use bevy::log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{error::Error, fs, path::PathBuf};
+
+/// A value kept in its own save file between runs, falling back to its defaults
+pub trait SaveFile: Serialize + DeserializeOwned + Default {
+    /// the name of the save file
+    const FILE: &'static str;
+    /// what the value is called when it can't be saved
+    const NAME: &'static str;
+
+    /// Loads the saved value, using the defaults if there is none
+    fn load() -> Self {
+        load(Self::FILE).unwrap_or_default()
+    }
+
+    /// Saves the value, logging rather than failing if it can't be written
+    fn save(&self) {
+        if let Err(e) = save(Self::FILE, self) {
+            warn!("couldn't save {}: {}", Self::NAME, e);
+        }
+    }
+}
+
+/// Finds where a save file should live, creating the folder if needed
+///
+/// This is the `syncing_circuits` folder in the user's config directory, falling back to the
+/// working directory when no home can be found.
+///
+/// # Arguments
+/// * 'file_name' - the name of the save file
+///
+/// # Returns
+/// The path of the save file
+pub fn config_path(file_name: &str) -> PathBuf {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from));
+    match base {
+        Some(base) => {
+            let folder = base.join("syncing_circuits");
+            //if this fails, writing the file will report it
+            let _ = fs::create_dir_all(&folder);
+            folder.join(file_name)
+        }
+        None => PathBuf::from(file_name),
+    }
+}
+
+/// Reads a save file
+///
+/// # Arguments
+/// * 'file_name' - the name of the save file
+///
+/// # Returns
+/// The saved value, or none if the file is missing or unreadable
+pub fn load<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let contents = fs::read_to_string(config_path(file_name)).ok()?;
+    ron::from_str(&contents).ok()
+}
+
+/// Writes a save file, replacing any older one
+///
+/// # Arguments
+/// * 'file_name' - the name of the save file
+/// * 'value' - the value to save
+pub fn save<T: Serialize>(file_name: &str, value: &T) -> Result<(), Box<dyn Error>> {
+    let contents = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?;
+    fs::write(config_path(file_name), contents)?;
+    Ok(())
+}
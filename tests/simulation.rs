@@ -0,0 +1,232 @@
+//! runs the simulation core in a headless app, without a window, renderer or sound card
+
+use bevy::ecs::event::Events;
+use bevy::prelude::*;
+use std::f64::consts::PI;
+
+use syncing_circuits::graphics::{
+    AppState, CircuitTimer, CircuitTimerMode, Coupling, CurrentPeak, CurrentTimePlot, DLRCCircuit,
+    SimulatedCircuitBundle, SimulationPlugin, DELTA_T, MIN_CIRCUIT_TIME,
+};
+use syncing_circuits::{DisconnectLightCircuitCalculator, RLCCalculator};
+
+/// the sandbox circuit's starting charge, resistance, inductance and capacitance
+const SANDBOX: (f64, f64, f64, f64) = (10.0, 0.2, 4.0, 6.0);
+
+/// Creates an app with only the simulation core, in the sandbox
+fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_state(AppState::Sandbox)
+        .add_plugin(SimulationPlugin);
+    app
+}
+
+/// Spawns a circuit with the sandbox's values
+fn spawn_sandbox_circuit(app: &mut App) -> Entity {
+    let (startcharge, resistance, inductance, capacitance) = SANDBOX;
+    app.world
+        .spawn()
+        .insert_bundle(SimulatedCircuitBundle::new(DLRCCircuit(
+            DisconnectLightCircuitCalculator::with_constants(
+                startcharge,
+                resistance,
+                inductance,
+                capacitance,
+            ),
+        )))
+        .id()
+}
+
+/// Starts the timer playing
+fn play(app: &mut App) {
+    app.world.resource_mut::<CircuitTimer>().mode = CircuitTimerMode::Play;
+}
+
+#[test]
+fn paused_timer_holds_still() {
+    let mut app = headless_app();
+    let circuit = spawn_sandbox_circuit(&mut app);
+    for _ in 0..10 {
+        app.update();
+    }
+    assert_eq!(app.world.resource::<CircuitTimer>().time, MIN_CIRCUIT_TIME);
+    let plot = app.world.get::<CurrentTimePlot>(circuit).unwrap();
+    assert_eq!(plot.0, vec![(MIN_CIRCUIT_TIME, 0.0)]);
+}
+
+#[test]
+fn plot_starts_at_the_starting_current() {
+    let mut app = headless_app();
+    let (_, resistance, inductance, capacitance) = SANDBOX;
+    let circuit = app
+        .world
+        .spawn()
+        .insert_bundle(SimulatedCircuitBundle::new(DLRCCircuit(
+            DisconnectLightCircuitCalculator::with_initial_current(
+                0.0,
+                3.0,
+                resistance,
+                inductance,
+                capacitance,
+            ),
+        )))
+        .id();
+    play(&mut app);
+    app.update();
+    let plot = app.world.get::<CurrentTimePlot>(circuit).unwrap();
+    assert_eq!(plot.0[0], (MIN_CIRCUIT_TIME, 3.0));
+    //no jump from zero on the first step
+    assert!((plot.0[1].1 - 3.0).abs() < 0.5);
+}
+
+#[test]
+fn steps_match_the_calculator() {
+    let mut app = headless_app();
+    let circuit = spawn_sandbox_circuit(&mut app);
+    play(&mut app);
+    for _ in 0..50 {
+        app.update();
+    }
+
+    let (startcharge, resistance, inductance, capacitance) = SANDBOX;
+    let mut reference =
+        RLCCalculator::with_constants(startcharge, resistance, inductance, capacitance);
+    for _ in 0..50 {
+        reference.tick(DELTA_T);
+    }
+    let rlc = &app.world.get::<DLRCCircuit>(circuit).unwrap().0.circuit;
+    assert_eq!(rlc.state(), reference.state());
+    let plot = app.world.get::<CurrentTimePlot>(circuit).unwrap();
+    assert_eq!(plot.0.len(), 51);
+    assert_eq!(plot.0.last().unwrap().1, reference.current());
+    let time = app.world.resource::<CircuitTimer>().time;
+    assert!((time - 50.0 * DELTA_T).abs() < 1e-9);
+}
+
+#[test]
+fn pause_menu_freezes_the_simulation() {
+    let mut app = headless_app();
+    spawn_sandbox_circuit(&mut app);
+    play(&mut app);
+    app.update();
+    app.world
+        .resource_mut::<State<AppState>>()
+        .push(AppState::Paused)
+        .unwrap();
+    app.update();
+    let paused_at = app.world.resource::<CircuitTimer>().time;
+    for _ in 0..10 {
+        app.update();
+    }
+    assert_eq!(app.world.resource::<CircuitTimer>().time, paused_at);
+}
+
+#[test]
+fn real_time_scale_follows_the_clock() {
+    let mut app = headless_app();
+    spawn_sandbox_circuit(&mut app);
+    app.world.resource_mut::<CircuitTimer>().real_time_scale = Some(6.0);
+    play(&mut app);
+    app.update();
+    let real_start = app.world.resource::<Time>().seconds_since_startup();
+    let start = app.world.resource::<CircuitTimer>().time;
+    for _ in 0..10 {
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        app.update();
+    }
+    let real_elapsed = app.world.resource::<Time>().seconds_since_startup() - real_start;
+    let elapsed = app.world.resource::<CircuitTimer>().time - start;
+    assert!(real_elapsed > 0.0);
+    assert!((elapsed - 6.0 * real_elapsed).abs() < 1e-9);
+}
+
+#[test]
+fn timer_stops_at_the_end() {
+    let mut app = headless_app();
+    spawn_sandbox_circuit(&mut app);
+    app.world.resource_mut::<CircuitTimer>().end = 1.0;
+    play(&mut app);
+    for _ in 0..20 {
+        app.update();
+    }
+    let timer = app.world.resource::<CircuitTimer>();
+    assert_eq!(timer.time, 1.0);
+    assert!(timer.mode == CircuitTimerMode::Pause);
+}
+
+#[test]
+fn peaks_land_on_the_extrema() {
+    let mut app = headless_app();
+    let circuit = spawn_sandbox_circuit(&mut app);
+    play(&mut app);
+
+    let mut reader = app.world.resource::<Events<CurrentPeak>>().get_reader();
+    let mut peaks = Vec::new();
+    while app.world.resource::<CircuitTimer>().mode == CircuitTimerMode::Play {
+        app.update();
+        let events = app.world.resource::<Events<CurrentPeak>>();
+        peaks.extend(
+            reader
+                .iter(events)
+                .map(|peak| (peak.circuit, peak.light, peak.time, peak.current)),
+        );
+    }
+
+    //the current peaks where tan(wd t) = wd / gamma, then every half of a damped period
+    let rlc = &app.world.get::<DLRCCircuit>(circuit).unwrap().0.circuit;
+    let wd = rlc.damped_angular_frequency().unwrap();
+    let gamma = rlc.resistance * 0.5 / rlc.inductance;
+    assert!(peaks.len() >= 4, "only found {} peaks", peaks.len());
+    for (k, &(entity, light, time, current)) in peaks.iter().enumerate() {
+        let expected = (wd.atan2(gamma) + k as f64 * PI) / wd;
+        assert_eq!(entity, circuit);
+        assert_eq!(light, None);
+        assert!(
+            (time - expected).abs() <= 2.0 * DELTA_T,
+            "peak {} was at t = {} rather than {}",
+            k,
+            time,
+            expected
+        );
+        //the first peak flows forwards as the capacitor discharges, then they alternate
+        assert_eq!(current > 0.0, k % 2 == 0);
+    }
+}
+
+#[test]
+fn coupled_circuits_share_energy() {
+    let mut app = headless_app();
+    let first = spawn_sandbox_circuit(&mut app);
+    let (_, resistance, inductance, capacitance) = SANDBOX;
+    let second = app
+        .world
+        .spawn()
+        .insert_bundle(SimulatedCircuitBundle::new(DLRCCircuit(
+            DisconnectLightCircuitCalculator::with_constants(
+                0.0,
+                resistance,
+                inductance,
+                capacitance,
+            ),
+        )))
+        .id();
+    app.world.spawn().insert(Coupling {
+        circuits: (first, second),
+        coefficient: 0.2,
+    });
+    play(&mut app);
+    for _ in 0..200 {
+        app.update();
+    }
+
+    let energy = |entity| {
+        app.world
+            .get::<DLRCCircuit>(entity)
+            .unwrap()
+            .0
+            .circuit
+            .energy()
+    };
+    assert!(energy(second) > 0.01 * energy(first));
+}